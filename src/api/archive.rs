@@ -0,0 +1,93 @@
+mod tests;
+
+use std::io::{Cursor, Read};
+use std::path::Path;
+use flate2::read::GzDecoder;
+use thiserror::Error;
+use zip::ZipArchive;
+
+const ZIP_TAG: [u8; 4] = [0x50, 0x4B, 0x03, 0x04];
+const GZIP_TAG: [u8; 2] = [0x1F, 0x8B];
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("{archive} has no file with one of the extensions {extensions:?}")]
+    NoMatchingFile {
+        archive: String,
+        extensions: Vec<String>,
+    },
+    #[error("{archive} has several files that could be loaded, pick one of {names:?}")]
+    SeveralFiles {
+        archive: String,
+        names: Vec<String>,
+    },
+    #[error("{archive} has no file named {name}")]
+    FileNotFound {
+        archive: String,
+        name: String,
+    },
+}
+
+// reads a file, or a file from inside a zip or gzip archive. returns the data with the name of the
+// file it came from, whose extension tells its format. zip archives are searched for the given name,
+// or for the one file with one of the extensions
+pub fn read(path: &str, name: Option<&str>, extensions: &[&str]) -> anyhow::Result<(Vec<u8>, String)> {
+    let raw = std::fs::read(path)?;
+    if raw.starts_with(&ZIP_TAG) {
+        read_zip(path, raw, name, extensions)
+    } else if raw.starts_with(&GZIP_TAG) {
+        read_gzip(path, &raw)
+    } else {
+        Ok((raw, path.to_string()))
+    }
+}
+
+fn read_zip(path: &str, raw: Vec<u8>, name: Option<&str>, extensions: &[&str]) -> anyhow::Result<(Vec<u8>, String)> {
+    let mut archive = ZipArchive::new(Cursor::new(raw))?;
+    let names: Vec<String> = archive.file_names().map(String::from).collect();
+    let name = match name {
+        // files in folders can be named without the folder
+        Some(name) => names.iter()
+            .find(|entry| *entry == name || file_name(entry) == name)
+            .ok_or_else(|| ArchiveError::FileNotFound { archive: path.to_string(), name: name.to_string() })?,
+        None => {
+            let candidates: Vec<&String> = names.iter().filter(|entry| has_extension(entry, extensions)).collect();
+            match candidates[..] {
+                [name] => name,
+                [] => return Err(ArchiveError::NoMatchingFile {
+                    archive: path.to_string(),
+                    extensions: extensions.iter().map(|extension| extension.to_string()).collect(),
+                }.into()),
+                _ => return Err(ArchiveError::SeveralFiles {
+                    archive: path.to_string(),
+                    names: candidates.into_iter().cloned().collect(),
+                }.into()),
+            }
+        }
+    };
+
+    let mut data = vec![];
+    archive.by_name(name)?.read_to_end(&mut data)?;
+    Ok((data, name.clone()))
+}
+
+// a gzip holds a single file, named in its header or after the archive without the .gz
+fn read_gzip(path: &str, raw: &[u8]) -> anyhow::Result<(Vec<u8>, String)> {
+    let mut decoder = GzDecoder::new(raw);
+    let mut data = vec![];
+    decoder.read_to_end(&mut data)?;
+    let name = decoder.header()
+        .and_then(|header| header.filename())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .unwrap_or_else(|| Path::new(path).with_extension("").to_string_lossy().into_owned());
+    Ok((data, name))
+}
+
+fn file_name(entry: &str) -> &str {
+    entry.rsplit('/').next().unwrap_or(entry)
+}
+
+fn has_extension(name: &str, extensions: &[&str]) -> bool {
+    Path::new(name).extension()
+        .is_some_and(|extension| extensions.iter().any(|wanted| extension.eq_ignore_ascii_case(wanted)))
+}
@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod archive_tests {
+    use std::io::Write;
+    use flate2::write::GzEncoder;
+    use flate2::{Compression, GzBuilder};
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+    use crate::api::archive::{read, ArchiveError};
+
+    const ROM_EXTENSIONS: [&str; 2] = ["nes", "fds"];
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("nesrs_test_{}", name)).to_string_lossy().into_owned()
+    }
+
+    fn create_zip(name: &str, files: &[(&str, &[u8])]) -> String {
+        let path = temp_path(name);
+        let mut zip = ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (file_name, data) in files {
+            zip.start_file(*file_name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_plain_file() {
+        let path = temp_path("plain.nes");
+        std::fs::write(&path, b"NES\x1a").unwrap();
+        let (data, name) = read(&path, None, &ROM_EXTENSIONS).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data, b"NES\x1a");
+        assert_eq!(name, path);
+    }
+
+    #[test]
+    fn test_zip_picks_the_rom() {
+        let rom = vec![0x42; 0x4000];
+        let path = create_zip("single.zip", &[("readme.txt", b"hello"), ("Games/Game (USA).NES", &rom)]);
+        let (data, name) = read(&path, None, &ROM_EXTENSIONS).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data, rom);
+        assert_eq!(name, "Games/Game (USA).NES");
+    }
+
+    #[test]
+    fn test_zip_by_name() {
+        let path = create_zip("several.zip", &[("Games/a.nes", b"a"), ("b.fds", b"b"), ("c.txt", b"c")]);
+        let err = read(&path, None, &ROM_EXTENSIONS).unwrap_err().downcast::<ArchiveError>().unwrap();
+        assert!(matches!(err, ArchiveError::SeveralFiles { names, .. } if names == ["Games/a.nes", "b.fds"]));
+
+        assert_eq!(read(&path, Some("a.nes"), &ROM_EXTENSIONS).unwrap().0, b"a");
+        assert_eq!(read(&path, Some("b.fds"), &ROM_EXTENSIONS).unwrap().0, b"b");
+        // a name picks any file, whatever the extension
+        assert_eq!(read(&path, Some("c.txt"), &ROM_EXTENSIONS).unwrap().0, b"c");
+        let err = read(&path, Some("d.nes"), &ROM_EXTENSIONS).unwrap_err().downcast::<ArchiveError>().unwrap();
+        assert!(matches!(err, ArchiveError::FileNotFound { name, .. } if name == "d.nes"));
+
+        let err = read(&path, None, &["nsf"]).unwrap_err().downcast::<ArchiveError>().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, ArchiveError::NoMatchingFile { .. }));
+    }
+
+    #[test]
+    fn test_gzip() {
+        let rom = vec![0x33; 0x2000];
+        let path = temp_path("named.gz");
+        let mut encoder = GzBuilder::new().filename("Game.fds").write(std::fs::File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(&rom).unwrap();
+        encoder.finish().unwrap();
+        let (data, name) = read(&path, None, &ROM_EXTENSIONS).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data, rom);
+        assert_eq!(name, "Game.fds");
+
+        // without a name in the header the archive name without .gz is used
+        let path = temp_path("game.nes.gz");
+        let mut encoder = GzEncoder::new(std::fs::File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(&rom).unwrap();
+        encoder.finish().unwrap();
+        let (data, name) = read(&path, None, &ROM_EXTENSIONS).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(data, rom);
+        assert_eq!(name, temp_path("game.nes"));
+    }
+}
@@ -1,39 +1,95 @@
-use std::cell::RefCell;
+mod tests;
+
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use postcard::to_stdvec;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::Sdl;
+use thiserror::Error;
+use crate::api::archive;
+use crate::api::ram_search::{RamFilter, RamSearch};
 use crate::hw::bus::Bus;
-use crate::hw::cartridge::Cartridge;
-use crate::hw::cpu::CPU;
+use crate::hw::cartridge::{fds, unif, Cartridge, CartridgeError};
+use crate::hw::cartridge::nsf::Nsf;
+use crate::hw::cheats::Cheat;
+use crate::hw::cartridge::database::GameInfo;
+use crate::hw::cartridge::patch::{apply_ips, apply_patch, create_ips};
+use crate::hw::cpu::{CpuFault, CPU};
 use crate::hw::joypad::{Joypad, JoypadButton};
 use crate::hw::memory::Memory;
 use crate::hw::ppu::PPU;
+use crate::hw::ram_init::RamInit;
+use crate::hw::region::Region;
 use crate::rendering::frame::Frame;
 use crate::rendering::renderer;
 
-#[derive(PartialEq)]
+const ROM_EXTENSIONS: [&str; 5] = ["nes", "unf", "unif", "fds", "cpu"];
+
+// save states start with this, followed by the postcard serialized CPU
+const STATE_TAG: [u8; 6] = [0x4E, 0x45, 0x53, 0x52, 0x53, 0x1A];
+
+#[derive(PartialEq, Debug)]
 pub enum LoadFormat {
     NES,
+    FDS,
+    NSF,
     CPU,
     Unknown,
 }
 
+impl LoadFormat {
+    // the format is told by the first bytes of the file, whatever it is named
+    pub fn detect(raw: &[u8]) -> LoadFormat {
+        if raw.starts_with(&Cartridge::INES_TAG) || unif::is_unif(raw) {
+            LoadFormat::NES
+        } else if fds::is_fds_image(raw) || fds::is_headerless_image(raw) {
+            LoadFormat::FDS
+        } else if Nsf::is_nsf(raw) {
+            LoadFormat::NSF
+        } else if raw.starts_with(&STATE_TAG) {
+            LoadFormat::CPU
+        } else {
+            LoadFormat::Unknown
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error("{0} isn't a ROM, disk image or save state")]
+    UnknownFormat(String),
+    #[error("{0} is an NSF tune, play it with NsfPlayer")]
+    NsfTune(String),
+}
+
 pub enum EmulatorTrigger {
     MemEquals { addr: u16, value: u8 },
+    // the CPU jammed or hit an unknown opcode, see Emulator::get_cpu_fault
+    CpuFault,
 }
 
 pub struct Emulator {
-    cpu: Arc<RefCell<CPU<'static>>>,
+    cpu: Arc<RefCell<CPU<Bus<'static>>>>,
     triggers: Vec<EmulatorTrigger>,
     load_format: LoadFormat,
     cartridge_path: String,
+    region_override: Option<Region>,
+    dot_rendering: Option<bool>,
+    throttle: Rc<Cell<bool>>,
+    // the disk image as loaded, writes to the disk are saved as a patch against it
+    disk_image: Vec<u8>,
+    game: Option<GameInfo>,
+    ram_search: Option<RamSearch>,
 }
 
 impl Emulator {
@@ -53,19 +109,47 @@ impl Emulator {
         }
     }
 
-    pub fn reset_cpu(&mut self) {
+    // cold start, snapshots are reloaded from disk instead
+    pub fn power_on(&mut self) -> anyhow::Result<()> {
         let cpu_clone = Arc::clone(&self.cpu);
         let mut cpu_borrow = cpu_clone.borrow_mut();
-        if self.load_format == LoadFormat::NES {
-            cpu_borrow.reset();
+        if self.load_format != LoadFormat::CPU {
+            cpu_borrow.power_on();
         } else {
+            let (bytes, _) = archive::read(&self.cartridge_path, None, &["cpu"])?;
+            let mut cpu = Emulator::deserialize_cpu(bytes)?;
             let callback = cpu_borrow.bus.gameloop_callback.take();
-            let bytes: Vec<u8> = std::fs::read(self.cartridge_path.as_str()).unwrap();
-            let mut cpu = Emulator::deserialize_cpu(bytes);
             cpu.bus.gameloop_callback = callback;
+            cpu.bus.cheats = std::mem::take(&mut cpu_borrow.bus.cheats);
+            if let Some(region) = self.region_override {
+                cpu.bus.set_region(region);
+            }
+            if let Some(enabled) = self.dot_rendering {
+                cpu.bus.ppu.set_dot_rendering(enabled);
+            }
             self.cpu = Arc::new(RefCell::new(cpu));
             self.set_key_event(JoypadButton::START.bits(), false);
         }
+        Ok(())
+    }
+
+    #[deprecated(note = "use power_on for a cold start, or reset for the reset button")]
+    pub fn reset_cpu(&mut self) -> anyhow::Result<()> {
+        self.power_on()
+    }
+
+    // the console reset button, RAM and PPU memory keep their contents
+    pub fn reset(&mut self) {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.reset();
+    }
+
+    // contents of the internal RAM after the next power_on
+    pub fn set_ram_init(&mut self, ram_init: RamInit) {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.set_ram_init(ram_init);
     }
 
     // returns true if breakpoint is hit
@@ -73,6 +157,11 @@ impl Emulator {
         let cpu_clone = Arc::clone(&self.cpu);
         let mut cpu_borrow = cpu_clone.borrow_mut();
         cpu_borrow.step(|_| {});
+        if let Some(sides) = cpu_borrow.bus.take_written_disk() {
+            if let Err(err) = self.save_disk(&sides) {
+                log::warn!("Failed to save the disk: {}", err);
+            }
+        }
         self.check_triggers(&mut *cpu_borrow)
     }
 
@@ -86,13 +175,189 @@ impl Emulator {
     pub fn get_value_at_address(&self, address: u16) -> u8 {
         let cpu_clone = Arc::clone(&self.cpu);
         let mut cpu_borrow = cpu_clone.borrow_mut();
-        let value = cpu_borrow.mem_read(address);
+        // peek, so inspecting memory doesn't clock the system or touch registers
+        let value = cpu_borrow.mem_peek(address);
         value
     }
+
+    // mono samples in the 0.0..1.0 range produced since the last call
+    pub fn get_audio_samples(&self) -> Vec<f32> {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.apu.take_samples()
+    }
+
+    // set once the CPU stops on a KIL opcode or an unknown instruction, cleared by power_on and reset
+    pub fn get_cpu_fault(&self) -> Option<CpuFault> {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.fault
+    }
+
+    pub fn get_region(&self) -> Region {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.region()
+    }
+
+    // overrides the region detected from the cartridge header
+    pub fn set_region(&mut self, region: Region) {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.set_region(region);
+        self.region_override = Some(region);
+    }
+
+    // draws frames dot by dot through the PPU fetch pipeline, slower but reproduces mid-frame
+    // scroll and CHR bank changes
+    pub fn set_dot_rendering(&mut self, enabled: bool) {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.ppu.set_dot_rendering(enabled);
+        self.dot_rendering = Some(enabled);
+    }
+
+    // Famicom Disk System sides of the loaded image, 0 for cartridges
+    pub fn get_disk_sides(&self) -> usize {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.disk_side_count()
+    }
+
+    pub fn get_inserted_disk(&self) -> Option<usize> {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.disk_side()
+    }
+
+    // ejects the disk and inserts the side about a second later, the way a player flips the disk
+    pub fn insert_disk(&mut self, side: usize) {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.insert_disk(side);
+    }
+
+    pub fn eject_disk(&mut self) {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.eject_disk();
+    }
+
+    // title, board and region of the game when the game database knows the ROM
+    pub fn get_game_info(&self) -> Option<&GameInfo> {
+        self.game.as_ref()
+    }
+
+    // a 6 or 8 letter Game Genie code, or a RAM freeze written AAAA:VV, returns the cheat's index
+    pub fn add_cheat(&mut self, code: &str, description: &str) -> anyhow::Result<usize> {
+        let cheat = Cheat::new(code, description)?;
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        Ok(cpu_borrow.bus.cheats.add(cheat))
+    }
+
+    pub fn get_cheats(&self) -> Vec<Cheat> {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.cheats.list().to_vec()
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) -> anyhow::Result<()> {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.cheats.set_enabled(index, enabled)?;
+        Ok(())
+    }
+
+    pub fn remove_cheat(&mut self, index: usize) -> anyhow::Result<Cheat> {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        Ok(cpu_borrow.bus.cheats.remove(index)?)
+    }
+
+    // adds the cheats of a cheat file, game.cht beside game.nes is loaded with the game
+    pub fn load_cheats(&mut self, path: &str) -> anyhow::Result<()> {
+        let text = std::fs::read_to_string(path)?;
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        cpu_borrow.bus.cheats.load(&text)?;
+        Ok(())
+    }
+
+    // a typo in game.cht shouldn't keep the game from starting, bad lines are skipped
+    fn load_cheats_beside_game(&mut self, path: &Path) {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                log::warn!("Failed to read {}: {}", path.display(), err);
+                return;
+            }
+        };
+        for err in self.cpu.borrow_mut().bus.cheats.load_valid(&text) {
+            log::warn!("Skipping a cheat in {}: {}", path.display(), err);
+        }
+    }
+
+    // starts a RAM search with every address of internal and PRG RAM as a candidate, and their
+    // current values as the snapshot
+    pub fn start_ram_search(&mut self) {
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        self.ram_search = Some(RamSearch::new(&mut cpu_borrow.bus));
+    }
+
+    pub fn take_ram_snapshot(&mut self) -> anyhow::Result<()> {
+        let search = self.ram_search.as_mut().ok_or_else(|| anyhow::anyhow!("No RAM search was started"))?;
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        search.snapshot(&mut cpu_borrow.bus);
+        Ok(())
+    }
+
+    // compares the candidates with the last snapshot, returns how many are left
+    pub fn filter_ram(&mut self, filter: RamFilter) -> anyhow::Result<usize> {
+        let search = self.ram_search.as_mut().ok_or_else(|| anyhow::anyhow!("No RAM search was started"))?;
+        let cpu_clone = Arc::clone(&self.cpu);
+        let mut cpu_borrow = cpu_clone.borrow_mut();
+        Ok(search.filter(&mut cpu_borrow.bus, filter))
+    }
+
+    // the addresses still in the search with their values in the last snapshot or filter, ready for
+    // EmulatorTrigger::MemEquals
+    pub fn get_ram_candidates(&self) -> Vec<(u16, u8)> {
+        self.ram_search.as_ref().map_or(vec![], |search| search.candidates().to_vec())
+    }
+
+    // limits emulation speed to the frame rate of the current region
+    pub fn set_throttle(&mut self, enabled: bool) {
+        self.throttle.set(enabled);
+    }
 }
 
 impl Emulator {
+    // a game.ips, game.ups or game.bps beside game.nes is applied to the ROM as it loads
     pub fn new(cartridge_path: &str, keyboard_input: bool, triggers: Vec<EmulatorTrigger>) -> anyhow::Result<Self> {
+        Emulator::open(cartridge_path, None, None, None, keyboard_input, triggers)
+    }
+
+    // loads the named file from a zip archive that has several ROMs, Emulator::new picks the one
+    // ROM of an archive by itself
+    pub fn new_from_archive(archive_path: &str, file_name: &str, keyboard_input: bool, triggers: Vec<EmulatorTrigger>) -> anyhow::Result<Self> {
+        Emulator::open(archive_path, Some(file_name), None, None, keyboard_input, triggers)
+    }
+
+    // applies an IPS, UPS or BPS patch from anywhere, the ROM file stays as it is
+    pub fn new_patched(cartridge_path: &str, patch_path: &str, keyboard_input: bool, triggers: Vec<EmulatorTrigger>) -> anyhow::Result<Self> {
+        Emulator::open(cartridge_path, None, None, Some(patch_path), keyboard_input, triggers)
+    }
+
+    // Famicom Disk System images also need the 8 KiB disksys.rom BIOS
+    pub fn new_fds(disk_path: &str, bios_path: &str, keyboard_input: bool, triggers: Vec<EmulatorTrigger>) -> anyhow::Result<Self> {
+        Emulator::open(disk_path, None, Some(bios_path), None, keyboard_input, triggers)
+    }
+
+    fn open(cartridge_path: &str, file_name: Option<&str>, bios_path: Option<&str>, patch_path: Option<&str>,
+            keyboard_input: bool, triggers: Vec<EmulatorTrigger>) -> anyhow::Result<Self> {
         // init sdl2
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
@@ -116,124 +381,134 @@ impl Emulator {
         key_map.insert(Keycode::A, JoypadButton::BUTTON_A);
         key_map.insert(Keycode::S, JoypadButton::BUTTON_B);
 
-
-        let path = Path::new(cartridge_path);
-        let mut load_format = LoadFormat::Unknown;
-
-        if let Some(extension) = path.extension() {
-            if extension == "cpu" {
-                load_format = LoadFormat::CPU;
-            } else if extension == "nes" {
-                load_format = LoadFormat::NES;
-            } else {
-                load_format = LoadFormat::Unknown;
-            }
+        // interactive sessions run at console speed, headless runs as fast as possible
+        let throttle = Rc::new(Cell::new(keyboard_input));
+        let frontend = Emulator::frontend_callback(canvas, sdl_context, key_map, keyboard_input, throttle.clone());
+
+        // .zip and .gz archives are opened, the format is told by the file inside
+        let (bytes, file_name) = archive::read(cartridge_path, file_name, &ROM_EXTENSIONS)?;
+        let mut load_format = LoadFormat::detect(&bytes);
+        // save states from before they had a header are only known by their extension
+        let extension = Path::new(&file_name).extension();
+        if load_format == LoadFormat::Unknown && extension.is_some_and(|extension| extension.eq_ignore_ascii_case("cpu")) {
+            load_format = LoadFormat::CPU;
         }
-
-        if load_format == LoadFormat::Unknown {
-            panic!("Unsupported cartridge format!");
+        match load_format {
+            LoadFormat::Unknown => return Err(LoadError::UnknownFormat(file_name).into()),
+            LoadFormat::NSF => return Err(LoadError::NsfTune(file_name).into()),
+            _ => {}
         }
 
-
-        let bytes: Vec<u8> = std::fs::read(cartridge_path)?;
-
-        if load_format == LoadFormat::NES {
-            let crt = Cartridge::new(bytes)?;
-
-            // the game cycle
-            let bus = Bus::new(Some(crt), move |ppu: &mut PPU, joypad: &mut Joypad| {
-                let mut frame = Frame::new();
-                let canvas_clone = canvas.clone();
-                let mut canvas_mut = canvas_clone.borrow_mut();
-                let creator = canvas_mut.texture_creator();
-                let mut texture = creator
-                    .create_texture_target(PixelFormatEnum::RGB24, 256, 240)
-                    .unwrap();
-
-                renderer::render(ppu, &mut frame);
-                texture.update(None, &frame.data, 256 * 3).unwrap();
-                ppu.current_frame = frame;
-                canvas_mut.copy(&texture, None, None).unwrap();
-
-                canvas_mut.present();
-
-                if keyboard_input {
-                    let mut event_pump = sdl_context.event_pump().unwrap();
-                    for event in event_pump.poll_iter() {
-                        match event {
-                            Event::Quit { .. }
-                            | Event::KeyDown {
-                                keycode: Some(Keycode::Escape),
-                                ..
-                            } => std::process::exit(0),
-                            Event::KeyDown { keycode, .. } => {
-                                if let Some(key) = key_map.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                                    joypad.set_button_pressed_status(key, true);
-                                }
-                            }
-                            Event::KeyUp { keycode, .. } => {
-                                if let Some(key) = key_map.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                                    joypad.set_button_pressed_status(key, false);
-                                }
-                            }
-
-                            _ => { /* do nothing */ }
-                        }
+        if load_format != LoadFormat::CPU {
+            let mut disk_image = vec![];
+            let crt = if load_format == LoadFormat::FDS {
+                let bios = std::fs::read(bios_path.ok_or(CartridgeError::MissingBios)?)?;
+                let disk = match std::fs::read(Emulator::disk_patch_path(cartridge_path)) {
+                    Ok(patch) => apply_ips(&bytes, &patch)?,
+                    Err(_) => bytes.clone(),
+                };
+                disk_image = bytes;
+                Cartridge::from_fds(disk, bios)?
+            } else {
+                let patch_path = patch_path.map(PathBuf::from).or_else(|| Emulator::soft_patch_path(cartridge_path));
+                match patch_path {
+                    Some(patch_path) => {
+                        log::info!("Applying {}", patch_path.display());
+                        Cartridge::new(apply_patch(&bytes, &std::fs::read(patch_path)?)?)?
                     }
+                    None => Cartridge::new(bytes)?,
                 }
-            });
+            };
+            if let Some(game) = &crt.game {
+                canvas_clone.borrow_mut().window_mut().set_title(&format!("NESRS - {}", game.title))?;
+            }
+            let game = crt.game.clone();
+
+            let bus = Bus::new(Some(crt), frontend);
 
             let cpu = Arc::new(RefCell::new(CPU::new(bus)));
-            Ok(Self {
+            let mut emulator = Self {
                 cpu,
                 triggers,
-                load_format: LoadFormat::NES,
+                load_format,
                 cartridge_path: String::from(cartridge_path),
-            })
+                region_override: None,
+                dot_rendering: None,
+                throttle,
+                disk_image,
+                game,
+                ram_search: None,
+            };
+            let cheat_path = Path::new(cartridge_path).with_extension("cht");
+            if cheat_path.is_file() {
+                emulator.load_cheats_beside_game(&cheat_path);
+            }
+            Ok(emulator)
         } else {
-            let mut cpu = Emulator::deserialize_cpu(bytes);
-            cpu.bus.gameloop_callback = Some(Box::new(move |ppu: &mut PPU, joypad: &mut Joypad| {
-                let mut frame = Frame::new();
-                let canvas_clone = canvas.clone();
-                let mut canvas_mut = canvas_clone.borrow_mut();
-                let creator = canvas_mut.texture_creator();
-                let mut texture = creator
-                    .create_texture_target(PixelFormatEnum::RGB24, 256, 240)
-                    .unwrap();
+            let mut cpu = Emulator::deserialize_cpu(bytes)?;
+            cpu.bus.gameloop_callback = Some(Box::new(frontend));
+            Ok(Self { cpu: Arc::new(RefCell::new(cpu)), triggers, load_format: LoadFormat::CPU, cartridge_path: String::from(cartridge_path), region_override: None, dot_rendering: None, throttle, disk_image: vec![], game: None, ram_search: None })
+        }
+    }
 
+    // the game cycle
+    fn frontend_callback(canvas: Rc<RefCell<Canvas<Window>>>, sdl_context: Sdl, key_map: HashMap<Keycode, JoypadButton>,
+                         keyboard_input: bool, throttle: Rc<Cell<bool>>) -> impl FnMut(&mut PPU, &mut Joypad) {
+        let mut next_frame = Instant::now();
+        move |ppu: &mut PPU, joypad: &mut Joypad| {
+            let mut frame = Frame::new();
+            let canvas_clone = canvas.clone();
+            let mut canvas_mut = canvas_clone.borrow_mut();
+            let creator = canvas_mut.texture_creator();
+            let mut texture = creator
+                .create_texture_target(PixelFormatEnum::RGB24, 256, 240)
+                .unwrap();
+
+            // with dot rendering the PPU already drew the frame
+            if !ppu.dot_rendering() {
                 renderer::render(ppu, &mut frame);
-                texture.update(None, &frame.data, 256 * 3).unwrap();
                 ppu.current_frame = frame;
-                canvas_mut.copy(&texture, None, None).unwrap();
-
-                canvas_mut.present();
-
-                if keyboard_input {
-                    let mut event_pump = sdl_context.event_pump().unwrap();
-                    for event in event_pump.poll_iter() {
-                        match event {
-                            Event::Quit { .. }
-                            | Event::KeyDown {
-                                keycode: Some(Keycode::Escape),
-                                ..
-                            } => std::process::exit(0),
-                            Event::KeyDown { keycode, .. } => {
-                                if let Some(key) = key_map.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                                    joypad.set_button_pressed_status(key, true);
-                                }
+            }
+            texture.update(None, &ppu.current_frame.data, 256 * 3).unwrap();
+            canvas_mut.copy(&texture, None, None).unwrap();
+
+            canvas_mut.present();
+
+            if throttle.get() {
+                let frame_duration = Duration::from_secs_f64(1.0 / ppu.region.frame_rate());
+                let now = Instant::now();
+                if next_frame > now {
+                    std::thread::sleep(next_frame - now);
+                } else {
+                    next_frame = now;
+                }
+                next_frame += frame_duration;
+            }
+
+            if keyboard_input {
+                let mut event_pump = sdl_context.event_pump().unwrap();
+                for event in event_pump.poll_iter() {
+                    match event {
+                        Event::Quit { .. }
+                        | Event::KeyDown {
+                            keycode: Some(Keycode::Escape),
+                            ..
+                        } => std::process::exit(0),
+                        Event::KeyDown { keycode, .. } => {
+                            if let Some(key) = key_map.get(&keycode.unwrap_or(Keycode::Ampersand)) {
+                                joypad.set_button_pressed_status(key, true);
                             }
-                            Event::KeyUp { keycode, .. } => {
-                                if let Some(key) = key_map.get(&keycode.unwrap_or(Keycode::Ampersand)) {
-                                    joypad.set_button_pressed_status(key, false);
-                                }
+                        }
+                        Event::KeyUp { keycode, .. } => {
+                            if let Some(key) = key_map.get(&keycode.unwrap_or(Keycode::Ampersand)) {
+                                joypad.set_button_pressed_status(key, false);
                             }
-
-                            _ => { /* do nothing */ }
                         }
+
+                        _ => { /* do nothing */ }
                     }
                 }
-            }));
-            Ok(Self { cpu: Arc::new(RefCell::new(cpu)), triggers, load_format: LoadFormat::CPU, cartridge_path: String::from(cartridge_path) })
+            }
         }
     }
 
@@ -243,19 +518,43 @@ impl Emulator {
         println!("Cpu state:: PC: {}, A: {}, X: {}, Y: {}", cpu_borrow.program_counter, cpu_borrow.register_a, cpu_borrow.register_x, cpu_borrow.register_y);
         let bytes = to_stdvec(&*cpu_borrow)?;
         let mut file = File::create(path)?;
+        file.write_all(&STATE_TAG)?;
         file.write_all(&bytes)?;
         Ok(())
     }
 
-    fn deserialize_cpu(data: Vec<u8>) -> CPU<'static> {
-        let mut new_cpu: CPU = postcard::from_bytes(data.as_slice())
-            .expect("Failed to deserialize cpu");
-        new_cpu
+    // game.ips, game.ups or game.bps beside game.nes is applied as it loads
+    fn soft_patch_path(cartridge_path: &str) -> Option<PathBuf> {
+        ["ips", "ups", "bps"].iter()
+            .map(|extension| Path::new(cartridge_path).with_extension(extension))
+            .find(|path| path.is_file())
+    }
+
+    // game.fds keeps what the game saved to the disk in game.fds.ips
+    fn disk_patch_path(disk_path: &str) -> PathBuf {
+        let mut path = PathBuf::from(disk_path).into_os_string();
+        path.push(".ips");
+        PathBuf::from(path)
+    }
+
+    fn save_disk(&self, sides: &[Vec<u8>]) -> anyhow::Result<()> {
+        let image = fds::rebuild_image(&self.disk_image, sides);
+        let patch = create_ips(&self.disk_image, &image)?;
+        std::fs::write(Emulator::disk_patch_path(&self.cartridge_path), patch)?;
+        Ok(())
+    }
+
+    fn deserialize_cpu(data: Vec<u8>) -> anyhow::Result<CPU<Bus<'static>>> {
+        let data = data.strip_prefix(&STATE_TAG).unwrap_or(&data);
+        let new_cpu: CPU<Bus> = postcard::from_bytes(data)
+            .map_err(|err| anyhow::anyhow!("Failed to deserialize cpu: {}", err))?;
+        Ok(new_cpu)
     }
 
-    fn check_triggers(&self, cpu: &mut CPU) -> bool {
+    fn check_triggers(&self, cpu: &mut CPU<Bus>) -> bool {
         self.triggers.iter().any(|trigger| match trigger {
-            EmulatorTrigger::MemEquals { addr, value } => cpu.mem_read(*addr) == *value
+            EmulatorTrigger::MemEquals { addr, value } => cpu.mem_peek(*addr) == *value,
+            EmulatorTrigger::CpuFault => cpu.fault.is_some(),
         })
     }
 }
\ No newline at end of file
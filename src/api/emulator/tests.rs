@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod emulator_tests {
+    use crate::api::emulator::{Emulator, LoadFormat, STATE_TAG};
+    use crate::hw::bus::Bus;
+    use crate::hw::cpu::CPU;
+
+    #[test]
+    fn test_detect_format() {
+        let mut ines = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x00, 0x00];
+        assert_eq!(LoadFormat::detect(&ines), LoadFormat::NES);
+        // NES 2.0
+        ines[7] = 0x08;
+        assert_eq!(LoadFormat::detect(&ines), LoadFormat::NES);
+        assert_eq!(LoadFormat::detect(b"UNIF\x07\x00\x00\x00"), LoadFormat::NES);
+        assert_eq!(LoadFormat::detect(b"FDS\x1a\x02"), LoadFormat::FDS);
+        assert_eq!(LoadFormat::detect(b"\x01*NINTENDO-HVC*\x00"), LoadFormat::FDS);
+        assert_eq!(LoadFormat::detect(b"NESM\x1a\x01"), LoadFormat::NSF);
+        assert_eq!(LoadFormat::detect(b"NESRS\x1a\x00"), LoadFormat::CPU);
+        assert_eq!(LoadFormat::detect(b"PK\x03\x04"), LoadFormat::Unknown);
+        assert_eq!(LoadFormat::detect(&[]), LoadFormat::Unknown);
+    }
+
+    #[test]
+    fn test_state_file() {
+        let mut cpu = CPU::new(Bus::new(None, |_, _| {}));
+        cpu.program_counter = 0x1234;
+        cpu.register_a = 0x56;
+        let bytes = postcard::to_stdvec(&cpu).unwrap();
+
+        let mut state = STATE_TAG.to_vec();
+        state.extend_from_slice(&bytes);
+        assert_eq!(LoadFormat::detect(&state), LoadFormat::CPU);
+        let loaded = Emulator::deserialize_cpu(state).unwrap();
+        assert_eq!(loaded.program_counter, 0x1234);
+        assert_eq!(loaded.register_a, 0x56);
+
+        // states saved before the header still load
+        let loaded = Emulator::deserialize_cpu(bytes).unwrap();
+        assert_eq!(loaded.program_counter, 0x1234);
+    }
+}
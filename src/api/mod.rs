@@ -1 +1,4 @@
-pub mod emulator;
\ No newline at end of file
+pub mod archive;
+pub mod emulator;
+pub mod nsf;
+pub mod ram_search;
\ No newline at end of file
@@ -0,0 +1,183 @@
+mod tests;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use anyhow::bail;
+use crate::api::archive;
+use crate::hw::bus::Bus;
+use crate::hw::cartridge::nsf::{Nsf, IDLE_LOOP};
+use crate::hw::cpu::CPU;
+use crate::hw::memory::Memory;
+use crate::hw::region::Region;
+
+// https://www.nesdev.org/wiki/NSF
+// plays NSF tunes on the emulated CPU and APU the way a hardware NSF player does: INIT once per
+// track, then PLAY at the rate given in the header, with the CPU waiting in an idle loop in between
+pub struct NsfPlayer {
+    cpu: CPU<Bus<'static>>,
+    nsf: Nsf,
+    track: u8,
+    // CPU cycles between PLAY calls, and the cycle of the next one
+    play_period: f64,
+    next_play: f64,
+}
+
+impl NsfPlayer {
+    // also opens the .nsf inside a zip or gzip archive
+    pub fn new(path: &str) -> anyhow::Result<Self> {
+        let (raw, _) = archive::read(path, None, &["nsf"])?;
+        NsfPlayer::from_bytes(&raw)
+    }
+
+    pub fn from_bytes(raw: &[u8]) -> anyhow::Result<Self> {
+        let nsf = Nsf::new(raw)?;
+        if nsf.expansion_audio != 0 {
+            log::warn!("NSF expansion audio isn't emulated, only the APU channels play");
+        }
+        let mut bus = Bus::new(None, |_, _| {});
+        bus.insert_nsf(&nsf);
+        let speed = if nsf.play_speed() == 0 { 1_000_000.0 / nsf.region.frame_rate() } else { nsf.play_speed() as f64 };
+        let play_period = speed * nsf.region.cpu_clock_rate() / 1_000_000.0;
+
+        let mut player = NsfPlayer { cpu: CPU::new(bus), track: 0, play_period, next_play: 0.0, nsf };
+        player.cpu.power_on();
+        player.select_track(player.nsf.starting_song - 1)?;
+        Ok(player)
+    }
+
+    pub fn get_title(&self) -> &str {
+        &self.nsf.title
+    }
+
+    pub fn get_artist(&self) -> &str {
+        &self.nsf.artist
+    }
+
+    pub fn get_copyright(&self) -> &str {
+        &self.nsf.copyright
+    }
+
+    pub fn get_track_count(&self) -> u8 {
+        self.nsf.songs
+    }
+
+    // 0 based, the header's starting song is selected when the file is loaded
+    pub fn get_track(&self) -> u8 {
+        self.track
+    }
+
+    pub fn get_region(&self) -> Region {
+        self.nsf.region
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.cpu.bus.apu.set_sample_rate(sample_rate);
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.cpu.bus.apu.sample_rate()
+    }
+
+    // https://www.nesdev.org/wiki/NSF#Initializing_a_tune
+    pub fn select_track(&mut self, track: u8) -> anyhow::Result<()> {
+        if track >= self.nsf.songs {
+            bail!("Track {} doesn't exist, the file has {} tracks", track + 1, self.nsf.songs);
+        }
+        self.track = track;
+
+        for addr in (0x0000..0x0800).chain(0x6000..0x8000) {
+            self.cpu.mem_write(addr, 0);
+        }
+        for addr in 0x4000..0x4014 {
+            self.cpu.mem_write(addr, 0);
+        }
+        self.cpu.mem_write(0x4015, 0x00);
+        self.cpu.mem_write(0x4015, 0x0F);
+        self.cpu.mem_write(0x4017, 0x40);
+        if self.nsf.bankswitched() {
+            for (register, &bank) in self.nsf.bankswitch.iter().enumerate() {
+                self.cpu.mem_write(0x5FF8 + register as u16, bank);
+            }
+        }
+
+        self.cpu.register_a = track;
+        self.cpu.register_x = (self.nsf.region == Region::PAL) as u8;
+        self.cpu.stack_pointer = 0xFD;
+        self.cpu.fault = None;
+        self.call(self.nsf.init_addr);
+        // INIT runs to completion before the first PLAY
+        let limit = self.cpu.bus.cycles() + self.nsf.region.cpu_clock_rate() as usize;
+        while self.cpu.program_counter != IDLE_LOOP && self.cpu.bus.cycles() < limit && !self.step() {}
+        self.next_play = self.cpu.bus.cycles() as f64;
+        self.cpu.bus.apu.take_samples();
+        Ok(())
+    }
+
+    // plays the current track for the given time, returns mono samples in the 0.0..1.0 range
+    pub fn render(&mut self, seconds: f64) -> Vec<f32> {
+        let end = self.cpu.bus.cycles() + (seconds * self.nsf.region.cpu_clock_rate()) as usize;
+        let mut samples = Vec::new();
+        while self.cpu.bus.cycles() < end {
+            // a PLAY that runs past the next call delays it, like on the hardware
+            if self.cpu.program_counter == IDLE_LOOP && self.cpu.bus.cycles() as f64 >= self.next_play {
+                self.next_play += self.play_period;
+                self.call(self.nsf.play_addr);
+                // the APU only buffers a second of audio
+                samples.extend(self.cpu.bus.apu.take_samples());
+            }
+            if self.step() {
+                break;
+            }
+        }
+        samples.extend(self.cpu.bus.apu.take_samples());
+        samples
+    }
+
+    pub fn write_wav(&mut self, path: &str, seconds: f64) -> anyhow::Result<()> {
+        let samples = self.render(seconds);
+        write_wav(path, &samples, self.get_sample_rate())?;
+        Ok(())
+    }
+
+    // the routine returns to the idle loop with its RTS
+    fn call(&mut self, addr: u16) {
+        self.cpu.stack_push_u16(IDLE_LOOP - 1);
+        self.cpu.program_counter = addr;
+    }
+
+    // true once the CPU jammed, the tune stays silent from then on
+    fn step(&mut self) -> bool {
+        self.cpu.step(|_| {});
+        self.cpu.fault.is_some()
+    }
+}
+
+// 16 bit mono PCM
+pub fn write_wav(path: &str, samples: &[f32], sample_rate: u32) -> std::io::Result<()> {
+    let data_size = samples.len() as u32 * 2;
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(b"RIFF")?;
+    file.write_all(&(36 + data_size).to_le_bytes())?;
+    file.write_all(b"WAVEfmt ")?;
+    file.write_all(&16u32.to_le_bytes())?;
+    // PCM, one channel
+    file.write_all(&1u16.to_le_bytes())?;
+    file.write_all(&1u16.to_le_bytes())?;
+    file.write_all(&sample_rate.to_le_bytes())?;
+    file.write_all(&(sample_rate * 2).to_le_bytes())?;
+    file.write_all(&2u16.to_le_bytes())?;
+    file.write_all(&16u16.to_le_bytes())?;
+    file.write_all(b"data")?;
+    file.write_all(&data_size.to_le_bytes())?;
+
+    // the console's output filter removes the DC offset of the mixer
+    let (mut previous_in, mut previous_out) = (samples.first().copied().unwrap_or(0.0), 0.0);
+    for &sample in samples {
+        let filtered = sample - previous_in + 0.995 * previous_out;
+        previous_in = sample;
+        previous_out = filtered;
+        let value = (filtered * 2.0).clamp(-1.0, 1.0) * i16::MAX as f32;
+        file.write_all(&(value as i16).to_le_bytes())?;
+    }
+    file.flush()
+}
@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod nsf_tests {
+    use crate::api::nsf::NsfPlayer;
+    use crate::hw::cartridge::nsf::Nsf;
+    use crate::hw::cartridge::CartridgeError;
+    use crate::hw::memory::Memory;
+    use crate::hw::region::Region;
+
+    // INIT stores A and X at $00 and $01 and starts a square wave on pulse 1, PLAY counts its calls at $02
+    const INIT: [u8; 24] = [
+        0x85, 0x00, 0x86, 0x01, 0xA9, 0x00, 0x85, 0x02,
+        0xA9, 0xBF, 0x8D, 0x00, 0x40, 0xA9, 0xFD, 0x8D,
+        0x02, 0x40, 0xA9, 0x00, 0x8D, 0x03, 0x40, 0x60,
+    ];
+    const PLAY: [u8; 3] = [0xE6, 0x02, 0x60];
+
+    fn create_nsf_header(songs: u8, starting_song: u8, bankswitch: [u8; 8]) -> Vec<u8> {
+        let mut header = b"NESM\x1a".to_vec();
+        header.push(1);
+        header.push(songs);
+        header.push(starting_song);
+        header.extend_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x40, 0x80]);
+        for text in [&b"Test Tune"[..], b"Composer", b"2026"] {
+            let mut field = text.to_vec();
+            field.resize(32, 0);
+            header.extend(field);
+        }
+        // 16639 microseconds, 60.1 Hz
+        header.extend_from_slice(&16639u16.to_le_bytes());
+        header.extend_from_slice(&bankswitch);
+        header.extend_from_slice(&19997u16.to_le_bytes());
+        header.resize(0x80, 0);
+        header
+    }
+
+    fn create_nsf(songs: u8, starting_song: u8) -> Vec<u8> {
+        let mut nsf = create_nsf_header(songs, starting_song, [0; 8]);
+        let mut data = INIT.to_vec();
+        data.resize(0x40, 0);
+        data.extend_from_slice(&PLAY);
+        nsf.extend(data);
+        nsf
+    }
+
+    #[test]
+    fn test_nsf_header() {
+        let nsf = Nsf::new(&create_nsf(3, 2)).unwrap();
+        assert_eq!(nsf.songs, 3);
+        assert_eq!(nsf.starting_song, 2);
+        assert_eq!(nsf.init_addr, 0x8000);
+        assert_eq!(nsf.play_addr, 0x8040);
+        assert_eq!(nsf.title, "Test Tune");
+        assert_eq!(nsf.artist, "Composer");
+        assert_eq!(nsf.play_speed(), 16639);
+        assert_eq!(nsf.region, Region::NTSC);
+        assert!(!nsf.bankswitched());
+
+        assert!(matches!(Nsf::new(b"NESM\x1a\x01"), Err(CartridgeError::TruncatedNsf(6))));
+        assert!(matches!(Nsf::new(&[0; 0x100]), Err(CartridgeError::InvalidHeader { .. })));
+    }
+
+    #[test]
+    fn test_nsf_init_gets_the_track() {
+        let mut player = NsfPlayer::from_bytes(&create_nsf(3, 2)).unwrap();
+        assert_eq!(player.get_track(), 1);
+        assert_eq!(player.cpu.mem_peek(0x00), 1);
+        assert_eq!(player.cpu.mem_peek(0x01), 0);
+
+        player.select_track(2).unwrap();
+        assert_eq!(player.cpu.mem_peek(0x00), 2);
+        assert!(player.select_track(3).is_err());
+    }
+
+    #[test]
+    fn test_nsf_play_rate() {
+        let mut player = NsfPlayer::from_bytes(&create_nsf(1, 1)).unwrap();
+        let samples = player.render(0.5);
+        // the first call comes right after INIT
+        assert_eq!(player.cpu.mem_peek(0x02), 31);
+        assert!((samples.len() as i32 - 22050).abs() < 10);
+        // the square wave is playing
+        let low = samples.iter().cloned().fold(f32::MAX, f32::min);
+        let high = samples.iter().cloned().fold(0.0, f32::max);
+        assert!(high - low > 0.1);
+    }
+
+    #[test]
+    fn test_nsf_render_longer_than_the_apu_buffer() {
+        let mut player = NsfPlayer::from_bytes(&create_nsf(1, 1)).unwrap();
+        let samples = player.render(3.0);
+        assert!((samples.len() as i32 - 3 * 44100).abs() < 10);
+    }
+
+    #[test]
+    fn test_nsf_bankswitching() {
+        // $8000 starts with bank 1, where INIT copies a byte from bank 0 at $9000
+        let mut nsf = create_nsf_header(1, 1, [1, 0, 0, 0, 0, 0, 0, 0]);
+        nsf.extend(vec![0xAA; 0x1000]);
+        nsf.extend_from_slice(&[0xAD, 0x00, 0x90, 0x85, 0x03, 0x8D, 0xF9, 0x5F, 0x60]);
+        let mut player = NsfPlayer::from_bytes(&nsf).unwrap();
+        assert_eq!(player.cpu.mem_peek(0x03), 0xAA);
+        // INIT then mapped bank $AA, past the end of the data, to $9000
+        assert_eq!(player.cpu.mem_peek(0x9000), 0x00);
+        // the player maps the initial banks again for the next track
+        player.select_track(0).unwrap();
+        assert_eq!(player.cpu.mem_peek(0x03), 0xAA);
+    }
+
+    #[test]
+    fn test_nsf_write_wav() {
+        let mut player = NsfPlayer::from_bytes(&create_nsf(1, 1)).unwrap();
+        player.set_sample_rate(8000);
+        let path = std::env::temp_dir().join("nesrs_test_nsf.wav");
+        player.write_wav(path.to_str().unwrap(), 0.5).unwrap();
+        let wav = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 8000);
+        let data_size = u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize;
+        assert_eq!(wav.len(), 44 + data_size);
+        assert!((data_size as i32 / 2 - 4000).abs() < 10);
+    }
+}
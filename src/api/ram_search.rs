@@ -0,0 +1,62 @@
+mod tests;
+
+use crate::hw::bus::Bus;
+use crate::hw::memory::Memory;
+
+// what a RAM value has to do since the last snapshot to stay a candidate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RamFilter {
+    Equal,
+    Changed,
+    Increased,
+    Decreased,
+    Value(u8),
+}
+
+impl RamFilter {
+    fn matches(&self, previous: u8, current: u8) -> bool {
+        match self {
+            RamFilter::Equal => current == previous,
+            RamFilter::Changed => current != previous,
+            RamFilter::Increased => current > previous,
+            RamFilter::Decreased => current < previous,
+            RamFilter::Value(value) => current == *value,
+        }
+    }
+}
+
+// cheat search style hunt for game variables: every RAM address starts as a candidate, and each
+// filter keeps the ones whose value behaved as asked since the last snapshot
+pub struct RamSearch {
+    // addresses with their value in the last snapshot
+    candidates: Vec<(u16, u8)>,
+}
+
+impl RamSearch {
+    pub fn new(bus: &mut Bus) -> Self {
+        let candidates = bus.ram_addresses().into_iter().map(|addr| (addr, bus.mem_peek(addr))).collect();
+        RamSearch { candidates }
+    }
+
+    // remembers the current values without dropping any candidate
+    pub fn snapshot(&mut self, memory: &mut impl Memory) {
+        for (addr, value) in self.candidates.iter_mut() {
+            *value = memory.mem_peek(*addr);
+        }
+    }
+
+    // returns how many candidates are left, their values become the new snapshot
+    pub fn filter(&mut self, memory: &mut impl Memory, filter: RamFilter) -> usize {
+        self.candidates.retain_mut(|(addr, value)| {
+            let current = memory.mem_peek(*addr);
+            let keep = filter.matches(*value, current);
+            *value = current;
+            keep
+        });
+        self.candidates.len()
+    }
+
+    pub fn candidates(&self) -> &[(u16, u8)] {
+        &self.candidates
+    }
+}
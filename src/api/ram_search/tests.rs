@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod ram_search_tests {
+    use crate::api::ram_search::{RamFilter, RamSearch};
+    use crate::hw::bus::Bus;
+    use crate::hw::cartridge::Cartridge;
+    use crate::hw::memory::Memory;
+
+    fn create_bus(mapper: u8) -> Bus<'static> {
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, (mapper & 0x0F) << 4, mapper & 0xF0, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.extend(vec![0; 0x8000 + 0x2000]);
+        Bus::new(Some(Cartridge::new(rom).unwrap()), |_, _| {})
+    }
+
+    fn addresses(search: &RamSearch) -> Vec<u16> {
+        search.candidates().iter().map(|(addr, _)| *addr).collect()
+    }
+
+    #[test]
+    fn test_ram_search_covers_ram() {
+        let mut bus = create_bus(0);
+        assert_eq!(RamSearch::new(&mut bus).candidates().len(), 0x800);
+        // MMC5 has PRG RAM at $6000
+        let mut bus = create_bus(5);
+        let search = RamSearch::new(&mut bus);
+        assert_eq!(search.candidates().len(), 0x800 + 0x2000);
+        assert_eq!(search.candidates().last(), Some(&(0x7FFF, 0)));
+        // FME-7 maps PRG ROM at $6000 until $8 selects its RAM
+        let mut bus = create_bus(69);
+        assert_eq!(RamSearch::new(&mut bus).candidates().len(), 0x800);
+        bus.mem_write(0x8000, 0x08);
+        bus.mem_write(0xA000, 0xC0);
+        assert_eq!(RamSearch::new(&mut bus).candidates().len(), 0x800 + 0x2000);
+    }
+
+    #[test]
+    fn test_ram_search_filters() {
+        let mut bus = create_bus(0);
+        bus.mem_write(0x0067, 3);
+        bus.mem_write(0x0100, 3);
+        bus.mem_write(0x0200, 3);
+        let mut search = RamSearch::new(&mut bus);
+
+        // a life is lost
+        bus.mem_write(0x0067, 2);
+        bus.mem_write(0x0100, 7);
+        bus.mem_write(0x0200, 1);
+        assert_eq!(search.filter(&mut bus, RamFilter::Changed), 3);
+        assert_eq!(addresses(&search), [0x0067, 0x0100, 0x0200]);
+
+        bus.mem_write(0x0067, 1);
+        bus.mem_write(0x0100, 8);
+        bus.mem_write(0x0200, 0);
+        assert_eq!(search.filter(&mut bus, RamFilter::Decreased), 2);
+        assert_eq!(search.candidates(), [(0x0067, 1), (0x0200, 0)]);
+
+        assert_eq!(search.filter(&mut bus, RamFilter::Equal), 2);
+        assert_eq!(search.filter(&mut bus, RamFilter::Value(1)), 1);
+        assert_eq!(search.candidates(), [(0x0067, 1)]);
+    }
+
+    #[test]
+    fn test_ram_search_snapshot() {
+        let mut bus = create_bus(5);
+        // MMC5 PRG RAM is write protected until both registers unlock it
+        bus.mem_write(0x5102, 0x02);
+        bus.mem_write(0x5103, 0x01);
+        bus.mem_write(0x6000, 10);
+        let mut search = RamSearch::new(&mut bus);
+        bus.mem_write(0x6000, 11);
+        bus.mem_write(0x0010, 1);
+        // the snapshot forgets the changes without filtering
+        search.snapshot(&mut bus);
+        assert_eq!(search.candidates().len(), 0x800 + 0x2000);
+        assert_eq!(search.filter(&mut bus, RamFilter::Changed), 0);
+
+        let mut search = RamSearch::new(&mut bus);
+        bus.mem_write(0x6000, 12);
+        assert_eq!(search.filter(&mut bus, RamFilter::Increased), 1);
+        assert_eq!(search.candidates(), [(0x6000, 12)]);
+    }
+}
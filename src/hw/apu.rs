@@ -0,0 +1,290 @@
+pub(crate) mod units;
+pub(crate) mod pulse;
+mod triangle;
+mod noise;
+mod dmc;
+mod tests;
+
+use serde::{Deserialize, Serialize};
+use crate::hw::apu::dmc::Dmc;
+use crate::hw::apu::noise::Noise;
+use crate::hw::apu::pulse::Pulse;
+use crate::hw::apu::triangle::Triangle;
+use crate::hw::region::Region;
+
+// https://www.nesdev.org/wiki/APU_Frame_Counter
+// CPU cycles at which the sequencer steps, the last entry is only used in 5-step mode
+const NTSC_FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+#[derive(Serialize, Deserialize)]
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    five_step_mode: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    frame_cycle: u32,
+    cycles: u64,
+    pub region: Region,
+    // level of the cartridge's expansion audio, added to the mix
+    expansion_output: f32,
+
+    sample_rate: u32,
+    // CPU cycles since the last sample, output is averaged over them
+    sample_clock: f64,
+    sample_sum: f32,
+    sample_count: u32,
+    #[serde(skip)]
+    samples: Vec<f32>,
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl APU {
+    pub fn new() -> Self {
+        APU {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            five_step_mode: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            cycles: 0,
+            region: Region::NTSC,
+            expansion_output: 0.0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_clock: 0.0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            samples: vec![],
+        }
+    }
+
+    // https://www.nesdev.org/wiki/CPU_power_up_state#APU
+    pub fn power_on(&mut self) {
+        let samples = std::mem::take(&mut self.samples);
+        *self = APU {
+            region: self.region,
+            sample_rate: self.sample_rate,
+            samples,
+            ..APU::new()
+        };
+    }
+
+    // channels are silenced as if $4015 was written with 0, the frame counter mode is kept
+    pub fn reset(&mut self) {
+        self.write_status(0);
+        self.frame_irq = false;
+        self.frame_cycle = 0;
+    }
+
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4000 => self.pulse1.write_control(value),
+            0x4001 => self.pulse1.write_sweep(value),
+            0x4002 => self.pulse1.write_timer_low(value),
+            0x4003 => self.pulse1.write_timer_high(value),
+            0x4004 => self.pulse2.write_control(value),
+            0x4005 => self.pulse2.write_sweep(value),
+            0x4006 => self.pulse2.write_timer_low(value),
+            0x4007 => self.pulse2.write_timer_high(value),
+            0x4008 => self.triangle.write_control(value),
+            0x400A => self.triangle.write_timer_low(value),
+            0x400B => self.triangle.write_timer_high(value),
+            0x400C => self.noise.write_control(value),
+            0x400E => self.noise.write_period(value, self.region),
+            0x400F => self.noise.write_length(value),
+            0x4010 => self.dmc.write_control(value, self.region),
+            0x4011 => self.dmc.write_direct_load(value),
+            0x4012 => self.dmc.write_sample_address(value),
+            0x4013 => self.dmc.write_sample_length(value),
+            0x4015 => self.write_status(value),
+            0x4017 => self.write_frame_counter(value),
+            _ => {}
+        }
+    }
+
+    // ---D NT21
+    fn write_status(&mut self, value: u8) {
+        self.pulse1.length.set_enabled(value & 0b0000_0001 != 0);
+        self.pulse2.length.set_enabled(value & 0b0000_0010 != 0);
+        self.triangle.length.set_enabled(value & 0b0000_0100 != 0);
+        self.noise.length.set_enabled(value & 0b0000_1000 != 0);
+        self.dmc.set_enabled(value & 0b0001_0000 != 0);
+    }
+
+    // MI-- ----
+    // the sequencer restarts 3-4 CPU cycles after the write on hardware, here it restarts immediately
+    fn write_frame_counter(&mut self, value: u8) {
+        self.five_step_mode = value & 0b1000_0000 != 0;
+        self.irq_inhibit = value & 0b0100_0000 != 0;
+        if self.irq_inhibit {
+            self.frame_irq = false;
+        }
+        self.frame_cycle = 0;
+        if self.five_step_mode {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        }
+    }
+
+    // IF-D NT21, reading clears the frame interrupt flag
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    pub fn peek_status(&self) -> u8 {
+        let mut status = 0;
+        if self.pulse1.length.is_active() {
+            status |= 0b0000_0001;
+        }
+        if self.pulse2.length.is_active() {
+            status |= 0b0000_0010;
+        }
+        if self.triangle.length.is_active() {
+            status |= 0b0000_0100;
+        }
+        if self.noise.length.is_active() {
+            status |= 0b0000_1000;
+        }
+        if self.dmc.is_active() {
+            status |= 0b0001_0000;
+        }
+        if self.frame_irq {
+            status |= 0b0100_0000;
+        }
+        if self.dmc.irq_flag {
+            status |= 0b1000_0000;
+        }
+        status
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.frame_irq || self.dmc.irq_flag
+    }
+
+    pub fn dmc_fetch_address(&self) -> Option<u16> {
+        self.dmc.fetch_address()
+    }
+
+    pub fn dmc_fill_sample_buffer(&mut self, value: u8) {
+        self.dmc.fill_sample_buffer(value);
+    }
+
+    // advances the APU by one CPU cycle
+    pub fn tick(&mut self) {
+        self.clock_frame_counter();
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.cycles % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.cycles += 1;
+
+        self.sample();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        // Dendy keeps the NTSC frame counter
+        let steps = if self.region == Region::PAL { &PAL_FRAME_STEPS } else { &NTSC_FRAME_STEPS };
+
+        self.frame_cycle += 1;
+        if self.frame_cycle == steps[0] || self.frame_cycle == steps[2] {
+            self.clock_quarter_frame();
+        } else if self.frame_cycle == steps[1] {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        } else if self.frame_cycle == steps[3] && !self.five_step_mode {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+            if !self.irq_inhibit {
+                self.frame_irq = true;
+            }
+            self.frame_cycle = 0;
+        } else if self.frame_cycle == steps[4] {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+            self.frame_cycle = 0;
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    // https://www.nesdev.org/wiki/APU_Mixer
+    pub fn output(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+
+        let tnd = self.triangle.output() as f32 / 8227.0 + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+
+        pulse_out + tnd_out + self.expansion_output
+    }
+
+    pub fn set_expansion_output(&mut self, level: f32) {
+        self.expansion_output = level;
+    }
+
+    fn sample(&mut self) {
+        self.sample_sum += self.output();
+        self.sample_count += 1;
+        self.sample_clock += 1.0;
+
+        let cycles_per_sample = self.region.cpu_clock_rate() / self.sample_rate as f64;
+        if self.sample_clock >= cycles_per_sample {
+            self.sample_clock -= cycles_per_sample;
+            // keep at most a second of audio when nobody drains the buffer
+            if self.samples.len() >= self.sample_rate as usize {
+                self.samples.drain(..self.sample_rate as usize / 2);
+            }
+            self.samples.push(self.sample_sum / self.sample_count as f32);
+            self.sample_sum = 0.0;
+            self.sample_count = 0;
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_clock = 0.0;
+    }
+
+    // mono samples in the 0.0..1.0 range produced since the last call
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::apu::units::{Envelope, LengthCounter};
+use crate::hw::region::Region;
+
+// https://www.nesdev.org/wiki/APU_Noise
+// periods in CPU cycles
+const NTSC_PERIOD_TABLE: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const PAL_PERIOD_TABLE: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+
+#[derive(Serialize, Deserialize)]
+pub struct Noise {
+    pub length: LengthCounter,
+    pub(super) envelope: Envelope,
+    mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift_register: u16,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Noise {
+            length: LengthCounter::default(),
+            envelope: Envelope::default(),
+            mode: false,
+            timer_period: NTSC_PERIOD_TABLE[0],
+            timer: 0,
+            shift_register: 1,
+        }
+    }
+}
+
+impl Noise {
+    // --LC VVVV
+    pub fn write_control(&mut self, value: u8) {
+        self.length.halt = value & 0b0010_0000 != 0;
+        self.envelope.write(value);
+    }
+
+    // M--- PPPP
+    pub fn write_period(&mut self, value: u8, region: Region) {
+        self.mode = value & 0b1000_0000 != 0;
+        let table = if region == Region::PAL { &PAL_PERIOD_TABLE } else { &NTSC_PERIOD_TABLE };
+        self.timer_period = table[(value & 0b1111) as usize];
+    }
+
+    // LLLL L---
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value >> 3);
+        self.envelope.restart();
+    }
+
+    // clocked every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.shift_register & 1 == 1 || !self.length.is_active() {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::apu::units::{Envelope, LengthCounter};
+
+// https://www.nesdev.org/wiki/APU_Pulse
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Pulse {
+    // pulse 1 negates the sweep change in ones' complement, pulse 2 in two's complement
+    ones_complement: bool,
+    // expansion pulses (MMC5) have no sweep unit and play any period
+    sweep_unit: bool,
+    duty: u8,
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+    pub length: LengthCounter,
+    envelope: Envelope,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Self {
+        Pulse {
+            ones_complement,
+            sweep_unit: true,
+            duty: 0,
+            sequence: 0,
+            timer_period: 0,
+            timer: 0,
+            length: LengthCounter::default(),
+            envelope: Envelope::default(),
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_divider: 0,
+            sweep_reload: false,
+        }
+    }
+
+    pub fn without_sweep() -> Self {
+        Pulse { sweep_unit: false, ..Pulse::new(false) }
+    }
+
+    // DDLC VVVV
+    pub fn write_control(&mut self, value: u8) {
+        self.duty = value >> 6;
+        self.length.halt = value & 0b0010_0000 != 0;
+        self.envelope.write(value);
+    }
+
+    // EPPP NSSS
+    pub fn write_sweep(&mut self, value: u8) {
+        self.sweep_enabled = value & 0b1000_0000 != 0;
+        self.sweep_period = (value >> 4) & 0b111;
+        self.sweep_negate = value & 0b0000_1000 != 0;
+        self.sweep_shift = value & 0b111;
+        self.sweep_reload = true;
+    }
+
+    pub fn write_timer_low(&mut self, value: u8) {
+        self.timer_period = (self.timer_period & 0x0700) | value as u16;
+    }
+
+    // LLLL LHHH
+    pub fn write_timer_high(&mut self, value: u8) {
+        self.timer_period = (self.timer_period & 0x00FF) | ((value & 0b111) as u16) << 8;
+        self.length.load(value >> 3);
+        self.sequence = 0;
+        self.envelope.restart();
+    }
+
+    // clocked every APU cycle (every other CPU cycle)
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence = (self.sequence + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    // https://www.nesdev.org/wiki/APU_Sweep
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.is_muted() {
+            self.timer_period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer_period >> self.sweep_shift;
+        if !self.sweep_negate {
+            self.timer_period + change
+        } else if self.ones_complement {
+            self.timer_period.saturating_sub(change + 1)
+        } else {
+            self.timer_period.saturating_sub(change)
+        }
+    }
+
+    // the sweep unit mutes the channel even when it is disabled
+    fn is_muted(&self) -> bool {
+        self.sweep_unit && (self.timer_period < 8 || self.sweep_target() > 0x7FF)
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.is_active() || self.is_muted() || DUTY_TABLE[self.duty as usize][self.sequence as usize] == 0 {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
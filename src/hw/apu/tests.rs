@@ -0,0 +1,169 @@
+#[cfg(test)]
+mod tests {
+    use crate::hw::apu::APU;
+    use crate::hw::region::Region;
+
+    fn run_cycles(apu: &mut APU, cycles: u32) {
+        for _ in 0..cycles {
+            apu.tick();
+        }
+    }
+
+    #[test]
+    fn test_length_counter_needs_channel_enabled() {
+        let mut apu = APU::new();
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.peek_status() & 0b0000_0001, 0);
+
+        apu.write_register(0x4015, 0b0000_0001);
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.peek_status() & 0b0000_0001, 0b0000_0001);
+    }
+
+    #[test]
+    fn test_length_counter_counts_down_on_half_frames() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0b0000_0100);
+        // length index 3 loads 2
+        apu.write_register(0x400B, 0b0001_1000);
+        run_cycles(&mut apu, 14913);
+        assert_eq!(apu.peek_status() & 0b0000_0100, 0b0000_0100);
+        run_cycles(&mut apu, 29829 - 14913);
+        assert_eq!(apu.peek_status() & 0b0000_0100, 0);
+    }
+
+    #[test]
+    fn test_frame_irq_modes() {
+        let mut apu = APU::new();
+        run_cycles(&mut apu, 29829);
+        assert!(apu.irq_pending());
+        assert_eq!(apu.read_status() & 0b0100_0000, 0b0100_0000);
+        assert!(!apu.irq_pending());
+
+        // inhibited
+        let mut apu = APU::new();
+        apu.write_register(0x4017, 0b0100_0000);
+        run_cycles(&mut apu, 40000);
+        assert!(!apu.irq_pending());
+
+        // 5-step mode never raises the interrupt
+        let mut apu = APU::new();
+        apu.write_register(0x4017, 0b1000_0000);
+        run_cycles(&mut apu, 80000);
+        assert!(!apu.irq_pending());
+    }
+
+    #[test]
+    fn test_pulse_output() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0b0000_0001);
+        // 50% duty, constant volume 15
+        apu.write_register(0x4000, 0b1011_1111);
+        apu.write_register(0x4002, 0xFD);
+        apu.write_register(0x4003, 0b0000_1000);
+
+        let mut high = 0;
+        for _ in 0..4096 {
+            apu.tick();
+            if apu.pulse1.output() == 15 {
+                high += 1;
+            }
+        }
+        assert!(high > 1800 && high < 2300);
+    }
+
+    #[test]
+    fn test_pulse_muted_by_low_period() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0b0000_0001);
+        apu.write_register(0x4000, 0b1011_1111);
+        apu.write_register(0x4002, 0x07);
+        apu.write_register(0x4003, 0b0000_1000);
+
+        for _ in 0..64 {
+            apu.tick();
+            assert_eq!(apu.pulse1.output(), 0);
+        }
+    }
+
+    #[test]
+    fn test_envelope_decay() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0b0000_1000);
+        // decaying envelope with period 0, halted length counter
+        apu.write_register(0x400C, 0b0010_0000);
+        apu.write_register(0x400F, 0b0000_1000);
+
+        run_cycles(&mut apu, 7457);
+        assert_eq!(apu.noise.envelope.volume(), 15);
+        run_cycles(&mut apu, 14913 - 7457);
+        assert_eq!(apu.noise.envelope.volume(), 14);
+    }
+
+    #[test]
+    fn test_triangle_needs_linear_counter() {
+        let mut apu = APU::new();
+        apu.write_register(0x4015, 0b0000_0100);
+        apu.write_register(0x400A, 0x10);
+        apu.write_register(0x400B, 0b0000_1000);
+
+        // the linear counter is still 0
+        let start = apu.triangle.output();
+        run_cycles(&mut apu, 1000);
+        assert_eq!(apu.triangle.output(), start);
+
+        apu.write_register(0x4008, 0b0111_1111);
+        apu.write_register(0x400B, 0b0000_1000);
+        run_cycles(&mut apu, 7457 + 100);
+        assert_ne!(apu.triangle.output(), start);
+    }
+
+    #[test]
+    fn test_dmc_reads_sample_and_raises_irq() {
+        let mut apu = APU::new();
+        // IRQ enabled, fastest rate
+        apu.write_register(0x4010, 0b1000_1111);
+        apu.write_register(0x4012, 0x01);
+        apu.write_register(0x4013, 0x00);
+        apu.write_register(0x4015, 0b0001_0000);
+
+        assert_eq!(apu.dmc_fetch_address(), Some(0xC040));
+        apu.dmc_fill_sample_buffer(0xFF);
+        assert_eq!(apu.dmc_fetch_address(), None);
+        assert!(apu.irq_pending());
+        assert_eq!(apu.read_status() & 0b1001_0000, 0b1000_0000);
+
+        apu.write_register(0x4015, 0);
+        assert!(!apu.irq_pending());
+    }
+
+    #[test]
+    fn test_dmc_output_level() {
+        let mut apu = APU::new();
+        apu.write_register(0x4011, 0x40);
+        apu.write_register(0x4010, 0b0000_1111);
+        apu.write_register(0x4013, 0x00);
+        apu.write_register(0x4015, 0b0001_0000);
+        apu.dmc_fill_sample_buffer(0xFF);
+
+        // one byte takes up to 8 output cycles of 54 CPU cycles to start, then 8 more to play
+        run_cycles(&mut apu, 54 * 17);
+        assert_eq!(apu.dmc.output(), 0x40 + 16);
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mut apu = APU::new();
+        apu.set_sample_rate(48000);
+        // one second of CPU cycles
+        run_cycles(&mut apu, 1_789_773);
+        let samples = apu.take_samples();
+        assert!((47999..=48001).contains(&samples.len()));
+        assert!(apu.take_samples().is_empty());
+
+        let mut apu = APU::new();
+        apu.region = Region::PAL;
+        run_cycles(&mut apu, 1_662_607);
+        assert!((44099..=44101).contains(&apu.take_samples().len()));
+    }
+}
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::apu::units::LengthCounter;
+
+// https://www.nesdev.org/wiki/APU_Triangle
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Triangle {
+    pub length: LengthCounter,
+    // doubles as the length counter halt flag
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    timer_period: u16,
+    timer: u16,
+    sequence: u8,
+}
+
+impl Triangle {
+    // CRRR RRRR
+    pub fn write_control(&mut self, value: u8) {
+        self.control = value & 0b1000_0000 != 0;
+        self.length.halt = self.control;
+        self.linear_reload_value = value & 0b0111_1111;
+    }
+
+    pub fn write_timer_low(&mut self, value: u8) {
+        self.timer_period = (self.timer_period & 0x0700) | value as u16;
+    }
+
+    // LLLL LHHH
+    pub fn write_timer_high(&mut self, value: u8) {
+        self.timer_period = (self.timer_period & 0x00FF) | ((value & 0b111) as u16) << 8;
+        self.length.load(value >> 3);
+        self.linear_reload = true;
+    }
+
+    // clocked every CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.length.is_active() && self.linear_counter > 0 {
+                self.sequence = (self.sequence + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    // the sequencer holds its last value when silenced, there is no volume control
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.sequence as usize]
+    }
+}
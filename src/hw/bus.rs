@@ -2,37 +2,67 @@ mod tests;
 
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
+use crate::hw::apu::APU;
 use crate::hw::cartridge::Cartridge;
+use crate::hw::cartridge::mapper::MapperBoard;
+use crate::hw::cartridge::nsf::Nsf;
+use crate::hw::cheats::Cheats;
 use crate::hw::joypad::{Joypad, JoypadButton};
-use crate::hw::memory::Memory;
+use crate::hw::memory::{CpuBus, Memory};
 use crate::hw::ppu::PPU;
+use crate::hw::ram_init::RamInit;
+use crate::hw::region::Region;
 
 #[derive(Serialize, Deserialize)]
 pub struct Bus<'call> {
     #[serde(with = "BigArray")]
     cpu_vram: [u8; 2048],
-    cartridge: Option<Cartridge>,
+    mapper: Option<MapperBoard>,
     pub(crate) ppu: PPU,
+    pub apu: APU,
     cycles: usize,
+    region: Region,
+    ram_init: RamInit,
+    // the CPU drives the master clock, the PPU catches up with it one dot at a time
+    master_clock: u64,
+    ppu_clock: u64,
+    // CPU cycles of the current instruction already spent on memory accesses
+    instruction_cycles: u8,
+    // CPU cycle on which the PPU last raised its NMI line
+    nmi_cycle: usize,
 
     #[serde(skip)]
     pub gameloop_callback: Option<Box<dyn FnMut(&mut PPU, &mut Joypad) + 'call>>,
     pub joypad1: Joypad,
     keys_to_press: Vec<JoypadButton>,
     keys_to_release: Vec<JoypadButton>,
+    // last value driven onto the CPU data bus, returned by reads nothing responds to
+    open_bus: u8,
+    // cheats belong to the session, not to save states
+    #[serde(skip)]
+    pub cheats: Cheats,
 }
 
 impl<'call> Default for Bus<'call> {
     fn default() -> Self {
         Self {
             cpu_vram: [0; 2048],
-            cartridge: None,
+            mapper: None,
             ppu: PPU::new_empty_rom(),
+            apu: APU::new(),
             cycles: 0,
+            region: Region::NTSC,
+            ram_init: RamInit::default(),
+            master_clock: 0,
+            ppu_clock: 0,
+            instruction_cycles: 0,
+            nmi_cycle: 0,
             joypad1: Joypad::new(),
             keys_to_press: vec![],
             keys_to_release: vec![],
+            open_bus: 0,
             gameloop_callback: Some(Box::new(|_, _| {})),
+            cheats: Cheats::default(),
         }
     }
 }
@@ -40,7 +70,7 @@ impl<'call> Default for Bus<'call> {
 const RAM_START: u16 = 0x0000;
 const RAM_END: u16 = 0x1FFF;
 const PPU_REG_END: u16 = 0x3FFF;
-const PRG_START: u16 = 0x8000;
+const CARTRIDGE_START: u16 = 0x4020;
 const PRG_END: u16 = 0xFFFF;
 
 impl<'a> Bus<'a> {
@@ -52,54 +82,196 @@ impl<'a> Bus<'a> {
             let c = cartridge.clone().unwrap().clone();
             PPU::new(c.chr_rom, c.screen_mirroring)
         } else { PPU::new_empty_rom() };
+        let region = cartridge.as_ref().map_or(Region::NTSC, |c| c.region);
+        let mapper = cartridge.as_ref().map(MapperBoard::new_or_nrom);
 
-        Bus {
+        let mut bus = Bus {
             cpu_vram: [0; 2048],
-            cartridge,
+            mapper,
             ppu,
+            apu: APU::new(),
             cycles: 0,
+            region,
+            ram_init: RamInit::default(),
+            master_clock: 0,
+            ppu_clock: 0,
+            instruction_cycles: 0,
+            nmi_cycle: 0,
             gameloop_callback: Some(Box::from(gameloop_callback)),
             joypad1: Joypad::new(),
             keys_to_press: vec![],
             keys_to_release: vec![],
-        }
+            open_bus: 0,
+            cheats: Cheats::default(),
+        };
+        bus.set_region(region);
+        bus.attach_mapper();
+        bus
     }
 
     pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
-        self.cartridge = Some(cartridge.clone());
+        self.mapper = Some(MapperBoard::new_or_nrom(&cartridge));
         self.ppu = PPU::new(cartridge.chr_rom.clone(), cartridge.screen_mirroring);
+        self.set_region(cartridge.region);
+        self.attach_mapper();
+    }
+
+    // NSF tunes get the banking and RAM of an NSF player instead of a cartridge
+    pub fn insert_nsf(&mut self, nsf: &Nsf) {
+        self.mapper = Some(MapperBoard::new_nsf(nsf));
+        self.ppu = PPU::new_empty_rom();
+        self.set_region(nsf.region);
+        self.attach_mapper();
     }
 
-    fn read_prg_rom(&self, mut addr: u16) -> u8 {
-        addr -= 0x8000;
-        let cartridge = self.cartridge.as_ref();
-        if let Some(c) = cartridge {
-            if c.prg_rom.len() == 0x4000 && addr >= 0x4000 {
-                //mirror if needed
-                addr = addr % 0x4000;
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.region = region;
+        self.apu.region = region;
+    }
+
+    pub fn ram_init(&self) -> RamInit {
+        self.ram_init
+    }
+
+    // applied to the internal RAM on the next power on
+    pub fn set_ram_init(&mut self, ram_init: RamInit) {
+        self.ram_init = ram_init;
+    }
+
+    // internal RAM, and the PRG RAM at $6000-$7FFF when the board has some
+    pub fn ram_addresses(&self) -> Vec<u16> {
+        let mut addresses: Vec<u16> = (RAM_START..0x0800).collect();
+        if self.mapper.as_ref().is_some_and(|board| board.mapper().prg_ram_mapped()) {
+            addresses.extend(0x6000..=0x7FFF);
+        }
+        addresses
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    // https://www.nesdev.org/wiki/Cycle_reference_chart
+    // PAL runs 3.2 PPU dots per CPU cycle, so the PPU catches up with the master clock dot by dot
+    fn clock_cpu_cycle(&mut self) {
+        self.cycles += 1;
+        self.master_clock += self.region.cpu_divider() as u64;
+
+        self.apu.tick();
+        if let Some(board) = self.mapper.as_mut() {
+            board.mapper_mut().tick();
+            self.apu.set_expansion_output(board.mapper().audio_output());
+        }
+        if let Some(addr) = self.apu.dmc_fetch_address() {
+            // the CPU is stalled while the DMC reads its sample byte
+            let value = self.read(addr);
+            self.apu.dmc_fill_sample_buffer(value);
+            for _ in 0..4 {
+                self.cycles += 1;
+                self.master_clock += self.region.cpu_divider() as u64;
+                self.apu.tick();
+                self.catch_up_ppu();
             }
-            c.prg_rom[addr as usize]
-        } else {
-            0
         }
+
+        self.catch_up_ppu();
     }
 
-    pub fn poll_nmi_status(&mut self) -> Option<u8> {
-        self.ppu.nmi_interrupt.take()
+    fn catch_up_ppu(&mut self) {
+        let ppu_divider = self.region.ppu_divider() as u64;
+        while self.ppu_clock + ppu_divider <= self.master_clock {
+            self.ppu_clock += ppu_divider;
+
+            let nmi_before = self.ppu.nmi_interrupt.is_some();
+            self.ppu.tick_with_mapper(1, self.mapper.as_mut().map(|board| board.mapper_mut()));
+            if let Some(board) = self.mapper.as_mut() {
+                board.mapper_mut().ppu_bus_access(self.ppu.bus_address());
+                self.sync_mapper();
+            }
+            if !nmi_before && self.ppu.nmi_interrupt.is_some() {
+                self.nmi_cycle = self.cycles;
+            }
+
+            if self.ppu.is_vblank_start() {
+                self.apply_freezes();
+                if let Some(ref mut cb) = self.gameloop_callback {
+                    cb(&mut self.ppu, &mut self.joypad1);
+                }
+                self.handle_key_events();
+            }
+        }
+    }
+
+    // RAM freezes are written once a frame, before the NMI handler runs
+    fn apply_freezes(&mut self) {
+        let freezes: Vec<(u16, u8)> = self.cheats.freezes().collect();
+        for (addr, value) in freezes {
+            self.write(addr, value);
+        }
     }
 
-    pub fn tick(&mut self, cycles: u8) {
-        self.cycles += cycles as usize;
+    // every CPU memory access takes one CPU cycle, so the rest of the system is clocked before
+    // the access happens and register reads and writes land on the right PPU dot
+    fn clock_access(&mut self) {
+        self.clock_cpu_cycle();
+        self.instruction_cycles = self.instruction_cycles.saturating_add(1);
+    }
 
-        let nmi_before = self.ppu.nmi_interrupt.is_some();
-        self.ppu.tick(cycles * 3);
-        let nmi_after = self.ppu.nmi_interrupt.is_some();
+    fn attach_mapper(&mut self) {
+        if self.mapper.as_ref().is_some_and(|board| board.mapper().needs_dot_rendering()) {
+            self.ppu.set_dot_rendering(true);
+        }
+        self.sync_mapper();
+    }
 
-        if !nmi_before && nmi_after {
-            if let Some(ref mut cb) = self.gameloop_callback {
-                cb(&mut self.ppu, &mut self.joypad1);
+    // the PPU sees the CHR banks and mirroring the mapper last selected
+    fn sync_mapper(&mut self) {
+        if let Some(board) = &self.mapper {
+            let mapper = board.mapper();
+            self.ppu.set_chr_banks(mapper.chr_banks());
+            if let Some(mirroring) = mapper.mirroring() {
+                self.ppu.mirroring = mirroring;
             }
-            self.handle_key_events();
+        }
+    }
+
+    // Famicom Disk System sides, 0 for cartridges
+    pub fn disk_side_count(&self) -> usize {
+        match &self.mapper {
+            Some(MapperBoard::FDS(fds)) => fds.side_count(),
+            _ => 0,
+        }
+    }
+
+    pub fn disk_side(&self) -> Option<usize> {
+        match &self.mapper {
+            Some(MapperBoard::FDS(fds)) => fds.disk_side(),
+            _ => None,
+        }
+    }
+
+    pub fn insert_disk(&mut self, side: usize) {
+        if let Some(MapperBoard::FDS(fds)) = &mut self.mapper {
+            fds.insert(side);
+        }
+    }
+
+    pub fn eject_disk(&mut self) {
+        if let Some(MapperBoard::FDS(fds)) = &mut self.mapper {
+            fds.eject();
+        }
+    }
+
+    // every disk side in the .fds layout, once after the BIOS finished writing to the disk
+    pub fn take_written_disk(&mut self) -> Option<Vec<Vec<u8>>> {
+        match &mut self.mapper {
+            Some(MapperBoard::FDS(fds)) => fds.take_written_sides(),
+            _ => None,
         }
     }
 
@@ -125,55 +297,90 @@ impl<'a> Bus<'a> {
     }
 }
 
-impl<'a> Memory for Bus<'a> {
-    fn mem_read(&mut self, addr: u16) -> u8 {
-        match addr {
+// https://www.nesdev.org/wiki/Open_bus_behavior
+impl<'a> Bus<'a> {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = match addr {
             RAM_START..=RAM_END => {
                 let mirror_down_addr = addr & 0b00000111_11111111;
                 self.cpu_vram[mirror_down_addr as usize]
             }
-            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 | 0x4014 => {
-                // panic!("Attempt to read from write-only PPU address {:x}", addr);
-                0
-            }
+            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => self.ppu.read_io_latch(),
             0x2002 => self.ppu.read_status(),
             0x2004 => self.ppu.read_oam_data(),
             0x2007 => self.ppu.read_data(),
             0x2008..=PPU_REG_END => {
                 let mirror_down_addr = addr & 0b00100000_00000111;
-                self.mem_read(mirror_down_addr)
+                self.read(mirror_down_addr)
+            }
+            0x4015 => {
+                // internal to the CPU, so the data bus keeps its value and only drives bit 5
+                return (self.open_bus & 0b0010_0000) | (self.apu.read_status() & 0b1101_1111);
             }
             0x4016 => {
-                self.joypad1.read()
+                // only the low bits are driven by the controller port
+                (self.open_bus & 0b1110_0000) | self.joypad1.read()
             }
 
             0x4017 => {
                 // ignore joypad 2
-                0
+                self.open_bus & 0b1110_0000
             }
-            PRG_START..=PRG_END => {
-                self.read_prg_rom(addr)
+            CARTRIDGE_START..=PRG_END => {
+                let data = self.mapper.as_mut().and_then(|board| board.mapper_mut().read(addr));
+                self.sync_mapper();
+                data.map_or(self.open_bus, |value| self.cheats.read(addr, value))
             }
             _ => {
                 // println!("Ignoring mem access at {:#x}", addr);
-                0
+                self.open_bus
             }
+        };
+        self.open_bus = data;
+        data
+    }
+
+    fn peek(&mut self, addr: u16) -> u8 {
+        match addr {
+            RAM_START..=RAM_END => self.cpu_vram[(addr & 0b00000111_11111111) as usize],
+            0x2000..=PPU_REG_END => self.ppu.peek_register(addr & 0b00100000_00000111),
+            0x4015 => self.apu.peek_status(),
+            CARTRIDGE_START..=PRG_END => {
+                let data = self.mapper.as_ref().and_then(|board| board.mapper().peek(addr));
+                data.map_or(0, |value| self.cheats.read(addr, value))
+            }
+            // nothing holds a value there, it only exists while the bus is driven
+            _ => 0,
         }
     }
 
-    fn mem_write(&mut self, addr: u16, data: u8) {
+    fn write(&mut self, addr: u16, data: u8) {
+        self.open_bus = data;
+        if (0x2000..=0x2007).contains(&addr) {
+            self.ppu.write_io_latch(data);
+            if let Some(board) = self.mapper.as_mut() {
+                board.mapper_mut().ppu_register_write(addr, data);
+            }
+        }
+
         match addr {
             RAM_START..=RAM_END => {
                 let mirror_down_addr = addr & 0b11111111111;
                 self.cpu_vram[mirror_down_addr as usize] = data;
             }
             0x2000 => {
+                let nmi_before = self.ppu.nmi_interrupt.is_some();
                 self.ppu.write_to_ctrl(data);
+                if !nmi_before && self.ppu.nmi_interrupt.is_some() {
+                    self.nmi_cycle = self.cycles;
+                }
             }
             0x2001 => {
                 self.ppu.write_to_mask(data);
             }
-            0x2002 => panic!("attempt to write to PPU status register"),
+            0x2002 => {
+                // read-only, the write only reaches the io latch
+            }
 
             0x2003 => {
                 self.ppu.write_to_oam_addr(data);
@@ -193,32 +400,99 @@ impl<'a> Memory for Bus<'a> {
             }
             0x2008..=PPU_REG_END => {
                 let mirror_down_addr = addr & 0b00100000_00000111;
-                self.mem_write(mirror_down_addr, data);
+                self.write(mirror_down_addr, data);
+            }
+            0x4000..=0x4013 | 0x4015 | 0x4017 => {
+                // $4017 also belongs to joypad 2 on reads, which is ignored
+                self.apu.write_register(addr, data);
             }
             // https://wiki.nesdev.com/w/index.php/PPU_programmer_reference#OAM_DMA_.28.244014.29_.3E_write
             0x4014 => {
-                let mut buffer: [u8; 256] = [0; 256];
+                // the CPU halts for one cycle, plus one more to align on an odd cycle,
+                // then alternates between reading and writing 256 bytes
+                self.clock_cpu_cycle();
+                if self.cycles % 2 == 1 {
+                    self.clock_cpu_cycle();
+                }
+
                 let hi: u16 = (data as u16) << 8;
                 for i in 0..256u16 {
-                    buffer[i as usize] = self.mem_read(hi + i);
+                    self.clock_cpu_cycle();
+                    let value = self.read(hi + i);
+                    self.clock_cpu_cycle();
+                    self.ppu.write_to_oam_data(value);
                 }
-
-                self.ppu.write_oam_dma(&buffer);
-
-                // todo: handle this eventually
-                // let add_cycles: u16 = if self.cycles % 2 == 1 { 514 } else { 513 };
-                // self.tick(add_cycles); //todo this will cause weird effects as PPU will have 513/514 * 3 ticks
             }
             0x4016 => {
                 self.joypad1.write(data);
             }
-            0x4017 => {
-                // ignore joypad 2
+            CARTRIDGE_START..=PRG_END => {
+                if let Some(board) = self.mapper.as_mut() {
+                    board.mapper_mut().write(addr, data);
+                    self.sync_mapper();
+                }
             }
-            0x8000..=0xFFFF => panic!("Attempt to write to Cartridge ROM space: {:x}", addr),
             _ => {
                 // println!("Ignoring mem write-access at {:x}", addr);
             }
         }
     }
 }
+
+impl<'a> Memory for Bus<'a> {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.clock_access();
+        self.read(addr)
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.clock_access();
+        self.write(addr, data);
+    }
+
+    fn mem_peek(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+}
+
+impl<'a> CpuBus for Bus<'a> {
+    // https://www.nesdev.org/wiki/CPU_interrupts#Detailed_interrupt_behavior
+    // interrupts are polled before the last cycle of an instruction, an NMI raised on that cycle
+    // is only taken after the next instruction
+    fn poll_nmi_status(&mut self) -> Option<u8> {
+        if self.nmi_cycle == self.cycles {
+            return None;
+        }
+        self.ppu.nmi_interrupt.take()
+    }
+
+    // catches up the cycles of an instruction or interrupt that didn't access memory
+    fn tick(&mut self, cycles: u8) {
+        for _ in self.instruction_cycles..cycles {
+            self.clock_cpu_cycle();
+        }
+        self.instruction_cycles = 0;
+    }
+
+    fn poll_irq_status(&mut self) -> bool {
+        self.apu.irq_pending() || self.mapper.as_ref().is_some_and(|board| board.mapper().irq_pending())
+    }
+
+    // https://www.nesdev.org/wiki/CPU_power_up_state
+    fn power_on(&mut self) {
+        self.ram_init.fill(&mut self.cpu_vram);
+        self.ppu.power_on();
+        self.apu.power_on();
+        self.cycles = 0;
+        self.master_clock = 0;
+        self.ppu_clock = 0;
+        self.instruction_cycles = 0;
+        self.nmi_cycle = 0;
+        self.open_bus = 0;
+    }
+
+    fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+    }
+}
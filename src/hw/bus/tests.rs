@@ -1,7 +1,10 @@
 #[cfg(test)]
 mod tests {
     use crate::hw::bus::Bus;
-    use crate::hw::memory::Memory;
+    use crate::hw::joypad::JoypadButton;
+    use crate::hw::memory::{CpuBus, Memory};
+    use crate::hw::ram_init::RamInit;
+    use crate::hw::region::Region;
 
     #[test]
     fn test_bus_new() {
@@ -112,7 +115,6 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn test_unmapped_memory_write() {
         let mut bus = Bus::new(None, move |_, _| {});
 
@@ -120,9 +122,18 @@ mod tests {
         bus.mem_write(0x8000, 0x55);
         bus.mem_write(0xFFFF, 0x99);
 
-        assert_eq!(bus.mem_read(0x4000), 0);
-        assert_eq!(bus.mem_read(0x8000), 0);
-        assert_eq!(bus.mem_read(0xFFFF), 0);
+        assert_eq!(bus.mem_peek(0x4000), 0);
+        assert_eq!(bus.mem_peek(0x8000), 0);
+        assert_eq!(bus.mem_peek(0xFFFF), 0);
+    }
+
+    #[test]
+    fn test_ppu_status_write_ignored() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        let status = bus.ppu.status_register.snapshot();
+
+        bus.mem_write(0x2002, 0xFF);
+        assert_eq!(bus.ppu.status_register.snapshot(), status);
     }
 
     #[test]
@@ -157,4 +168,247 @@ mod tests {
         assert_eq!(bus.mem_read(0x0000), 0x42);
         assert_eq!(bus.mem_read(0x0800), 0x42);
     }
-}
\ No newline at end of file
+
+    fn cpu_cycles_until_vblank(region: Region) -> usize {
+        let mut bus = Bus::new(None, move |_, _| {});
+        bus.set_region(region);
+        let mut cycles = 0;
+        while !bus.ppu.status_register.is_in_vblank() {
+            bus.tick(1);
+            cycles += 1;
+        }
+        cycles
+    }
+
+    #[test]
+    fn test_vblank_timing_per_region() {
+        // vblank starts on dot 1 of scanline 241, 3 dots per CPU cycle
+        assert_eq!(cpu_cycles_until_vblank(Region::NTSC), 27394);
+        // same scanline at 3.2 dots per CPU cycle
+        assert_eq!(cpu_cycles_until_vblank(Region::PAL), 25682);
+        // vblank starts at scanline 291 on Dendy
+        assert_eq!(cpu_cycles_until_vblank(Region::Dendy), 33078);
+    }
+
+    #[test]
+    fn test_pal_fractional_dots() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        bus.set_region(Region::PAL);
+
+        bus.tick(1);
+        assert_eq!(bus.master_clock - bus.ppu_clock, 1);
+        bus.tick(4);
+        assert_eq!(bus.master_clock - bus.ppu_clock, 0);
+    }
+
+    #[test]
+    fn test_open_bus_unmapped_read() {
+        let mut bus = Bus::new(None, move |_, _| {});
+
+        bus.mem_write(0x0010, 0x5A);
+        bus.mem_read(0x0010);
+        assert_eq!(bus.mem_read(0x5000), 0x5A);
+        assert_eq!(bus.mem_read(0x8000), 0x5A);
+
+        bus.mem_write(0x0010, 0xC3);
+        assert_eq!(bus.mem_read(0x4018), 0xC3);
+    }
+
+    #[test]
+    fn test_open_bus_controller_port() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        bus.joypad1.set_button_pressed_status(&JoypadButton::BUTTON_A, true);
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+
+        // LDA $4016 leaves the high byte of the operand on the bus
+        bus.mem_write(0x0000, 0x40);
+        bus.mem_read(0x0000);
+        assert_eq!(bus.mem_read(0x4016), 0x41);
+        assert_eq!(bus.mem_read(0x4016), 0x40);
+        assert_eq!(bus.mem_read(0x4017), 0x40);
+    }
+
+    #[test]
+    fn test_open_bus_apu_status_keeps_bus() {
+        let mut bus = Bus::new(None, move |_, _| {});
+
+        bus.mem_write(0x0000, 0xFF);
+        bus.mem_read(0x0000);
+        assert_eq!(bus.mem_read(0x4015), 0b0010_0000);
+        assert_eq!(bus.mem_read(0x5000), 0xFF);
+    }
+
+    #[test]
+    fn test_ppu_write_only_registers_return_io_latch() {
+        let mut bus = Bus::new(None, move |_, _| {});
+
+        bus.mem_write(0x2000, 0x00);
+        bus.mem_write(0x2003, 0x9E);
+        assert_eq!(bus.mem_read(0x2000), 0x9E);
+        assert_eq!(bus.mem_read(0x2005), 0x9E);
+        assert_eq!(bus.mem_read(0x3FF6), 0x9E);
+        assert_eq!(bus.mem_read(0x2002) & 0b0001_1111, 0x1E);
+    }
+
+    #[test]
+    fn test_peek_has_no_side_effects() {
+        let mut bus = Bus::new(None, move |_, _| {});
+
+        bus.mem_write(0x0000, 0x12);
+        bus.ppu.status_register.set_vblank_status(true);
+
+        assert_eq!(bus.mem_peek(0x0000), 0x12);
+        assert_eq!(bus.mem_peek(0x2002) & 0b1000_0000, 0b1000_0000);
+        assert!(bus.ppu.status_register.is_in_vblank());
+        assert_eq!(bus.mem_peek(0x4016), 0);
+        assert_eq!(bus.open_bus, 0x12);
+    }
+
+    #[test]
+    fn test_memory_access_clocks_one_cpu_cycle() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        bus.mem_read(0x0000);
+        bus.mem_write(0x0000, 0x01);
+        assert_eq!(bus.cycles(), 2);
+        assert_eq!(bus.ppu_clock, 2 * 12);
+
+        // peeking is free
+        bus.mem_peek(0x0000);
+        assert_eq!(bus.cycles(), 2);
+    }
+
+    #[test]
+    fn test_tick_catches_up_remaining_instruction_cycles() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        bus.mem_read(0x0000);
+        bus.mem_read(0x0001);
+        bus.tick(4);
+        assert_eq!(bus.cycles(), 4);
+
+        bus.tick(2);
+        assert_eq!(bus.cycles(), 6);
+    }
+
+    #[test]
+    fn test_status_read_sees_vblank_on_the_access_cycle() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        for _ in 0..cpu_cycles_until_vblank(Region::NTSC) - 1 {
+            bus.tick(1);
+        }
+        assert!(!bus.ppu.status_register.is_in_vblank());
+
+        assert_eq!(bus.mem_read(0x2002) & 0b1000_0000, 0b1000_0000);
+    }
+
+    #[test]
+    fn test_oam_dma_stalls_cpu() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        for i in 0..256u16 {
+            bus.mem_write(0x0200 + i, i as u8);
+        }
+        let cycles = bus.cycles();
+
+        bus.mem_write(0x4014, 0x02);
+        let dma_cycles = bus.cycles() - cycles - 1;
+        assert!(dma_cycles == 513 || dma_cycles == 514);
+        assert_eq!(bus.ppu.oam_data[0x10], 0x10);
+        assert_eq!(bus.ppu.oam_data[0xFF], 0xFF);
+    }
+
+    #[test]
+    fn test_apu_status_register() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        bus.mem_write(0x4015, 0b0000_0001);
+        bus.mem_write(0x4003, 0b0000_1000);
+        assert_eq!(bus.mem_read(0x4015) & 0b0001_1111, 0b0000_0001);
+
+        bus.mem_write(0x4015, 0);
+        assert_eq!(bus.mem_read(0x4015) & 0b0001_1111, 0);
+    }
+
+    #[test]
+    fn test_apu_frame_irq() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        let mut cycles = 0;
+        while !bus.poll_irq_status() {
+            bus.tick(1);
+            cycles += 1;
+        }
+        assert_eq!(cycles, 29829);
+
+        // reading the status acknowledges the interrupt
+        assert_eq!(bus.mem_read(0x4015) & 0b0100_0000, 0b0100_0000);
+        assert!(!bus.poll_irq_status());
+    }
+
+    #[test]
+    fn test_nmi_enabled_in_vblank_waits_one_instruction() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        bus.ppu.status_register.set_vblank_status(true);
+
+        // the write is the last cycle of STA $2000
+        bus.mem_read(0x0000);
+        bus.mem_read(0x0001);
+        bus.mem_read(0x0002);
+        bus.mem_write(0x2000, 0b1000_0000);
+        bus.tick(4);
+        assert_eq!(bus.poll_nmi_status(), None);
+
+        bus.mem_read(0x0000);
+        bus.tick(2);
+        assert_eq!(bus.poll_nmi_status(), Some(1));
+    }
+
+    #[test]
+    fn test_gameloop_runs_once_per_frame_without_nmi() {
+        let frames = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = frames.clone();
+        let mut bus = Bus::new(None, move |_, _| counter.set(counter.get() + 1));
+        for _ in 0..cpu_cycles_until_vblank(Region::NTSC) * 2 {
+            bus.tick(1);
+        }
+        assert_eq!(frames.get(), 1);
+    }
+
+    #[test]
+    fn test_power_on_ram_init() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        bus.set_ram_init(RamInit::FF);
+        bus.power_on();
+        assert!((0..0x800).all(|addr| bus.mem_peek(addr) == 0xFF));
+
+        bus.set_ram_init(RamInit::ConsolePattern);
+        bus.power_on();
+        assert_eq!(bus.mem_peek(0x0003), 0x00);
+        assert_eq!(bus.mem_peek(0x0004), 0xFF);
+        assert_eq!(bus.mem_peek(0x07FF), 0xFF);
+    }
+
+    #[test]
+    fn test_power_on_random_ram_is_seeded() {
+        let ram = |seed| {
+            let mut bus = Bus::new(None, move |_, _| {});
+            bus.set_ram_init(RamInit::Random { seed });
+            bus.power_on();
+            (0..0x800).map(|addr| bus.mem_peek(addr)).collect::<Vec<u8>>()
+        };
+        assert_eq!(ram(1), ram(1));
+        assert_ne!(ram(1), ram(2));
+        assert!(ram(1).iter().any(|&b| b != ram(1)[0]));
+    }
+
+    #[test]
+    fn test_reset_keeps_ram_and_clears_ppu_control() {
+        let mut bus = Bus::new(None, move |_, _| {});
+        bus.set_ram_init(RamInit::FF);
+        bus.mem_write(0x0010, 0x42);
+        bus.mem_write(0x2000, 0b1000_0000);
+        bus.mem_write(0x2001, 0b0001_1000);
+
+        bus.reset();
+        assert_eq!(bus.mem_peek(0x0010), 0x42);
+        assert!(!bus.ppu.controller_register.generate_vblank_nmi());
+        assert!(!bus.ppu.mask_register.show_background());
+    }
+}
@@ -1,13 +1,25 @@
+pub mod mapper;
+pub mod database;
+pub mod fds;
+pub mod hash;
+pub mod nsf;
+pub mod patch;
+pub mod unif;
 mod tests;
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use crate::hw::cartridge::database::{GameDatabase, GameInfo};
+use crate::hw::cartridge::mapper::MapperBoard;
+use crate::hw::region::Region;
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum ScreenMirroring {
     Horizontal,
     Vertical,
     FourScreen,
+    // CIRAM page of each nametable, for mappers that control the nametables one by one
+    Custom([u8; 4]),
 }
 
 #[derive(Error, Debug)]
@@ -17,10 +29,36 @@ pub enum CartridgeError {
         expected: Vec<u8>,
         found: Vec<u8>,
     },
-    #[error("NES2.0 format is not supported")]
+    #[error("Unsupported INES version")]
     UnsupportedINESVersion,
     #[error("Illegal screen mirroring found")]
     IllegalScreenMirroring,
+    #[error("ROM is too short (expected {expected} bytes, got {found})")]
+    TruncatedRom {
+        expected: usize,
+        found: usize,
+    },
+    #[error("Unsupported mapper {0}")]
+    UnsupportedMapper(u16),
+    #[error("Invalid FDS image ({0} bytes of disk data is not a whole number of sides)")]
+    InvalidDiskImage(usize),
+    #[error("UNIF file has no {0} chunk")]
+    MissingUnifChunk(&'static str),
+    #[error("UNIF chunk {0} runs past the end of the file")]
+    TruncatedUnifChunk(String),
+    #[error("Unknown UNIF board {0}")]
+    UnknownUnifBoard(String),
+    #[error("Invalid game database entry on line {0}")]
+    InvalidDatabaseEntry(usize),
+    #[error("NSF file is too short ({0} bytes)")]
+    TruncatedNsf(usize),
+    #[error("FDS images need the Disk System BIOS")]
+    MissingBios,
+    #[error("Invalid FDS BIOS (expected {expected} bytes, got {found})")]
+    InvalidBios {
+        expected: usize,
+        found: usize,
+    },
 }
 
 #[derive(Serialize, Deserialize)]
@@ -28,55 +66,192 @@ pub enum CartridgeError {
 pub struct Cartridge {
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
-    pub mapper: u8,
+    pub mapper: u16,
+    pub submapper: u8,
     pub screen_mirroring: ScreenMirroring,
+    pub region: Region,
+    // PRG RAM kept alive by a battery, for save games
+    pub battery: bool,
+    // Famicom Disk System sides in the .fds layout, empty for cartridges
+    pub disk_sides: Vec<Vec<u8>>,
+    // the game database entry matching the ROM hashes
+    pub game: Option<GameInfo>,
 }
 
 impl Cartridge {
-    const INES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+    pub(crate) const INES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+    const INES_VERSION_1: u8 = 0b0000_0000;
+    const INES_VERSION_2: u8 = 0b0000_1000;
     pub(crate) const PRG_ROM_PAGE_SIZE: usize = 16384;
     pub(crate) const CHR_ROM_PAGE_SIZE: usize = 8192;
     const HEADER_SIZE: usize = 16;
     const TRAINER_SIZE: usize = 512;
+    // the number emulators gave the Disk System before it had an image format of its own
+    pub(crate) const FDS_MAPPER: u16 = 20;
 
     pub fn new(raw: Vec<u8>) -> anyhow::Result<Self> {
-        if raw[0..4] != Self::INES_TAG {
+        if unif::is_unif(&raw) {
+            return Cartridge::from_unif(&raw);
+        }
+        if !raw.starts_with(&Self::INES_TAG) {
             return Err(CartridgeError::InvalidHeader
-            { expected: Self::INES_TAG.to_vec(), found: raw[0..4].to_vec() }.into());
+            { expected: Self::INES_TAG.to_vec(), found: raw[..raw.len().min(4)].to_vec() }.into());
+        }
+        if raw.len() < Self::HEADER_SIZE {
+            return Err(CartridgeError::TruncatedRom { expected: Self::HEADER_SIZE, found: raw.len() }.into());
         }
 
         let cb1 = raw[6];
         let cb2 = raw[7];
 
-        let mapper = (cb2 & 0b1111_0000) | (cb1 >> 4);
+        let mut mapper = ((cb2 & 0b1111_0000) | (cb1 >> 4)) as u16;
+        let mut submapper = 0;
+        let mut region = Region::NTSC;
+        let mut prg_rom_pages = raw[4] as usize;
+        let mut chr_rom_pages = raw[5] as usize;
+
         let ines_ver = cb2 & 0b0000_1100;
-        if ines_ver != 0 {
-            return Err(CartridgeError::UnsupportedINESVersion.into());
+        match ines_ver {
+            Self::INES_VERSION_1 => {}
+            // https://www.nesdev.org/wiki/NES_2.0
+            Self::INES_VERSION_2 => {
+                mapper |= ((raw[8] & 0b0000_1111) as u16) << 8;
+                submapper = raw[8] >> 4;
+                prg_rom_pages |= ((raw[9] & 0b0000_1111) as usize) << 8;
+                chr_rom_pages |= ((raw[9] >> 4) as usize) << 8;
+                region = Region::from_nes2_timing(raw[12]);
+            }
+            _ => return Err(CartridgeError::UnsupportedINESVersion.into()),
         }
 
         let vertical = cb1 & 0b0000_0001 != 0;
-        let horizontal = cb1 & 0b0000_0010 == 0;
         let four_screen = cb1 & 0b0000_1000 != 0;
+        // bit 1 is the battery, not part of the mirroring
+        let battery = cb1 & 0b0000_0010 != 0;
 
-        let mirroring = match (vertical, horizontal, four_screen) {
-            (true, _, false) => ScreenMirroring::Vertical,
-            (false, true, false) => ScreenMirroring::Horizontal,
-            (_, _, true) => ScreenMirroring::FourScreen,
-            _ => return Err(CartridgeError::IllegalScreenMirroring.into())
+        let mirroring = match (vertical, four_screen) {
+            (_, true) => ScreenMirroring::FourScreen,
+            (true, false) => ScreenMirroring::Vertical,
+            (false, false) => ScreenMirroring::Horizontal,
         };
 
-        let prg_rom_size = raw[4] as usize * Self::PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = raw[5] as usize * Self::CHR_ROM_PAGE_SIZE;
+        let prg_rom_size = prg_rom_pages * Self::PRG_ROM_PAGE_SIZE;
+        let chr_rom_size = chr_rom_pages * Self::CHR_ROM_PAGE_SIZE;
 
         let skip_trainer = cb1 & 0b0000_0100 == 0;
         let prg_rom_start = Self::HEADER_SIZE + if skip_trainer { 0 } else { Self::TRAINER_SIZE };
         let chr_rom_start = prg_rom_start + prg_rom_size;
+        if raw.len() < chr_rom_start + chr_rom_size {
+            return Err(CartridgeError::TruncatedRom { expected: chr_rom_start + chr_rom_size, found: raw.len() }.into());
+        }
 
-        Ok(Cartridge {
+        let mut cartridge = Cartridge {
             prg_rom: raw[prg_rom_start..prg_rom_start + prg_rom_size].to_vec(),
             chr_rom: raw[chr_rom_start..chr_rom_start + chr_rom_size].to_vec(),
             mapper,
+            submapper,
             screen_mirroring: mirroring,
+            region,
+            battery,
+            disk_sides: vec![],
+            game: None,
+        };
+        // NES 2.0 headers are trusted, the database only corrects the ones from iNES dumps
+        cartridge.identify(GameDatabase::bundled(), ines_ver == Self::INES_VERSION_1);
+        Ok(cartridge)
+    }
+
+    // looks the ROM up in the game database, and takes the mapper, mirroring, battery and region
+    // from the entry when the header can't be trusted
+    fn identify(&mut self, database: &GameDatabase, correct_header: bool) {
+        self.game = database.find(&self.prg_rom, &self.chr_rom).cloned();
+        let Some(game) = self.game.as_ref().filter(|_| correct_header) else {
+            return;
+        };
+        if (self.mapper, self.submapper) != (game.mapper, game.submapper) {
+            log::info!("{}: mapper {} from the game database, the header says {}", game.title, game.mapper, self.mapper);
+        }
+        self.mapper = game.mapper;
+        self.submapper = game.submapper;
+        if let Some(mirroring) = &game.mirroring {
+            self.screen_mirroring = mirroring.clone();
+        }
+        self.battery = game.battery;
+        self.region = game.region;
+    }
+
+    // chunks are assembled into the same PRG and CHR ROM an iNES file would have, the board name
+    // picks the mapper
+    pub fn from_unif(raw: &[u8]) -> anyhow::Result<Self> {
+        let mut board = None;
+        let mut prg_chunks: [&[u8]; 16] = [&[]; 16];
+        let mut chr_chunks: [&[u8]; 16] = [&[]; 16];
+        let mut mirroring = ScreenMirroring::Horizontal;
+        let mut battery = false;
+        let mut region = Region::NTSC;
+
+        for (id, data) in unif::chunks(raw)? {
+            if let Some(index) = unif::rom_chunk_index(&id, b"PRG") {
+                prg_chunks[index] = data;
+            } else if let Some(index) = unif::rom_chunk_index(&id, b"CHR") {
+                chr_chunks[index] = data;
+            }
+            match (&id, data.first()) {
+                (b"MAPR", _) => board = Some(unif::board_name(data)),
+                // 5 means the mapper controls the mirroring
+                (b"MIRR", Some(0 | 5)) => mirroring = ScreenMirroring::Horizontal,
+                (b"MIRR", Some(1)) => mirroring = ScreenMirroring::Vertical,
+                (b"MIRR", Some(2)) => mirroring = ScreenMirroring::Custom([0; 4]),
+                (b"MIRR", Some(3)) => mirroring = ScreenMirroring::Custom([1; 4]),
+                (b"MIRR", Some(4)) => mirroring = ScreenMirroring::FourScreen,
+                (b"MIRR", Some(_)) => return Err(CartridgeError::IllegalScreenMirroring.into()),
+                (b"BATR", Some(&value)) => battery = value != 0,
+                // 2 runs on both, like NES 2.0 multi-region cartridges
+                (b"TVCI", Some(1)) => region = Region::PAL,
+                _ => {}
+            }
+        }
+
+        let board = board.ok_or(CartridgeError::MissingUnifChunk("MAPR"))?;
+        let (mapper, submapper) = unif::board_mapper(&board).ok_or(CartridgeError::UnknownUnifBoard(board))?;
+        let prg_rom = prg_chunks.concat();
+        if prg_rom.is_empty() {
+            return Err(CartridgeError::MissingUnifChunk("PRG0").into());
+        }
+
+        let mut cartridge = Cartridge {
+            prg_rom,
+            chr_rom: chr_chunks.concat(),
+            mapper,
+            submapper,
+            screen_mirroring: mirroring,
+            region,
+            battery,
+            disk_sides: vec![],
+            game: None,
+        };
+        // the board name says exactly which board is needed, so unlike an iNES mapper number it
+        // doesn't fall back to NROM
+        MapperBoard::new(&cartridge)?;
+        cartridge.identify(GameDatabase::bundled(), false);
+        Ok(cartridge)
+    }
+
+    // the BIOS takes the place of PRG ROM, pattern tables are in CHR RAM
+    pub fn from_fds(raw: Vec<u8>, bios: Vec<u8>) -> anyhow::Result<Self> {
+        if bios.len() != fds::BIOS_SIZE {
+            return Err(CartridgeError::InvalidBios { expected: fds::BIOS_SIZE, found: bios.len() }.into());
+        }
+        Ok(Cartridge {
+            prg_rom: bios,
+            chr_rom: vec![],
+            mapper: Self::FDS_MAPPER,
+            submapper: 0,
+            screen_mirroring: ScreenMirroring::Horizontal,
+            region: Region::NTSC,
+            battery: false,
+            disk_sides: fds::disk_sides(&raw)?,
+            game: None,
         })
     }
 }
\ No newline at end of file
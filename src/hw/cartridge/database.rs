@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::hash::{crc32, sha1};
+use crate::hw::cartridge::{CartridgeError, ScreenMirroring};
+use crate::hw::region::Region;
+
+// one game per line: crc32;sha1;mapper;submapper;mirroring;battery;region;board;title
+// the hashes cover PRG ROM followed by CHR ROM, like the No-Intro headerless hashes. a sha1 of -
+// matches on the crc32 alone, a mirroring of - leaves it to the header since the mapper controls it
+const BUNDLED: &str = include_str!("gamedb.txt");
+const FIELDS: usize = 9;
+
+lazy_static::lazy_static! {
+    static ref BUNDLED_DATABASE: GameDatabase = GameDatabase::parse(BUNDLED).expect("bundled game database is valid");
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GameInfo {
+    pub title: String,
+    pub board: String,
+    pub region: Region,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Option<ScreenMirroring>,
+    pub battery: bool,
+}
+
+// the sha1 that has to match as well, when there's one
+type Entry = (Option<[u8; 20]>, GameInfo);
+
+#[derive(Debug, Default)]
+pub struct GameDatabase {
+    games: HashMap<u32, Vec<Entry>>,
+}
+
+impl GameDatabase {
+    pub fn bundled() -> &'static GameDatabase {
+        &BUNDLED_DATABASE
+    }
+
+    pub fn parse(text: &str) -> Result<Self, CartridgeError> {
+        let mut database = GameDatabase::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (crc, entry) = Self::parse_line(line).ok_or(CartridgeError::InvalidDatabaseEntry(number + 1))?;
+            database.games.entry(crc).or_default().push(entry);
+        }
+        Ok(database)
+    }
+
+    fn parse_line(line: &str) -> Option<(u32, Entry)> {
+        let fields: Vec<&str> = line.splitn(FIELDS, ';').map(str::trim).collect();
+        if fields.len() != FIELDS {
+            return None;
+        }
+        let crc = u32::from_str_radix(fields[0], 16).ok()?;
+        let sha = match fields[1] {
+            "-" => None,
+            hex if hex.len() == 40 => {
+                let mut sha = [0; 20];
+                for (i, byte) in sha.iter_mut().enumerate() {
+                    *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+                }
+                Some(sha)
+            }
+            _ => return None,
+        };
+        let mirroring = match fields[4] {
+            "H" => Some(ScreenMirroring::Horizontal),
+            "V" => Some(ScreenMirroring::Vertical),
+            "4" => Some(ScreenMirroring::FourScreen),
+            "-" => None,
+            _ => return None,
+        };
+        let region = match fields[6] {
+            "NTSC" => Region::NTSC,
+            "PAL" => Region::PAL,
+            "Dendy" => Region::Dendy,
+            _ => return None,
+        };
+        Some((crc, (sha, GameInfo {
+            title: fields[8].to_string(),
+            board: fields[7].to_string(),
+            region,
+            mapper: fields[2].parse().ok()?,
+            submapper: fields[3].parse().ok()?,
+            mirroring,
+            battery: match fields[5] { "0" => false, "1" => true, _ => return None },
+        })))
+    }
+
+    pub fn find(&self, prg_rom: &[u8], chr_rom: &[u8]) -> Option<&GameInfo> {
+        let rom = [prg_rom, chr_rom].concat();
+        let candidates = self.games.get(&crc32(&rom))?;
+        // only hash again when an entry asks for it
+        let sha = candidates.iter().any(|(sha, _)| sha.is_some()).then(|| sha1(&rom));
+        candidates.iter()
+            .find(|(expected, _)| expected.is_none() || *expected == sha)
+            .map(|(_, game)| game)
+    }
+}
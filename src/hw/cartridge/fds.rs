@@ -0,0 +1,99 @@
+use crate::hw::cartridge::CartridgeError;
+
+// https://www.nesdev.org/wiki/FDS_file_format
+const FDS_TAG: [u8; 4] = [0x46, 0x44, 0x53, 0x1A];
+const HEADER_SIZE: usize = 16;
+pub const SIDE_SIZE: usize = 65500;
+pub const BIOS_SIZE: usize = 0x2000;
+// every side starts with the disk info block, its block code and verification string
+const DISK_INFO: &[u8] = b"\x01*NINTENDO-HVC*";
+
+// https://www.nesdev.org/wiki/FDS_disk_format
+// the drive sees a gap before the first block and between blocks, and a start mark and CRC around each
+// block, none of which are stored in .fds images
+const LEAD_IN_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+const START_MARK: u8 = 0x80;
+const CRC_SIZE: usize = 2;
+const FILE_SIZE_OFFSET: usize = 13;
+
+pub fn is_fds_image(raw: &[u8]) -> bool {
+    raw.starts_with(&FDS_TAG)
+}
+
+// images without the fwNES header start right with the first side
+pub fn is_headerless_image(raw: &[u8]) -> bool {
+    raw.starts_with(DISK_INFO)
+}
+
+// disk sides as stored in the image, the fwNES header is optional
+pub fn disk_sides(raw: &[u8]) -> Result<Vec<Vec<u8>>, CartridgeError> {
+    let data = if is_fds_image(raw) { &raw[HEADER_SIZE.min(raw.len())..] } else { raw };
+    if data.is_empty() || data.len() % SIDE_SIZE != 0 {
+        return Err(CartridgeError::InvalidDiskImage(data.len()));
+    }
+    Ok(data.chunks(SIDE_SIZE).map(|side| side.to_vec()).collect())
+}
+
+// the original image with its sides replaced, keeping the header if it had one
+pub fn rebuild_image(original: &[u8], sides: &[Vec<u8>]) -> Vec<u8> {
+    let mut image = if is_fds_image(original) { original[..HEADER_SIZE].to_vec() } else { vec![] };
+    for side in sides {
+        image.extend_from_slice(side);
+    }
+    image
+}
+
+fn block_size(block: &[u8], file_size: usize) -> Option<usize> {
+    match block.first()? {
+        // disk info, file amount, file header and file data
+        1 => Some(56),
+        2 => Some(2),
+        3 => Some(16),
+        4 => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+// lays a side out the way the drive reads it
+pub fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; LEAD_IN_GAP];
+    let mut position = 0;
+    let mut file_size = 0;
+    while let Some(size) = block_size(&side[position..], file_size) {
+        let Some(block) = side.get(position..position + size) else { break };
+        if block[0] == 3 {
+            file_size = u16::from_le_bytes([block[FILE_SIZE_OFFSET], block[FILE_SIZE_OFFSET + 1]]) as usize;
+        }
+        raw.push(START_MARK);
+        raw.extend_from_slice(block);
+        // the drive doesn't report CRC errors, so the CRC bytes are left empty
+        raw.extend_from_slice(&[0; CRC_SIZE]);
+        raw.extend(std::iter::repeat_n(0, BLOCK_GAP));
+        position += size;
+    }
+    raw.resize(raw.len().max(SIDE_SIZE + LEAD_IN_GAP), 0);
+    raw
+}
+
+// the blocks of a side the drive has read or written, back in the image layout
+pub fn remove_gaps(raw: &[u8]) -> Vec<u8> {
+    let mut side = Vec::with_capacity(SIDE_SIZE);
+    let mut position = 0;
+    let mut file_size = 0;
+    while position < raw.len() {
+        if raw[position] != START_MARK {
+            position += 1;
+            continue;
+        }
+        let Some(size) = block_size(&raw[position + 1..], file_size) else { break };
+        let Some(block) = raw.get(position + 1..position + 1 + size) else { break };
+        if block[0] == 3 {
+            file_size = u16::from_le_bytes([block[FILE_SIZE_OFFSET], block[FILE_SIZE_OFFSET + 1]]) as usize;
+        }
+        side.extend_from_slice(block);
+        position += 1 + size + CRC_SIZE;
+    }
+    side.resize(SIDE_SIZE, 0);
+    side
+}
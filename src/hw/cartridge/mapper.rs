@@ -0,0 +1,166 @@
+mod nrom;
+mod discrete;
+mod mmc2;
+mod mmc5;
+mod fds;
+mod fds_audio;
+mod fme7;
+mod namco163;
+mod nsf;
+mod vrc4;
+mod vrc6;
+mod vrc_irq;
+
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::{Cartridge, CartridgeError, ScreenMirroring};
+use crate::hw::cartridge::mapper::discrete::{Discrete, DiscreteBoard};
+use crate::hw::cartridge::mapper::fds::Fds;
+use crate::hw::cartridge::mapper::fme7::Fme7;
+use crate::hw::cartridge::mapper::mmc2::Mmc2;
+use crate::hw::cartridge::mapper::mmc5::Mmc5;
+use crate::hw::cartridge::mapper::namco163::Namco163;
+use crate::hw::cartridge::mapper::nrom::Nrom;
+use crate::hw::cartridge::mapper::nsf::NsfBoard;
+use crate::hw::cartridge::mapper::vrc4::Vrc4;
+use crate::hw::cartridge::mapper::vrc6::Vrc6;
+use crate::hw::cartridge::nsf::Nsf;
+
+// 1 KiB windows of the pattern tables at $0000-$1FFF
+pub const CHR_WINDOWS: usize = 8;
+pub const CHR_WINDOW_SIZE: usize = 0x400;
+// an APU pulse at full volume, expansion audio levels are set relative to it
+pub const APU_PULSE_LEVEL: f32 = 0.149;
+
+// https://www.nesdev.org/wiki/Mapper
+pub trait Mapper {
+    // $4020-$FFFF, None leaves the value on the open bus
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        self.peek(addr)
+    }
+    // what a read would return, without side effects
+    fn peek(&self, addr: u16) -> Option<u8>;
+    // PRG RAM sits at $6000-$7FFF, rather than ROM, registers or nothing
+    fn prg_ram_mapped(&self) -> bool {
+        false
+    }
+    fn write(&mut self, addr: u16, value: u8);
+    // offset into CHR memory of each 1 KiB window of the pattern tables
+    fn chr_banks(&self) -> [usize; CHR_WINDOWS] {
+        std::array::from_fn(|window| window * CHR_WINDOW_SIZE)
+    }
+    // None keeps the mirroring set by the header
+    fn mirroring(&self) -> Option<ScreenMirroring> {
+        None
+    }
+    // every address the PPU puts on its bus, for CHR latches and scanline counters
+    fn ppu_bus_access(&mut self, _addr: u16) {}
+    // rendering fetches, Some replaces what the PPU would read from CHR or its nametables
+    fn ppu_read(&mut self, _addr: u16) -> Option<u8> {
+        None
+    }
+    // writes to $2000-$2007, for boards that snoop the PPU registers
+    fn ppu_register_write(&mut self, _addr: u16, _value: u8) {}
+    // one CPU cycle
+    fn tick(&mut self) {}
+    fn irq_pending(&self) -> bool {
+        false
+    }
+    // expansion audio, mixed into the APU output
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+    // boards that switch CHR banks while a frame is drawn can't use the whole-frame renderer, nor
+    // can boards with a scanline or cycle IRQ, which games use for status bars and other mid-frame
+    // scroll changes
+    fn needs_dot_rendering(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum MapperBoard {
+    NROM(Nrom),
+    MMC2(Mmc2),
+    MMC5(Mmc5),
+    // VRC2 and VRC4
+    VRC4(Vrc4),
+    VRC6(Vrc6),
+    // FME-7 and Sunsoft 5B
+    FME7(Fme7),
+    NAMCO163(Namco163),
+    // the Famicom Disk System RAM adapter
+    FDS(Fds),
+    // the banking and RAM an NSF player provides
+    NSF(NsfBoard),
+    // GxROM, Color Dreams, BNROM, NINA-001, Camerica and NINA-03/06
+    Discrete(Discrete),
+}
+
+impl MapperBoard {
+    pub fn new(cartridge: &Cartridge) -> Result<Self, CartridgeError> {
+        let prg_rom = cartridge.prg_rom.clone();
+        Ok(match cartridge.mapper {
+            0 => MapperBoard::NROM(Nrom::new(prg_rom)),
+            5 => MapperBoard::MMC5(Mmc5::new(prg_rom, cartridge.chr_rom.clone())),
+            9 => MapperBoard::MMC2(Mmc2::new(prg_rom, false)),
+            10 => MapperBoard::MMC2(Mmc2::new(prg_rom, true)),
+            11 => MapperBoard::Discrete(Discrete::new(prg_rom, DiscreteBoard::ColorDreams)),
+            19 => MapperBoard::NAMCO163(Namco163::new(prg_rom, cartridge.chr_rom.clone())),
+            20 => MapperBoard::FDS(Fds::new(prg_rom, &cartridge.disk_sides)),
+            21 | 22 | 23 | 25 => MapperBoard::VRC4(Vrc4::new(prg_rom, cartridge.mapper, cartridge.submapper)),
+            24 => MapperBoard::VRC6(Vrc6::new(prg_rom, false)),
+            26 => MapperBoard::VRC6(Vrc6::new(prg_rom, true)),
+            34 => MapperBoard::Discrete(Discrete::mapper_34(prg_rom, cartridge.submapper, cartridge.chr_rom.len())),
+            66 => MapperBoard::Discrete(Discrete::new(prg_rom, DiscreteBoard::GXROM)),
+            69 => MapperBoard::FME7(Fme7::new(prg_rom)),
+            71 => {
+                let mirroring_control = cartridge.submapper == 1;
+                MapperBoard::Discrete(Discrete::new(prg_rom, DiscreteBoard::Camerica { mirroring_control }))
+            }
+            79 => MapperBoard::Discrete(Discrete::new(prg_rom, DiscreteBoard::NINA03)),
+            mapper => return Err(CartridgeError::UnsupportedMapper(mapper)),
+        })
+    }
+
+    pub fn new_nsf(nsf: &Nsf) -> Self {
+        MapperBoard::NSF(NsfBoard::new(nsf))
+    }
+
+    // boards that aren't emulated yet run as NROM, which is enough for some of them to boot
+    pub fn new_or_nrom(cartridge: &Cartridge) -> Self {
+        MapperBoard::new(cartridge).unwrap_or_else(|err| {
+            log::warn!("{}, running the cartridge as NROM", err);
+            MapperBoard::NROM(Nrom::new(cartridge.prg_rom.clone()))
+        })
+    }
+
+    pub fn mapper(&self) -> &dyn Mapper {
+        match self {
+            MapperBoard::NROM(board) => board,
+            MapperBoard::MMC2(board) => board,
+            MapperBoard::MMC5(board) => board,
+            MapperBoard::VRC4(board) => board,
+            MapperBoard::VRC6(board) => board,
+            MapperBoard::FME7(board) => board,
+            MapperBoard::NAMCO163(board) => board,
+            MapperBoard::FDS(board) => board,
+            MapperBoard::NSF(board) => board,
+            MapperBoard::Discrete(board) => board,
+        }
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut dyn Mapper {
+        match self {
+            MapperBoard::NROM(board) => board,
+            MapperBoard::MMC2(board) => board,
+            MapperBoard::MMC5(board) => board,
+            MapperBoard::VRC4(board) => board,
+            MapperBoard::VRC6(board) => board,
+            MapperBoard::FME7(board) => board,
+            MapperBoard::NAMCO163(board) => board,
+            MapperBoard::FDS(board) => board,
+            MapperBoard::NSF(board) => board,
+            MapperBoard::Discrete(board) => board,
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::{Mapper, CHR_WINDOWS, CHR_WINDOW_SIZE};
+use crate::hw::cartridge::ScreenMirroring;
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x1000;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum DiscreteBoard {
+    // https://www.nesdev.org/wiki/GxROM
+    GXROM,
+    // https://www.nesdev.org/wiki/Color_Dreams
+    ColorDreams,
+    // https://www.nesdev.org/wiki/BNROM
+    BNROM,
+    // https://www.nesdev.org/wiki/NINA-001
+    NINA001,
+    // https://www.nesdev.org/wiki/Camerica_BF9093, Fire Hawk also switches single screen mirroring
+    Camerica { mirroring_control: bool },
+    // https://www.nesdev.org/wiki/NINA-003-006
+    NINA03,
+}
+
+// boards built from a latch or two, one register picks the PRG and CHR banks
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Discrete {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    board: DiscreteBoard,
+    prg_bank: u8,
+    // 4 KiB banks at $0000 and $1000
+    chr_banks: [u8; 2],
+    mirroring: Option<ScreenMirroring>,
+}
+
+impl Discrete {
+    pub fn new(prg_rom: Vec<u8>, board: DiscreteBoard) -> Self {
+        Discrete {
+            prg_rom,
+            prg_ram: if board == DiscreteBoard::NINA001 { vec![0; PRG_RAM_SIZE] } else { vec![] },
+            board,
+            prg_bank: 0,
+            chr_banks: [0, 1],
+            mirroring: None,
+        }
+    }
+
+    // mapper 34 is BNROM with CHR RAM and NINA-001 with CHR ROM when the submapper doesn't tell
+    pub fn mapper_34(prg_rom: Vec<u8>, submapper: u8, chr_rom_size: usize) -> Self {
+        let board = match submapper {
+            1 => DiscreteBoard::NINA001,
+            2 => DiscreteBoard::BNROM,
+            _ if chr_rom_size > 0x2000 => DiscreteBoard::NINA001,
+            _ => DiscreteBoard::BNROM,
+        };
+        Discrete::new(prg_rom, board)
+    }
+
+    fn set_chr_8k(&mut self, bank: u8) {
+        self.chr_banks = [bank * 2, bank * 2 + 1];
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let offset = match (&self.board, addr) {
+            (DiscreteBoard::Camerica { .. }, 0x8000..=0xBFFF) => self.prg_bank as usize * 0x4000 + (addr & 0x3FFF) as usize,
+            // the last 16 KiB are fixed
+            (DiscreteBoard::Camerica { .. }, _) => self.prg_rom.len().saturating_sub(0x4000) + (addr & 0x3FFF) as usize,
+            _ => self.prg_bank as usize * 0x8000 + (addr & 0x7FFF) as usize,
+        };
+        offset % self.prg_rom.len()
+    }
+}
+
+impl Mapper for Discrete {
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => Some(self.prg_ram[(addr - 0x6000) as usize]),
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => Some(self.prg_rom[self.prg_offset(addr)]),
+            _ => None,
+        }
+    }
+
+    fn prg_ram_mapped(&self) -> bool {
+        !self.prg_ram.is_empty()
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match (&self.board, addr) {
+            (DiscreteBoard::GXROM, 0x8000..=0xFFFF) => {
+                self.prg_bank = (value >> 4) & 0b11;
+                self.set_chr_8k(value & 0b11);
+            }
+            (DiscreteBoard::ColorDreams, 0x8000..=0xFFFF) => {
+                self.prg_bank = value & 0b11;
+                self.set_chr_8k(value >> 4);
+            }
+            (DiscreteBoard::BNROM, 0x8000..=0xFFFF) => self.prg_bank = value,
+            // the registers sit on top of PRG RAM, which also takes the write
+            (DiscreteBoard::NINA001, 0x6000..=0x7FFF) => {
+                self.prg_ram[(addr - 0x6000) as usize] = value;
+                match addr {
+                    0x7FFD => self.prg_bank = value & 1,
+                    0x7FFE => self.chr_banks[0] = value & 0x0F,
+                    0x7FFF => self.chr_banks[1] = value & 0x0F,
+                    _ => {}
+                }
+            }
+            (DiscreteBoard::Camerica { mirroring_control: true }, 0x9000..=0x9FFF) => {
+                self.mirroring = Some(ScreenMirroring::Custom([(value >> 4) & 1; 4]));
+            }
+            (DiscreteBoard::Camerica { .. }, 0xC000..=0xFFFF) => self.prg_bank = value & 0x0F,
+            // only A8 and the address range are decoded, $4100-$41FF, $4300-$43FF and so on
+            (DiscreteBoard::NINA03, 0x4020..=0x5FFF) if addr & 0xE100 == 0x4100 => {
+                self.prg_bank = (value >> 3) & 1;
+                self.set_chr_8k(value & 0b111);
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_banks(&self) -> [usize; CHR_WINDOWS] {
+        std::array::from_fn(|window| {
+            let bank = self.chr_banks[window / (CHR_WINDOWS / 2)] as usize;
+            bank * CHR_BANK_SIZE + (window % (CHR_WINDOWS / 2)) * CHR_WINDOW_SIZE
+        })
+    }
+
+    fn mirroring(&self) -> Option<ScreenMirroring> {
+        self.mirroring.clone()
+    }
+}
@@ -0,0 +1,365 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::fds;
+use crate::hw::cartridge::mapper::fds_audio::FdsAudio;
+use crate::hw::cartridge::mapper::Mapper;
+use crate::hw::cartridge::ScreenMirroring;
+
+const PRG_RAM_SIZE: usize = 0x8000;
+// CPU cycles from the head returning to the start of the disk until the first byte
+const HEAD_RETURN_DELAY: u32 = 50000;
+// CPU cycles per byte, the drive transfers about 96 kbit/s
+const BYTE_DELAY: u32 = 150;
+// about a second without a disk, long enough for the BIOS to notice the side was changed
+const INSERT_DELAY: u32 = 1_800_000;
+
+// https://www.nesdev.org/wiki/Family_Computer_Disk_System
+// the RAM adapter, with 32 KiB of PRG RAM, 8 KiB of CHR RAM, the BIOS at $E000, a timer IRQ,
+// the disk drive port and a wavetable sound channel
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Fds {
+    bios: Vec<u8>,
+    prg_ram: Vec<u8>,
+    // every side as the drive sees it, gaps and block marks included
+    disk_sides: Vec<Vec<u8>>,
+    disk_side: Option<usize>,
+    next_side: Option<usize>,
+    insert_delay: u32,
+    disk_written: bool,
+
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_repeat: bool,
+    irq_enabled: bool,
+    timer_irq: bool,
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+
+    // $4025 control bits
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    mirroring: ScreenMirroring,
+    crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+
+    read_data: u8,
+    write_data: u8,
+    transfer_complete: bool,
+    disk_irq: bool,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    previous_crc_control: bool,
+    crc: u16,
+    position: usize,
+    delay: u32,
+
+    audio: FdsAudio,
+}
+
+impl Fds {
+    // sides in the .fds layout, the first one is inserted
+    pub fn new(bios: Vec<u8>, sides: &[Vec<u8>]) -> Self {
+        let disk_sides: Vec<Vec<u8>> = sides.iter().map(|side| fds::add_gaps(side)).collect();
+        Fds {
+            bios,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            disk_side: if disk_sides.is_empty() { None } else { Some(0) },
+            disk_sides,
+            next_side: None,
+            insert_delay: 0,
+            disk_written: false,
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_repeat: false,
+            irq_enabled: false,
+            timer_irq: false,
+            disk_registers_enabled: false,
+            sound_registers_enabled: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            mirroring: ScreenMirroring::Horizontal,
+            crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            read_data: 0,
+            write_data: 0,
+            transfer_complete: false,
+            disk_irq: false,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            previous_crc_control: false,
+            crc: 0,
+            position: 0,
+            delay: 0,
+            audio: FdsAudio::new(),
+        }
+    }
+
+    pub fn side_count(&self) -> usize {
+        self.disk_sides.len()
+    }
+
+    pub fn disk_side(&self) -> Option<usize> {
+        self.disk_side
+    }
+
+    pub fn eject(&mut self) {
+        self.disk_side = None;
+        self.next_side = None;
+    }
+
+    // the side goes in after a delay, so the BIOS sees the drive empty in between
+    pub fn insert(&mut self, side: usize) {
+        if side < self.disk_sides.len() {
+            self.eject();
+            self.next_side = Some(side);
+            self.insert_delay = INSERT_DELAY;
+        }
+    }
+
+    // the sides in the .fds layout once the BIOS is done writing to them
+    pub fn take_written_sides(&mut self) -> Option<Vec<Vec<u8>>> {
+        let writing = self.motor_on && !self.read_mode;
+        if !self.disk_written || writing {
+            return None;
+        }
+        self.disk_written = false;
+        Some(self.disk_sides.iter().map(|side| fds::remove_gaps(side)).collect())
+    }
+
+    fn tick_timer(&mut self) {
+        if !self.irq_enabled || !self.disk_registers_enabled {
+            return;
+        }
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            self.irq_counter = self.irq_reload;
+            if !self.irq_repeat {
+                self.irq_enabled = false;
+            }
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+
+    // https://www.nesdev.org/wiki/FDS_disk_format#CRC
+    fn update_crc(&mut self, value: u8) {
+        for bit in 0..8 {
+            let carry = self.crc & 1 != 0;
+            self.crc >>= 1;
+            if carry {
+                self.crc ^= 0x8408;
+            }
+            if value & (1 << bit) != 0 {
+                self.crc ^= 0x8000;
+            }
+        }
+    }
+
+    // the head moves one byte every BYTE_DELAY cycles while the motor runs
+    fn tick_drive(&mut self) {
+        if self.insert_delay > 0 {
+            self.insert_delay -= 1;
+            if self.insert_delay == 0 {
+                self.disk_side = self.next_side.take();
+            }
+        }
+
+        let Some(side) = self.disk_side.filter(|_| self.motor_on) else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = HEAD_RETURN_DELAY;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let mut need_irq = self.disk_irq_enabled;
+        if self.read_mode {
+            let data = self.disk_sides[side][self.position];
+            if !self.previous_crc_control {
+                self.update_crc(data);
+            }
+            if !self.disk_ready {
+                self.gap_ended = false;
+                self.crc = 0;
+            } else if data != 0 && !self.gap_ended {
+                // the start mark isn't handed to the CPU with an IRQ
+                self.gap_ended = true;
+                need_irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.disk_irq |= need_irq;
+            }
+        } else {
+            let mut data = 0;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                data = self.write_data;
+                self.disk_irq |= need_irq;
+            }
+            if !self.disk_ready {
+                data = 0;
+            }
+            if !self.crc_control {
+                self.update_crc(data);
+            } else {
+                if !self.previous_crc_control {
+                    self.update_crc(0);
+                    self.update_crc(0);
+                }
+                data = self.crc as u8;
+                self.crc >>= 8;
+            }
+            // the write head trails the read head by two bytes
+            if let Some(byte) = self.position.checked_sub(2).and_then(|index| self.disk_sides[side].get_mut(index)) {
+                *byte = data;
+                self.disk_written = true;
+            }
+            self.gap_ended = false;
+        }
+
+        self.previous_crc_control = self.crc_control;
+        self.position += 1;
+        if self.position >= self.disk_sides[side].len() {
+            // the head reached the end of the disk, the BIOS restarts the motor to go back
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_DELAY;
+        }
+    }
+
+    fn status(&self) -> u8 {
+        let mut value = self.timer_irq as u8 | (self.transfer_complete as u8) << 1;
+        if self.end_of_head {
+            value |= 0x40;
+        }
+        value
+    }
+
+    fn drive_status(&self) -> u8 {
+        let inserted = self.disk_side.is_some();
+        // no disk, not ready and write protected
+        !inserted as u8 | ((!inserted || !self.scanning) as u8) << 1 | (!inserted as u8) << 2 | 0x40
+    }
+}
+
+impl Mapper for Fds {
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        let value = self.peek(addr);
+        match addr {
+            0x4030 if self.disk_registers_enabled => {
+                self.timer_irq = false;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4031 if self.disk_registers_enabled => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            _ => {}
+        }
+        value
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4030 if self.disk_registers_enabled => Some(self.status()),
+            0x4031 if self.disk_registers_enabled => Some(self.read_data),
+            0x4032 if self.disk_registers_enabled => Some(self.drive_status()),
+            // the battery is good
+            0x4033 if self.disk_registers_enabled => Some(0x80),
+            0x4040..=0x4097 if self.sound_registers_enabled => self.audio.read(addr),
+            0x6000..=0xDFFF => Some(self.prg_ram[(addr - 0x6000) as usize]),
+            0xE000..=0xFFFF => self.bios.get((addr - 0xE000) as usize).copied(),
+            _ => None,
+        }
+    }
+
+    fn prg_ram_mapped(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | value as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | (value as u16) << 8,
+            0x4022 => {
+                self.irq_repeat = value & 0b01 != 0;
+                self.irq_enabled = value & 0b10 != 0 && self.disk_registers_enabled;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_registers_enabled = value & 0b01 != 0;
+                self.sound_registers_enabled = value & 0b10 != 0;
+                if !self.disk_registers_enabled {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_registers_enabled => {
+                self.write_data = value;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_registers_enabled => {
+                self.motor_on = value & 0x01 != 0;
+                self.reset_transfer = value & 0x02 != 0;
+                self.read_mode = value & 0x04 != 0;
+                self.mirroring = if value & 0x08 != 0 { ScreenMirroring::Horizontal } else { ScreenMirroring::Vertical };
+                self.crc_control = value & 0x10 != 0;
+                self.disk_ready = value & 0x40 != 0;
+                self.disk_irq_enabled = value & 0x80 != 0;
+                self.disk_irq = false;
+            }
+            0x4040..=0x408A if self.sound_registers_enabled => self.audio.write(addr, value),
+            0x6000..=0xDFFF => self.prg_ram[(addr - 0x6000) as usize] = value,
+            _ => {}
+        }
+    }
+
+    fn mirroring(&self) -> Option<ScreenMirroring> {
+        Some(self.mirroring.clone())
+    }
+
+    fn tick(&mut self) {
+        self.tick_timer();
+        self.tick_drive();
+        self.audio.tick();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn needs_dot_rendering(&self) -> bool {
+        true
+    }
+}
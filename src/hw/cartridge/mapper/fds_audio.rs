@@ -0,0 +1,243 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::APU_PULSE_LEVEL;
+
+// https://www.nesdev.org/wiki/FDS_audio
+// at full volume the channel is about 2.4 times as loud as an APU pulse
+const OUTPUT_LEVEL: f32 = 2.4 * APU_PULSE_LEVEL / 63.0;
+const WAVE_SIZE: usize = 64;
+const MAX_GAIN: u8 = 32;
+// the master volume of $4089 scales the output by 2/2, 2/3, 2/4 and 2/5
+const MASTER_VOLUMES: [u32; 4] = [36, 24, 17, 14];
+// modulation table entries add these steps to the counter, 4 resets it
+const MOD_STEPS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+
+// volume and modulation share the envelope unit
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct FdsEnvelope {
+    speed: u8,
+    increase: bool,
+    disabled: bool,
+    gain: u8,
+    timer: u32,
+    frequency: u16,
+}
+
+impl FdsEnvelope {
+    // MDSS SSSS, envelope off, direction and speed, which is also the gain while the envelope is off
+    fn write_control(&mut self, value: u8, master_speed: u8) {
+        self.speed = value & 0x3F;
+        self.increase = value & 0x40 != 0;
+        self.disabled = value & 0x80 != 0;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.reset_timer(master_speed);
+    }
+
+    fn write_frequency_low(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x0F00) | value as u16;
+    }
+
+    fn write_frequency_high(&mut self, value: u8) {
+        self.frequency = (self.frequency & 0x00FF) | ((value & 0x0F) as u16) << 8;
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    // true when the gain changed
+    fn tick(&mut self, master_speed: u8) -> bool {
+        if self.disabled || master_speed == 0 {
+            return false;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return false;
+        }
+        self.reset_timer(master_speed);
+        if self.increase && self.gain < MAX_GAIN {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+        true
+    }
+}
+
+// a 64 step wavetable whose pitch is bent by a second table of modulation steps
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FdsAudio {
+    wave: Vec<u8>,
+    wave_write: bool,
+    wave_position: usize,
+    wave_accumulator: u32,
+    wave_halted: bool,
+    envelopes_disabled: bool,
+    master_volume: usize,
+    master_speed: u8,
+    volume: FdsEnvelope,
+    output: u8,
+
+    modulation: FdsEnvelope,
+    mod_table: Vec<u8>,
+    mod_position: usize,
+    mod_accumulator: u32,
+    mod_halted: bool,
+    // 7 bit signed
+    mod_counter: i8,
+    mod_output: i32,
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        FdsAudio {
+            wave: vec![0; WAVE_SIZE],
+            wave_write: false,
+            wave_position: 0,
+            wave_accumulator: 0,
+            wave_halted: true,
+            envelopes_disabled: false,
+            master_volume: 0,
+            master_speed: 0xE8,
+            volume: FdsEnvelope::default(),
+            output: 0,
+            modulation: FdsEnvelope::default(),
+            mod_table: vec![0; WAVE_SIZE],
+            mod_position: 0,
+            mod_accumulator: 0,
+            mod_halted: true,
+            mod_counter: 0,
+            mod_output: 0,
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4040..=0x407F => Some(self.wave[(addr - 0x4040) as usize]),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4040..=0x407F if self.wave_write => self.wave[(addr - 0x4040) as usize] = value & 0x3F,
+            0x4080 => self.volume.write_control(value, self.master_speed),
+            0x4082 => self.volume.write_frequency_low(value),
+            0x4083 => {
+                self.volume.write_frequency_high(value);
+                self.wave_halted = value & 0x80 != 0;
+                self.envelopes_disabled = value & 0x40 != 0;
+                if self.wave_halted {
+                    self.wave_position = 0;
+                    self.wave_accumulator = 0;
+                }
+            }
+            0x4084 => self.modulation.write_control(value, self.master_speed),
+            0x4085 => {
+                self.mod_counter = Self::wrap_counter(value as i8);
+                self.update_mod_output();
+            }
+            0x4086 => self.modulation.write_frequency_low(value),
+            0x4087 => {
+                self.modulation.write_frequency_high(value);
+                self.mod_halted = value & 0x80 != 0;
+                if self.mod_halted {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // the table is only written while the modulator is halted, two entries at a time
+            0x4088 if self.mod_halted => {
+                for _ in 0..2 {
+                    self.mod_table[self.mod_position] = value & 0b111;
+                    self.mod_position = (self.mod_position + 1) % WAVE_SIZE;
+                }
+            }
+            0x4089 => {
+                self.wave_write = value & 0x80 != 0;
+                self.master_volume = (value & 0b11) as usize;
+            }
+            0x408A => self.master_speed = value,
+            _ => {}
+        }
+    }
+
+    fn wrap_counter(value: i8) -> i8 {
+        ((value as i16 + 64) & 0x7F) as i8 - 64
+    }
+
+    // the pitch bend from the modulation counter and gain, following the hardware's rounding
+    fn update_mod_output(&mut self) {
+        let counter = self.mod_counter as i32;
+        let mut temp = counter * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= self.volume.frequency as i32;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        self.mod_output = temp;
+    }
+
+    fn tick_modulator(&mut self) -> bool {
+        if self.mod_halted || self.modulation.frequency == 0 {
+            return false;
+        }
+        self.mod_accumulator += self.modulation.frequency as u32;
+        if self.mod_accumulator <= 0xFFFF {
+            return false;
+        }
+        self.mod_accumulator -= 0xFFFF;
+        let step = self.mod_table[self.mod_position];
+        self.mod_counter = if step == 4 { 0 } else { Self::wrap_counter(self.mod_counter.wrapping_add(MOD_STEPS[step as usize])) };
+        self.mod_position = (self.mod_position + 1) % WAVE_SIZE;
+        true
+    }
+
+    // one CPU cycle
+    pub fn tick(&mut self) {
+        if !self.wave_halted && !self.envelopes_disabled {
+            self.volume.tick(self.master_speed);
+            if self.modulation.tick(self.master_speed) {
+                self.update_mod_output();
+            }
+        }
+        if self.tick_modulator() {
+            self.update_mod_output();
+        }
+
+        if !self.wave_halted {
+            let pitch = self.volume.frequency as i32 + self.mod_output;
+            // the wave holds its position while the table is written
+            if pitch > 0 && !self.wave_write {
+                self.wave_accumulator += pitch as u32;
+                if self.wave_accumulator > 0xFFFF {
+                    self.wave_accumulator -= 0xFFFF;
+                    self.wave_position = (self.wave_position + 1) % WAVE_SIZE;
+                }
+            }
+        }
+
+        // the output only changes while the wave is playing
+        if !self.wave_write {
+            let level = self.volume.gain.min(MAX_GAIN) as u32 * MASTER_VOLUMES[self.master_volume];
+            self.output = (self.wave[self.wave_position] as u32 * level / 1152) as u8;
+        }
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output as f32 * OUTPUT_LEVEL
+    }
+}
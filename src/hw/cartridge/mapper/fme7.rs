@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::{Mapper, APU_PULSE_LEVEL, CHR_WINDOWS, CHR_WINDOW_SIZE};
+use crate::hw::cartridge::ScreenMirroring;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x2000;
+// the 5B volume curve tops out at 1.0 for volume 15
+const OUTPUT_LEVEL: f32 = APU_PULSE_LEVEL;
+// the 5B runs its tone, noise and envelope generators at a 16th of the CPU clock
+const AUDIO_DIVIDER: u8 = 16;
+
+// https://www.nesdev.org/wiki/Sunsoft_FME-7
+// a command register at $8000 selects what the parameter written to $A000 changes
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Fme7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    command: u8,
+    chr_banks: [u8; CHR_WINDOWS],
+    // ERbB BBBB, RAM enabled, RAM or ROM and the bank at $6000
+    prg_bank_6000: u8,
+    // $8000, $A000 and $C000, $E000 is fixed to the last bank
+    prg_banks: [u8; 3],
+    mirroring: ScreenMirroring,
+    irq_enabled: bool,
+    counter_enabled: bool,
+    counter: u16,
+    irq_pending: bool,
+    audio: Sunsoft5b,
+}
+
+impl Fme7 {
+    pub fn new(prg_rom: Vec<u8>) -> Self {
+        Fme7 {
+            prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            command: 0,
+            chr_banks: [0; CHR_WINDOWS],
+            prg_bank_6000: 0,
+            prg_banks: [0; 3],
+            mirroring: ScreenMirroring::Vertical,
+            irq_enabled: false,
+            counter_enabled: false,
+            counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5b::new(),
+        }
+    }
+
+    fn read_prg_rom(&self, bank: usize, addr: u16) -> Option<u8> {
+        if self.prg_rom.is_empty() {
+            return None;
+        }
+        let offset = bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE;
+        Some(self.prg_rom[offset % self.prg_rom.len()])
+    }
+
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = value,
+            0x8 => self.prg_bank_6000 = value,
+            0x9..=0xB => self.prg_banks[self.command as usize - 0x9] = value & 0x3F,
+            0xC => {
+                self.mirroring = match value & 0b11 {
+                    0 => ScreenMirroring::Vertical,
+                    1 => ScreenMirroring::Horizontal,
+                    page => ScreenMirroring::Custom([page & 1; 4]),
+                }
+            }
+            0xD => {
+                self.irq_enabled = value & 0x01 != 0;
+                self.counter_enabled = value & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0xE => self.counter = (self.counter & 0xFF00) | value as u16,
+            _ => self.counter = (self.counter & 0x00FF) | (value as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF => match self.prg_bank_6000 & 0xC0 {
+                0xC0 => Some(self.prg_ram[(addr - 0x6000) as usize]),
+                // RAM selected but disabled leaves the open bus
+                0x40 => None,
+                _ => self.read_prg_rom((self.prg_bank_6000 & 0x3F) as usize, addr),
+            },
+            0x8000..=0xDFFF => self.read_prg_rom(self.prg_banks[(addr as usize - 0x8000) / PRG_BANK_SIZE] as usize, addr),
+            0xE000..=0xFFFF => self.read_prg_rom((self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1), addr),
+            _ => None,
+        }
+    }
+
+    fn prg_ram_mapped(&self) -> bool {
+        self.prg_bank_6000 & 0xC0 == 0xC0
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_bank_6000 & 0xC0 == 0xC0 => self.prg_ram[(addr - 0x6000) as usize] = value,
+            0x8000..=0x9FFF => self.command = value & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(value),
+            0xC000..=0xDFFF => self.audio.select(value),
+            0xE000..=0xFFFF => self.audio.write(value),
+            _ => {}
+        }
+    }
+
+    fn chr_banks(&self) -> [usize; CHR_WINDOWS] {
+        std::array::from_fn(|window| self.chr_banks[window] as usize * CHR_WINDOW_SIZE)
+    }
+
+    fn mirroring(&self) -> Option<ScreenMirroring> {
+        Some(self.mirroring.clone())
+    }
+
+    // the counter decrements every CPU cycle and raises the IRQ when it wraps from $0000 to $FFFF
+    fn tick(&mut self) {
+        if self.counter_enabled {
+            self.counter = self.counter.wrapping_sub(1);
+            if self.counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.tick();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn needs_dot_rendering(&self) -> bool {
+        true
+    }
+}
+
+// https://www.nesdev.org/wiki/Sunsoft_5B_audio
+// a YM2149F, three square waves that can be mixed with a shared noise and envelope
+#[derive(Serialize, Deserialize, Debug)]
+struct Sunsoft5b {
+    register: u8,
+    divider: u8,
+    tone_periods: [u16; 3],
+    tone_timers: [u16; 3],
+    tone_levels: [bool; 3],
+    noise_period: u8,
+    noise_timer: u8,
+    // 17 bit LFSR
+    noise_shift: u32,
+    // --CB Acba, noise and tone disable bits of each channel
+    mixer: u8,
+    // ---E VVVV, the envelope replaces the volume when E is set
+    volumes: [u8; 3],
+    envelope_period: u16,
+    envelope_timer: u16,
+    // CAtH: continue, attack, alternate and hold
+    envelope_shape: u8,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_holding: bool,
+}
+
+impl Sunsoft5b {
+    fn new() -> Self {
+        Sunsoft5b {
+            register: 0,
+            divider: 0,
+            tone_periods: [0; 3],
+            tone_timers: [0; 3],
+            tone_levels: [false; 3],
+            noise_period: 0,
+            noise_timer: 0,
+            noise_shift: 1,
+            mixer: 0,
+            volumes: [0; 3],
+            envelope_period: 0,
+            envelope_timer: 0,
+            envelope_shape: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_holding: true,
+        }
+    }
+
+    // writes with the upper 4 bits set don't select a register
+    fn select(&mut self, value: u8) {
+        if value & 0xF0 == 0 {
+            self.register = value;
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        match self.register {
+            register @ 0x0..=0x5 => {
+                let channel = register as usize / 2;
+                let period = &mut self.tone_periods[channel];
+                *period = if register % 2 == 0 {
+                    (*period & 0x0F00) | value as u16
+                } else {
+                    (*period & 0x00FF) | ((value & 0x0F) as u16) << 8
+                };
+            }
+            0x6 => self.noise_period = value & 0x1F,
+            0x7 => self.mixer = value,
+            register @ 0x8..=0xA => self.volumes[register as usize - 0x8] = value & 0x1F,
+            0xB => self.envelope_period = (self.envelope_period & 0xFF00) | value as u16,
+            0xC => self.envelope_period = (self.envelope_period & 0x00FF) | (value as u16) << 8,
+            0xD => {
+                self.envelope_shape = value & 0x0F;
+                self.envelope_attack = value & 0b0100 != 0;
+                self.envelope_step = 0;
+                self.envelope_timer = 0;
+                self.envelope_holding = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self) {
+        self.divider += 1;
+        if self.divider < AUDIO_DIVIDER {
+            return;
+        }
+        self.divider = 0;
+
+        for channel in 0..3 {
+            self.tone_timers[channel] += 1;
+            if self.tone_timers[channel] >= self.tone_periods[channel].max(1) {
+                self.tone_timers[channel] = 0;
+                self.tone_levels[channel] = !self.tone_levels[channel];
+            }
+        }
+
+        self.noise_timer += 1;
+        if self.noise_timer >= self.noise_period.max(1) {
+            self.noise_timer = 0;
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 1;
+            self.noise_shift = (self.noise_shift >> 1) | feedback << 16;
+        }
+
+        self.envelope_timer += 1;
+        if self.envelope_timer >= self.envelope_period.max(1) {
+            self.envelope_timer = 0;
+            self.clock_envelope();
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        if self.envelope_step < 15 {
+            self.envelope_step += 1;
+            return;
+        }
+        let alternate = self.envelope_shape & 0b0010 != 0;
+        if self.envelope_shape & 0b1000 == 0 {
+            // without continue the envelope ends silent
+            self.envelope_holding = true;
+            self.envelope_attack = false;
+        } else if self.envelope_shape & 0b0001 != 0 {
+            self.envelope_holding = true;
+            self.envelope_attack ^= alternate;
+        } else {
+            self.envelope_attack ^= alternate;
+            self.envelope_step = 0;
+        }
+    }
+
+    fn envelope_level(&self) -> u8 {
+        if self.envelope_attack { self.envelope_step } else { 15 - self.envelope_step }
+    }
+
+    // the volume steps are 3 dB apart
+    fn output(&self) -> f32 {
+        let noise = self.noise_shift & 1 != 0;
+        (0..3)
+            .map(|channel| {
+                let tone_on = self.tone_levels[channel] || self.mixer & (1 << channel) != 0;
+                let noise_on = noise || self.mixer & (8 << channel) != 0;
+                let volume = if self.volumes[channel] & 0x10 != 0 { self.envelope_level() } else { self.volumes[channel] & 0x0F };
+                if !tone_on || !noise_on || volume == 0 {
+                    0.0
+                } else {
+                    10f32.powf((volume as f32 - 15.0) * 3.0 / 20.0)
+                }
+            })
+            .sum::<f32>()
+            * OUTPUT_LEVEL
+    }
+}
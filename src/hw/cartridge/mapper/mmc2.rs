@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::{Mapper, CHR_WINDOWS, CHR_WINDOW_SIZE};
+use crate::hw::cartridge::ScreenMirroring;
+
+const PRG_RAM_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x1000;
+const LATCH_FD: u8 = 0xFD;
+const LATCH_FE: u8 = 0xFE;
+
+// https://www.nesdev.org/wiki/MMC2
+// https://www.nesdev.org/wiki/MMC4
+// each pattern table has two CHR banks, picked by a latch that flips when the PPU fetches tile $FD or $FE
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Mmc2 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    // MMC4 switches 16 KiB of PRG instead of 8 KiB and has PRG RAM
+    mmc4: bool,
+    prg_bank: u8,
+    // $0000 with latch $FD, $0000 with latch $FE, $1000 with latch $FD, $1000 with latch $FE
+    chr_registers: [u8; 4],
+    latches: [u8; 2],
+    mirroring: ScreenMirroring,
+}
+
+impl Mmc2 {
+    pub fn new(prg_rom: Vec<u8>, mmc4: bool) -> Self {
+        Mmc2 {
+            prg_rom,
+            prg_ram: if mmc4 { vec![0; PRG_RAM_SIZE] } else { vec![] },
+            mmc4,
+            prg_bank: 0,
+            chr_registers: [0; 4],
+            latches: [LATCH_FE; 2],
+            mirroring: ScreenMirroring::Vertical,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let len = self.prg_rom.len();
+        let offset = if self.mmc4 {
+            match addr {
+                0x8000..=0xBFFF => self.prg_bank as usize * 0x4000 + (addr & 0x3FFF) as usize,
+                // last 16 KiB are fixed
+                _ => len.saturating_sub(0x4000) + (addr & 0x3FFF) as usize,
+            }
+        } else {
+            match addr {
+                0x8000..=0x9FFF => self.prg_bank as usize * 0x2000 + (addr & 0x1FFF) as usize,
+                // last three 8 KiB banks are fixed
+                _ => len.saturating_sub(0x6000) + (addr - 0xA000) as usize,
+            }
+        };
+        offset % len
+    }
+
+    fn chr_bank(&self, table: usize) -> usize {
+        let register = table * 2 + if self.latches[table] == LATCH_FD { 0 } else { 1 };
+        self.chr_registers[register] as usize * CHR_BANK_SIZE
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => Some(self.prg_ram[(addr - 0x6000) as usize]),
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => Some(self.prg_rom[self.prg_offset(addr)]),
+            _ => None,
+        }
+    }
+
+    fn prg_ram_mapped(&self) -> bool {
+        !self.prg_ram.is_empty()
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => self.prg_ram[(addr - 0x6000) as usize] = value,
+            0xA000..=0xAFFF => self.prg_bank = value & 0x0F,
+            0xB000..=0xBFFF => self.chr_registers[0] = value & 0x1F,
+            0xC000..=0xCFFF => self.chr_registers[1] = value & 0x1F,
+            0xD000..=0xDFFF => self.chr_registers[2] = value & 0x1F,
+            0xE000..=0xEFFF => self.chr_registers[3] = value & 0x1F,
+            0xF000..=0xFFFF => {
+                self.mirroring = if value & 1 == 0 { ScreenMirroring::Vertical } else { ScreenMirroring::Horizontal };
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_banks(&self) -> [usize; CHR_WINDOWS] {
+        let (left, right) = (self.chr_bank(0), self.chr_bank(1));
+        std::array::from_fn(|window| {
+            let bank = if window < CHR_WINDOWS / 2 { left } else { right };
+            bank + (window % (CHR_WINDOWS / 2)) * CHR_WINDOW_SIZE
+        })
+    }
+
+    fn mirroring(&self) -> Option<ScreenMirroring> {
+        Some(self.mirroring.clone())
+    }
+
+    // the latch flips after the fetch, so the tile that triggers it still uses the old bank
+    fn ppu_bus_access(&mut self, addr: u16) {
+        let latch = match addr {
+            // MMC2 only reacts to the first row of tile $FD/$FE in the left pattern table
+            0x0FD8 => Some((0, LATCH_FD)),
+            0x0FE8 => Some((0, LATCH_FE)),
+            0x0FD9..=0x0FDF if self.mmc4 => Some((0, LATCH_FD)),
+            0x0FE9..=0x0FEF if self.mmc4 => Some((0, LATCH_FE)),
+            0x1FD8..=0x1FDF => Some((1, LATCH_FD)),
+            0x1FE8..=0x1FEF => Some((1, LATCH_FE)),
+            _ => None,
+        };
+        if let Some((table, value)) = latch {
+            self.latches[table] = value;
+        }
+    }
+
+    fn needs_dot_rendering(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,469 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::apu::pulse::Pulse;
+use crate::hw::cartridge::mapper::{Mapper, CHR_WINDOWS, CHR_WINDOW_SIZE};
+use crate::hw::cartridge::ScreenMirroring;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x10000;
+const EXRAM_SIZE: usize = 0x400;
+const SPLIT_BANK_SIZE: usize = 0x1000;
+// CPU cycles between clocks of the 240 Hz frame counter of the pulses
+const FRAME_COUNTER_PERIOD: u16 = 7457;
+// CPU cycles without a PPU read after which the PPU isn't rendering anymore
+const IDLE_CYCLES: u8 = 3;
+// PPU reads of a scanline, counted from the read that starts it
+const BACKGROUND_READS: u16 = 128;
+const SPRITE_READS: u16 = 32;
+const PREFETCH_READS: u16 = 8;
+
+// ExRAM modes of $5104: 0 nametable, 1 extended attributes, 2 RAM, 3 read-only RAM
+const EXRAM_EXTENDED_ATTRIBUTES: u8 = 1;
+const EXRAM_READ_ONLY: u8 = 3;
+
+const NAMETABLE_EXRAM: u8 = 2;
+const NAMETABLE_FILL: u8 = 3;
+
+// https://www.nesdev.org/wiki/MMC5
+// the board watches every PPU fetch, so it can count scanlines, use separate CHR banks for 8x16
+// sprites and substitute nametables, attributes and patterns from its own 1 KiB of ExRAM
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Mmc5 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr_rom: Vec<u8>,
+    exram: Vec<u8>,
+
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    // 2 bits per nametable: CIRAM page 0, CIRAM page 1, ExRAM or fill mode
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    // $5113-$5117
+    prg_registers: [u8; 5],
+    // $5120-$5127 for sprites (set A) and $5128-$512B for the background (set B)
+    chr_registers: [u16; 12],
+    chr_upper: u8,
+    // the set written last is used for everything when sprites are 8x8
+    last_chr_set_b: bool,
+    sprite_8x16: bool,
+
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+
+    irq_scanline: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    in_frame: bool,
+    scanline: u8,
+    idle_cycles: u8,
+    last_nametable_addr: u16,
+    nametable_repeats: u8,
+    line_reads: u16,
+    // what the nametable fetch decided for the attribute and pattern fetches of the same tile
+    tile_extension: u8,
+    tile_split: Option<(u8, u8)>,
+
+    multiplicand: u8,
+    multiplier: u8,
+
+    pulses: [Pulse; 2],
+    pcm: u8,
+    frame_counter: u16,
+    odd_cycle: bool,
+}
+
+impl Mmc5 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Mmc5 {
+            prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr_rom,
+            exram: vec![0; EXRAM_SIZE],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            // the last bank is mapped at $E000 on power on
+            prg_registers: [0, 0, 0, 0, 0xFF],
+            chr_registers: [0; 12],
+            chr_upper: 0,
+            last_chr_set_b: false,
+            sprite_8x16: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_scanline: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline: 0,
+            idle_cycles: 0,
+            last_nametable_addr: 0,
+            nametable_repeats: 0,
+            line_reads: 0,
+            tile_extension: 0,
+            tile_split: None,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            pulses: [Pulse::without_sweep(), Pulse::without_sweep()],
+            pcm: 0,
+            frame_counter: 0,
+            odd_cycle: false,
+        }
+    }
+
+    // https://www.nesdev.org/wiki/MMC5#PRG_mode_($5100)
+    // 8 KiB bank of a CPU address in $6000-$FFFF and whether it is ROM
+    fn prg_bank(&self, addr: u16) -> (usize, bool) {
+        let window = (addr as usize - 0x6000) / PRG_BANK_SIZE;
+        if window == 0 {
+            return (self.prg_registers[0] as usize, false);
+        }
+        // register and size in 8 KiB banks of the window
+        let (register, size) = match (self.prg_mode, window) {
+            (0, _) => (4, 4),
+            (1, 1 | 2) | (2, 1 | 2) => (2, 2),
+            (1, _) => (4, 2),
+            (2, 3) => (3, 1),
+            (2, _) => (4, 1),
+            (_, window) => (window, 1),
+        };
+        let value = self.prg_registers[register];
+        // $5117 is always ROM, the others pick ROM with bit 7
+        let rom = register == 4 || value & 0x80 != 0;
+        // bigger banks ignore the low bits of the register
+        let bank = (value & 0x7F) as usize & !(size - 1);
+        (bank + (window - 1) % size, rom)
+    }
+
+    fn prg_ram_index(&self, bank: usize, addr: u16) -> usize {
+        (bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE) % PRG_RAM_SIZE
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect[0] & 0b11 == 0b10 && self.prg_ram_protect[1] & 0b11 == 0b01
+    }
+
+    // https://www.nesdev.org/wiki/MMC5#CHR_mode_($5101)
+    fn chr_offset(&self, addr: u16, set_b: bool) -> usize {
+        let size = 0x2000 >> self.chr_mode;
+        let window = addr as usize / CHR_WINDOW_SIZE % CHR_WINDOWS;
+        let register = if set_b {
+            // set B only covers 4 KiB, repeated in both pattern tables
+            let window = window % 4;
+            8 + match self.chr_mode {
+                0 | 1 => 3,
+                2 => window / 2 * 2 + 1,
+                _ => window,
+            }
+        } else {
+            match self.chr_mode {
+                0 => 7,
+                1 => window / 4 * 4 + 3,
+                2 => window / 2 * 2 + 1,
+                _ => window,
+            }
+        };
+        self.chr_registers[register] as usize * size + addr as usize % size
+    }
+
+    fn read_chr(&self, offset: usize) -> Option<u8> {
+        if self.chr_rom.is_empty() {
+            return None;
+        }
+        Some(self.chr_rom[offset % self.chr_rom.len()])
+    }
+
+    // https://www.nesdev.org/wiki/MMC5#Scanline_detection_and_scanline_IRQ
+    // the PPU reads the same nametable address three times in a row only at the start of a line
+    fn detect_scanline(&mut self, addr: u16) {
+        if (0x2000..=0x2FFF).contains(&addr) && addr == self.last_nametable_addr {
+            self.nametable_repeats += 1;
+            if self.nametable_repeats == 2 {
+                self.start_scanline();
+            }
+        } else {
+            self.nametable_repeats = 0;
+        }
+        self.last_nametable_addr = addr;
+    }
+
+    // the first line of a frame is the prerender line, counted as scanline 0
+    fn start_scanline(&mut self) {
+        if self.in_frame {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.scanline == self.irq_scanline {
+                self.irq_pending = true;
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline = 0;
+            self.irq_pending = false;
+        }
+        self.line_reads = 0;
+    }
+
+    fn is_sprite_fetch(&self, read: u16) -> bool {
+        self.in_frame && (BACKGROUND_READS..BACKGROUND_READS + SPRITE_READS).contains(&read)
+    }
+
+    // tile column and line of a background fetch, the last two tiles of a line belong to the next one
+    fn background_tile(&self, read: u16) -> Option<(u8, u8)> {
+        let visible_line = self.scanline.checked_sub(1)?;
+        if read < BACKGROUND_READS {
+            Some(((read / 4 + 2) as u8 % 32, visible_line))
+        } else if (BACKGROUND_READS + SPRITE_READS..BACKGROUND_READS + SPRITE_READS + PREFETCH_READS).contains(&read) {
+            Some((((read - BACKGROUND_READS - SPRITE_READS) / 4) as u8, visible_line + 1))
+        } else {
+            None
+        }
+    }
+
+    // https://www.nesdev.org/wiki/MMC5#Vertical_split_mode
+    // split tile row and fine y of a background tile, or None when it is outside the split
+    fn split_tile(&self, column: u8, line: u8) -> Option<(u8, u8)> {
+        let enabled = self.split_control & 0x80 != 0 && self.exram_mode <= EXRAM_EXTENDED_ATTRIBUTES;
+        let threshold = self.split_control & 0x1F;
+        let inside = if self.split_control & 0x40 == 0 { column < threshold } else { column >= threshold };
+        if !enabled || !inside {
+            return None;
+        }
+        let y = (self.split_scroll as u16 + line as u16) % 240;
+        Some(((y / 8) as u8, (y % 8) as u8))
+    }
+
+    fn nametable_read(&mut self, addr: u16, read: u16) -> Option<u8> {
+        let offset = (addr & 0x3FF) as usize;
+        let background = if self.in_frame { self.background_tile(read) } else { None };
+        if let Some((column, line)) = background {
+            match read % 4 {
+                // nametable fetch
+                0 => {
+                    self.tile_split = self.split_tile(column, line);
+                    self.tile_extension = self.exram[offset];
+                    if let Some((row, _)) = self.tile_split {
+                        return Some(self.exram[row as usize * 32 + column as usize]);
+                    }
+                }
+                // attribute fetch, returned with the same palette in every quadrant
+                1 => {
+                    if let Some((row, _)) = self.tile_split {
+                        let attribute = self.exram[0x3C0 + row as usize / 4 * 8 + column as usize / 4];
+                        let shift = (row & 0b10) << 1 | (column & 0b10);
+                        return Some((attribute >> shift & 0b11) * 0x55);
+                    }
+                    if self.exram_mode == EXRAM_EXTENDED_ATTRIBUTES {
+                        return Some((self.tile_extension >> 6) * 0x55);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let nametable = (addr >> 10) & 0b11;
+        match (self.nametable_mapping >> (nametable * 2)) & 0b11 {
+            NAMETABLE_EXRAM if self.exram_mode <= EXRAM_EXTENDED_ATTRIBUTES => Some(self.exram[offset]),
+            NAMETABLE_EXRAM => Some(0),
+            NAMETABLE_FILL if offset >= 0x3C0 => Some(self.fill_attribute * 0x55),
+            NAMETABLE_FILL => Some(self.fill_tile),
+            // CIRAM, through the mirroring
+            _ => None,
+        }
+    }
+
+    fn pattern_read(&self, addr: u16, read: u16) -> Option<u8> {
+        if self.is_sprite_fetch(read) {
+            let set_b = !self.sprite_8x16 && self.last_chr_set_b;
+            return self.read_chr(self.chr_offset(addr, set_b));
+        }
+        if self.in_frame && self.background_tile(read).is_some() {
+            if let Some((_, fine_y)) = self.tile_split {
+                let offset = self.split_bank as usize * SPLIT_BANK_SIZE + (addr & 0x0FF8 | fine_y as u16) as usize;
+                return self.read_chr(offset);
+            }
+            if self.exram_mode == EXRAM_EXTENDED_ATTRIBUTES {
+                let bank = (self.tile_extension & 0x3F) as usize | (self.chr_upper as usize) << 6;
+                return self.read_chr(bank * SPLIT_BANK_SIZE + (addr & 0x0FFF) as usize);
+            }
+        }
+        let set_b = self.sprite_8x16 || self.last_chr_set_b;
+        self.read_chr(self.chr_offset(addr, set_b))
+    }
+
+    // https://www.nesdev.org/wiki/MMC5_audio
+    fn clock_audio(&mut self) {
+        self.frame_counter += 1;
+        if self.frame_counter == FRAME_COUNTER_PERIOD {
+            self.frame_counter = 0;
+            for pulse in self.pulses.iter_mut() {
+                pulse.clock_quarter_frame();
+                pulse.clock_half_frame();
+            }
+        }
+        if self.odd_cycle {
+            for pulse in self.pulses.iter_mut() {
+                pulse.clock_timer();
+            }
+        }
+        self.odd_cycle = !self.odd_cycle;
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        let value = self.peek(addr);
+        match addr {
+            0x5204 => self.irq_pending = false,
+            // the NMI vector is fetched outside of rendering
+            0xFFFA | 0xFFFB => self.in_frame = false,
+            _ => {}
+        }
+        value
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x5015 => Some(self.pulses[0].length.is_active() as u8 | (self.pulses[1].length.is_active() as u8) << 1),
+            0x5204 => Some((self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6),
+            0x5205 => Some((self.multiplicand as u16 * self.multiplier as u16) as u8),
+            0x5206 => Some(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+            // ExRAM can only be read by the CPU when it is used as RAM
+            0x5C00..=0x5FFF if self.exram_mode > EXRAM_EXTENDED_ATTRIBUTES => Some(self.exram[(addr - 0x5C00) as usize]),
+            0x6000..=0xFFFF => {
+                let (bank, rom) = self.prg_bank(addr);
+                if !rom {
+                    Some(self.prg_ram[self.prg_ram_index(bank, addr)])
+                } else if self.prg_rom.is_empty() {
+                    None
+                } else {
+                    Some(self.prg_rom[(bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE) % self.prg_rom.len()])
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn prg_ram_mapped(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x5000..=0x5007 => {
+                let pulse = &mut self.pulses[(addr as usize - 0x5000) / 4];
+                match addr % 4 {
+                    0 => pulse.write_control(value),
+                    // no sweep unit
+                    1 => {}
+                    2 => pulse.write_timer_low(value),
+                    _ => pulse.write_timer_high(value),
+                }
+            }
+            // raw PCM, a write of 0 is ignored
+            0x5011 if value != 0 => self.pcm = value,
+            0x5015 => {
+                self.pulses[0].length.set_enabled(value & 0b01 != 0);
+                self.pulses[1].length.set_enabled(value & 0b10 != 0);
+            }
+            0x5100 => self.prg_mode = value & 0b11,
+            0x5101 => self.chr_mode = value & 0b11,
+            0x5102 | 0x5103 => self.prg_ram_protect[(addr - 0x5102) as usize] = value,
+            0x5104 => self.exram_mode = value & 0b11,
+            0x5105 => self.nametable_mapping = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0b11,
+            0x5113..=0x5117 => self.prg_registers[(addr - 0x5113) as usize] = value,
+            0x5120..=0x512B => {
+                self.chr_registers[(addr - 0x5120) as usize] = value as u16 | (self.chr_upper as u16) << 8;
+                self.last_chr_set_b = addr >= 0x5128;
+            }
+            0x5130 => self.chr_upper = value & 0b11,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_scanline = value,
+            0x5204 => self.irq_enabled = value & 0x80 != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            0x5C00..=0x5FFF if self.exram_mode != EXRAM_READ_ONLY => {
+                // in the nametable modes the PPU owns ExRAM outside of rendering and the write turns into 0
+                let rendering_mode = self.exram_mode <= EXRAM_EXTENDED_ATTRIBUTES;
+                self.exram[(addr - 0x5C00) as usize] = if rendering_mode && !self.in_frame { 0 } else { value };
+            }
+            0x6000..=0xDFFF if self.prg_ram_writable() => {
+                let (bank, rom) = self.prg_bank(addr);
+                if !rom {
+                    let index = self.prg_ram_index(bank, addr);
+                    self.prg_ram[index] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // the background set for the PPU's own CHR accesses, like $2007
+    fn chr_banks(&self) -> [usize; CHR_WINDOWS] {
+        let set_b = self.sprite_8x16 || self.last_chr_set_b;
+        std::array::from_fn(|window| self.chr_offset((window * CHR_WINDOW_SIZE) as u16, set_b))
+    }
+
+    // ExRAM and fill mode nametables are answered in ppu_read, CIRAM pages go through the PPU
+    fn mirroring(&self) -> Option<ScreenMirroring> {
+        Some(ScreenMirroring::Custom(std::array::from_fn(|nametable| {
+            (self.nametable_mapping >> (nametable * 2)) & 1
+        })))
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> Option<u8> {
+        self.idle_cycles = 0;
+        self.detect_scanline(addr);
+        let read = self.line_reads;
+        self.line_reads = self.line_reads.saturating_add(1);
+        match addr {
+            0x0000..=0x1FFF => self.pattern_read(addr, read),
+            0x2000..=0x3EFF => self.nametable_read(addr & 0x2FFF, read),
+            _ => None,
+        }
+    }
+
+    fn ppu_register_write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x2000 => self.sprite_8x16 = value & 0b0010_0000 != 0,
+            0x2001 if value & 0b0001_1000 == 0 => self.in_frame = false,
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.idle_cycles >= IDLE_CYCLES {
+            self.in_frame = false;
+        } else {
+            self.idle_cycles += 1;
+        }
+        self.clock_audio();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_enabled && self.irq_pending
+    }
+
+    // same mixing as the APU pulses, the PCM channel like the DMC
+    fn audio_output(&self) -> f32 {
+        let pulse = (self.pulses[0].output() + self.pulses[1].output()) as f32;
+        let pulse_out = if pulse == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulse + 100.0) };
+        let pcm = self.pcm as f32 / 2.0 / 22638.0;
+        let pcm_out = if pcm == 0.0 { 0.0 } else { 159.79 / (1.0 / pcm + 100.0) };
+        pulse_out + pcm_out
+    }
+
+    fn needs_dot_rendering(&self) -> bool {
+        true
+    }
+}
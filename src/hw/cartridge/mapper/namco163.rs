@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::{Mapper, APU_PULSE_LEVEL, CHR_WINDOWS, CHR_WINDOW_SIZE};
+use crate::hw::cartridge::ScreenMirroring;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x2000;
+const SOUND_RAM_SIZE: usize = 0x80;
+// bank numbers from $E0 up select a CIRAM page instead of CHR ROM
+const CIRAM_BANKS: u8 = 0xE0;
+const IRQ_COUNTER_MAX: u16 = 0x7FFF;
+// CPU cycles the chip spends on each enabled channel
+const CHANNEL_CYCLES: u8 = 15;
+// a centered sample peaks at 8 times volume 15
+const OUTPUT_LEVEL: f32 = APU_PULSE_LEVEL / 120.0;
+
+// https://www.nesdev.org/wiki/Namco_163
+// CIRAM as pattern table memory isn't emulated, those banks read CHR ROM
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Namco163 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    // nametables can be mapped to CHR ROM
+    chr_rom: Vec<u8>,
+    chr_banks: [u8; CHR_WINDOWS],
+    nametable_banks: [u8; 4],
+    // $8000, $A000 and $C000, $E000 is fixed to the last bank
+    prg_banks: [u8; 3],
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    // wavetables and channel registers, also used as save RAM by some games
+    sound_ram: Vec<u8>,
+    sound_address: u8,
+    auto_increment: bool,
+    sound_disabled: bool,
+    channel_timer: u8,
+    current_channel: usize,
+    channel_outputs: [i16; 8],
+}
+
+impl Namco163 {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        Namco163 {
+            prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            chr_rom,
+            chr_banks: [0; CHR_WINDOWS],
+            // vertical mirroring until the game sets the nametables
+            nametable_banks: [CIRAM_BANKS, CIRAM_BANKS + 1, CIRAM_BANKS, CIRAM_BANKS + 1],
+            prg_banks: [0; 3],
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            sound_ram: vec![0; SOUND_RAM_SIZE],
+            sound_address: 0,
+            auto_increment: false,
+            sound_disabled: false,
+            channel_timer: 0,
+            current_channel: 7,
+            channel_outputs: [0; 8],
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) / PRG_BANK_SIZE] as usize,
+            _ => (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1),
+        };
+        (bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE) % self.prg_rom.len()
+    }
+
+    fn next_sound_address(&mut self) {
+        if self.auto_increment {
+            self.sound_address = (self.sound_address + 1) % SOUND_RAM_SIZE as u8;
+        }
+    }
+
+    // channels 7 down to 7 - N play, N+1 is set by bits 4-6 of $7F
+    fn enabled_channels(&self) -> usize {
+        ((self.sound_ram[0x7F] >> 4) & 0b111) as usize + 1
+    }
+
+    // https://www.nesdev.org/wiki/Namco_163_audio
+    fn update_channel(&mut self, channel: usize) {
+        let base = 0x40 + channel * 8;
+        let ram = &self.sound_ram;
+        let frequency = ram[base] as u32 | (ram[base + 2] as u32) << 8 | ((ram[base + 4] & 0b11) as u32) << 16;
+        let length = 256 - (ram[base + 4] & 0xFC) as u32;
+        let mut phase = ram[base + 1] as u32 | (ram[base + 3] as u32) << 8 | (ram[base + 5] as u32) << 16;
+        phase = (phase + frequency) % (length << 16);
+
+        let sample_index = ((phase >> 16) + ram[base + 6] as u32) as usize & 0xFF;
+        let byte = ram[sample_index / 2 % SOUND_RAM_SIZE];
+        let sample = if sample_index.is_multiple_of(2) { byte & 0x0F } else { byte >> 4 };
+        let volume = (ram[base + 7] & 0x0F) as i16;
+        self.channel_outputs[channel] = (sample as i16 - 8) * volume;
+
+        self.sound_ram[base + 1] = phase as u8;
+        self.sound_ram[base + 3] = (phase >> 8) as u8;
+        self.sound_ram[base + 5] = (phase >> 16) as u8;
+    }
+}
+
+impl Mapper for Namco163 {
+    fn read(&mut self, addr: u16) -> Option<u8> {
+        let value = self.peek(addr);
+        if (0x4800..=0x4FFF).contains(&addr) {
+            self.next_sound_address();
+        }
+        value
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4FFF => Some(self.sound_ram[self.sound_address as usize]),
+            0x5000..=0x57FF => Some(self.irq_counter as u8),
+            0x5800..=0x5FFF => Some((self.irq_enabled as u8) << 7 | (self.irq_counter >> 8) as u8),
+            0x6000..=0x7FFF => Some(self.prg_ram[(addr - 0x6000) as usize]),
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => Some(self.prg_rom[self.prg_offset(addr)]),
+            _ => None,
+        }
+    }
+
+    fn prg_ram_mapped(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4800..=0x4FFF => {
+                self.sound_ram[self.sound_address as usize] = value;
+                self.next_sound_address();
+            }
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | value as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | ((value & 0x7F) as u16) << 8;
+                self.irq_enabled = value & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = value,
+            0x8000..=0xBFFF => self.chr_banks[(addr as usize - 0x8000) / 0x800] = value,
+            0xC000..=0xDFFF => self.nametable_banks[(addr as usize - 0xC000) / 0x800] = value,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = value & 0x3F;
+                self.sound_disabled = value & 0x40 != 0;
+            }
+            0xE800..=0xEFFF => self.prg_banks[1] = value & 0x3F,
+            0xF000..=0xF7FF => self.prg_banks[2] = value & 0x3F,
+            0xF800..=0xFFFF => {
+                self.sound_address = value & 0x7F;
+                self.auto_increment = value & 0x80 != 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_banks(&self) -> [usize; CHR_WINDOWS] {
+        std::array::from_fn(|window| self.chr_banks[window] as usize * CHR_WINDOW_SIZE)
+    }
+
+    // CHR ROM nametables are answered in ppu_read
+    fn mirroring(&self) -> Option<ScreenMirroring> {
+        Some(ScreenMirroring::Custom(self.nametable_banks.map(|bank| bank & 1)))
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> Option<u8> {
+        if !(0x2000..=0x3EFF).contains(&addr) || self.chr_rom.is_empty() {
+            return None;
+        }
+        let bank = self.nametable_banks[(addr as usize >> 10) & 0b11];
+        if bank >= CIRAM_BANKS {
+            return None;
+        }
+        let offset = bank as usize * CHR_WINDOW_SIZE + (addr as usize & 0x3FF);
+        Some(self.chr_rom[offset % self.chr_rom.len()])
+    }
+
+    fn tick(&mut self) {
+        if self.irq_enabled && self.irq_counter < IRQ_COUNTER_MAX {
+            self.irq_counter += 1;
+            if self.irq_counter == IRQ_COUNTER_MAX {
+                self.irq_pending = true;
+            }
+        }
+
+        self.channel_timer += 1;
+        if self.channel_timer == CHANNEL_CYCLES {
+            self.channel_timer = 0;
+            let channel = self.current_channel;
+            self.update_channel(channel);
+            let first = 8 - self.enabled_channels();
+            self.current_channel = if channel <= first { 7 } else { channel - 1 };
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    // the chip plays one channel at a time, which averages out to the mean of the enabled channels
+    fn audio_output(&self) -> f32 {
+        if self.sound_disabled {
+            return 0.0;
+        }
+        let channels = self.enabled_channels();
+        let sum: i16 = self.channel_outputs[8 - channels..].iter().sum();
+        sum as f32 / channels as f32 * OUTPUT_LEVEL
+    }
+
+    fn needs_dot_rendering(&self) -> bool {
+        true
+    }
+}
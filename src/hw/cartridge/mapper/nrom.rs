@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::Mapper;
+
+// https://www.nesdev.org/wiki/NROM
+// 16 or 32 KiB of PRG ROM with no bank switching, 16 KiB boards are mirrored at $C000
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+}
+
+impl Nrom {
+    pub fn new(prg_rom: Vec<u8>) -> Self {
+        Nrom { prg_rom }
+    }
+}
+
+impl Mapper for Nrom {
+    fn peek(&self, addr: u16) -> Option<u8> {
+        if addr < 0x8000 || self.prg_rom.is_empty() {
+            return None;
+        }
+        Some(self.prg_rom[(addr - 0x8000) as usize % self.prg_rom.len()])
+    }
+
+    // ROM writes are ignored
+    fn write(&mut self, _addr: u16, _value: u8) {}
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::Mapper;
+use crate::hw::cartridge::nsf::{Nsf, IDLE_LOOP};
+
+const BANK_SIZE: usize = 0x1000;
+const PRG_RAM_SIZE: usize = 0x2000;
+// JMP $5FF0
+const IDLE_CODE: [u8; 3] = [0x4C, 0xF0, 0x5F];
+
+// https://www.nesdev.org/wiki/NSF#Bankswitching
+// the program data in 4 KiB banks at $8000-$FFFF, with RAM at $6000 and the player's idle loop
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NsfBoard {
+    rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    bankswitched: bool,
+    banks: [u8; 8],
+}
+
+impl NsfBoard {
+    pub fn new(nsf: &Nsf) -> Self {
+        let bankswitched = nsf.bankswitched();
+        // banked tunes are padded to the load address within a bank, the others to $8000
+        let padding = if bankswitched { nsf.load_addr as usize & 0x0FFF } else { nsf.load_addr.saturating_sub(0x8000) as usize };
+        let mut rom = vec![0; padding];
+        rom.extend_from_slice(&nsf.data);
+        rom.resize(rom.len().div_ceil(BANK_SIZE).max(1) * BANK_SIZE, 0);
+        let banks = if bankswitched { nsf.bankswitch } else { std::array::from_fn(|bank| bank as u8) };
+        NsfBoard { rom, prg_ram: vec![0; PRG_RAM_SIZE], bankswitched, banks }
+    }
+}
+
+impl Mapper for NsfBoard {
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            IDLE_LOOP..=0x5FF2 => Some(IDLE_CODE[(addr - IDLE_LOOP) as usize]),
+            0x6000..=0x7FFF => Some(self.prg_ram[(addr - 0x6000) as usize]),
+            0x8000..=0xFFFF => {
+                let bank = self.banks[(addr as usize - 0x8000) / BANK_SIZE] as usize;
+                let offset = bank * BANK_SIZE + addr as usize % BANK_SIZE;
+                self.rom.get(offset).copied().or(Some(0))
+            }
+            _ => None,
+        }
+    }
+
+    fn prg_ram_mapped(&self) -> bool {
+        true
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x5FF8..=0x5FFF if self.bankswitched => self.banks[(addr - 0x5FF8) as usize] = value,
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = value,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::vrc_irq::VrcIrq;
+use crate::hw::cartridge::mapper::{Mapper, CHR_WINDOWS, CHR_WINDOW_SIZE};
+use crate::hw::cartridge::ScreenMirroring;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const PRG_RAM_SIZE: usize = 0x2000;
+
+// https://www.nesdev.org/wiki/VRC2_and_VRC4
+// the same chip on boards that wire different CPU address lines to its two register select inputs
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Vrc4 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    // VRC2 has no IRQ, no PRG swap mode and only horizontal or vertical mirroring
+    vrc2: bool,
+    // VRC2a ignores the lowest bit of the CHR banks
+    chr_shift: u8,
+    // address lines of register select bit 0 and bit 1, more than one line when the board is ambiguous
+    select_lines: [u16; 2],
+    prg_banks: [u8; 2],
+    // $C000 is switchable and $8000 is fixed to the second last bank
+    prg_swap: bool,
+    chr_banks: [u16; CHR_WINDOWS],
+    mirroring: ScreenMirroring,
+    // VRC2 boards without PRG RAM have a 1 bit latch at $6000-$6FFF
+    latch: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    // https://www.nesdev.org/wiki/NES_2.0_submappers#021,_023,_025:_VRC2_/_VRC4
+    pub fn new(prg_rom: Vec<u8>, mapper: u16, submapper: u8) -> Self {
+        let (select_lines, vrc2) = match (mapper, submapper) {
+            (21, 1) => ([0x002, 0x004], false),
+            (21, 2) => ([0x040, 0x080], false),
+            (21, _) => ([0x042, 0x084], false),
+            (22, _) => ([0x002, 0x001], true),
+            (23, 1) => ([0x001, 0x002], false),
+            (23, 2) => ([0x004, 0x008], false),
+            (23, 3) => ([0x001, 0x002], true),
+            (23, _) => ([0x005, 0x00A], false),
+            (25, 1) => ([0x002, 0x001], false),
+            (25, 2) => ([0x008, 0x004], false),
+            (25, 3) => ([0x002, 0x001], true),
+            (_, _) => ([0x00A, 0x005], false),
+        };
+        Vrc4 {
+            prg_rom,
+            prg_ram: if vrc2 { vec![] } else { vec![0; PRG_RAM_SIZE] },
+            vrc2,
+            chr_shift: if mapper == 22 { 1 } else { 0 },
+            select_lines,
+            prg_banks: [0; 2],
+            prg_swap: false,
+            chr_banks: [0; CHR_WINDOWS],
+            mirroring: ScreenMirroring::Vertical,
+            latch: 0,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    // $x000-$x003, whatever address lines the board uses
+    fn register(&self, addr: u16) -> u16 {
+        let bit0 = addr & self.select_lines[0] != 0;
+        let bit1 = addr & self.select_lines[1] != 0;
+        (addr & 0xF000) | bit0 as u16 | (bit1 as u16) << 1
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let banks = self.prg_rom.len() / PRG_BANK_SIZE;
+        let second_last = banks.saturating_sub(2);
+        let bank = match (addr, self.prg_swap) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.prg_banks[0] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.prg_banks[1] as usize,
+            _ => banks.saturating_sub(1),
+        };
+        (bank * PRG_BANK_SIZE + addr as usize % PRG_BANK_SIZE) % self.prg_rom.len()
+    }
+
+    // each 1 KiB bank is written 4 bits at a time, $B000/$B001 for bank 0 up to $E002/$E003 for bank 7
+    fn write_chr_bank(&mut self, register: u16, value: u8) {
+        let bank = ((register >> 12) - 0xB) as usize * 2 + (register as usize & 0b10) / 2;
+        if register & 1 == 0 {
+            self.chr_banks[bank] = (self.chr_banks[bank] & 0x1F0) | (value & 0x0F) as u16;
+        } else {
+            let high = if self.vrc2 { value & 0x0F } else { value & 0x1F };
+            self.chr_banks[bank] = (self.chr_banks[bank] & 0x0F) | (high as u16) << 4;
+        }
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => Some(self.prg_ram[(addr - 0x6000) as usize]),
+            0x6000..=0x6FFF => Some(self.latch),
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => Some(self.prg_rom[self.prg_offset(addr)]),
+            _ => None,
+        }
+    }
+
+    fn prg_ram_mapped(&self) -> bool {
+        !self.prg_ram.is_empty()
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => self.prg_ram[(addr - 0x6000) as usize] = value,
+            0x6000..=0x6FFF => self.latch = value & 1,
+            0x8000..=0xFFFF => match self.register(addr) {
+                0x8000..=0x8003 => self.prg_banks[0] = value & 0x1F,
+                0x9000..=0x9003 if self.vrc2 => {
+                    self.mirroring = if value & 1 == 0 { ScreenMirroring::Vertical } else { ScreenMirroring::Horizontal };
+                }
+                0x9000 | 0x9001 => {
+                    self.mirroring = match value & 0b11 {
+                        0 => ScreenMirroring::Vertical,
+                        1 => ScreenMirroring::Horizontal,
+                        page => ScreenMirroring::Custom([page & 1; 4]),
+                    };
+                }
+                0x9002 => self.prg_swap = value & 0b10 != 0,
+                0xA000..=0xA003 => self.prg_banks[1] = value & 0x1F,
+                register @ 0xB000..=0xEFFF => self.write_chr_bank(register, value),
+                0xF000 if !self.vrc2 => self.irq.write_latch_low(value),
+                0xF001 if !self.vrc2 => self.irq.write_latch_high(value),
+                0xF002 if !self.vrc2 => self.irq.write_control(value),
+                0xF003 if !self.vrc2 => self.irq.acknowledge(),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn chr_banks(&self) -> [usize; CHR_WINDOWS] {
+        std::array::from_fn(|window| (self.chr_banks[window] >> self.chr_shift) as usize * CHR_WINDOW_SIZE)
+    }
+
+    fn mirroring(&self) -> Option<ScreenMirroring> {
+        Some(self.mirroring.clone())
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    // VRC2 has no IRQ
+    fn needs_dot_rendering(&self) -> bool {
+        !self.vrc2
+    }
+}
@@ -0,0 +1,261 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::vrc_irq::VrcIrq;
+use crate::hw::cartridge::mapper::{Mapper, APU_PULSE_LEVEL, CHR_WINDOWS, CHR_WINDOW_SIZE};
+use crate::hw::cartridge::ScreenMirroring;
+
+const PRG_RAM_SIZE: usize = 0x2000;
+// a VRC6 pulse at its full volume of 15 matches an APU pulse
+const OUTPUT_LEVEL: f32 = APU_PULSE_LEVEL / 15.0;
+
+// https://www.nesdev.org/wiki/VRC6
+// only the 1 KiB CHR mode of $B003, the one the released games use
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    // mapper 26 swaps the A0 and A1 register select lines
+    swapped_lines: bool,
+    prg_bank_16k: u8,
+    prg_bank_8k: u8,
+    chr_banks: [u8; CHR_WINDOWS],
+    mirroring: ScreenMirroring,
+    prg_ram_enabled: bool,
+    irq: VrcIrq,
+
+    pulses: [Vrc6Pulse; 2],
+    sawtooth: Vrc6Sawtooth,
+    halt: bool,
+    // $9003 speeds up every channel by 16 or 256 times, for testing the chip
+    frequency_shift: u8,
+}
+
+impl Vrc6 {
+    pub fn new(prg_rom: Vec<u8>, swapped_lines: bool) -> Self {
+        Vrc6 {
+            prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            swapped_lines,
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_banks: [0; CHR_WINDOWS],
+            mirroring: ScreenMirroring::Vertical,
+            prg_ram_enabled: false,
+            irq: VrcIrq::default(),
+            pulses: [Vrc6Pulse::default(), Vrc6Pulse::default()],
+            sawtooth: Vrc6Sawtooth::default(),
+            halt: false,
+            frequency_shift: 0,
+        }
+    }
+
+    fn register(&self, addr: u16) -> u16 {
+        if self.swapped_lines {
+            (addr & 0xF000) | (addr & 1) << 1 | (addr & 2) >> 1
+        } else {
+            addr & 0xF003
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let offset = match addr {
+            0x8000..=0xBFFF => self.prg_bank_16k as usize * 0x4000 + (addr & 0x3FFF) as usize,
+            0xC000..=0xDFFF => self.prg_bank_8k as usize * 0x2000 + (addr & 0x1FFF) as usize,
+            // the last 8 KiB are fixed
+            _ => self.prg_rom.len().saturating_sub(0x2000) + (addr & 0x1FFF) as usize,
+        };
+        offset % self.prg_rom.len()
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => Some(self.prg_ram[(addr - 0x6000) as usize]),
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => Some(self.prg_rom[self.prg_offset(addr)]),
+            _ => None,
+        }
+    }
+
+    fn prg_ram_mapped(&self) -> bool {
+        self.prg_ram_enabled
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => self.prg_ram[(addr - 0x6000) as usize] = value,
+            0x8000..=0xFFFF => match self.register(addr) {
+                0x8000..=0x8003 => self.prg_bank_16k = value & 0x0F,
+                register @ (0x9000..=0x9002 | 0xA000..=0xA002) => {
+                    let pulse = &mut self.pulses[(register >> 12) as usize - 0x9];
+                    match register & 0b11 {
+                        0 => pulse.write_control(value),
+                        1 => pulse.write_period_low(value),
+                        _ => pulse.write_period_high(value),
+                    }
+                }
+                0x9003 => {
+                    self.halt = value & 0b001 != 0;
+                    self.frequency_shift = if value & 0b100 != 0 { 8 } else if value & 0b010 != 0 { 4 } else { 0 };
+                }
+                0xB000 => self.sawtooth.rate = value & 0x3F,
+                0xB001 => self.sawtooth.write_period_low(value),
+                0xB002 => self.sawtooth.write_period_high(value),
+                0xB003 => {
+                    self.mirroring = match (value >> 2) & 0b11 {
+                        0 => ScreenMirroring::Vertical,
+                        1 => ScreenMirroring::Horizontal,
+                        page => ScreenMirroring::Custom([page & 1; 4]),
+                    };
+                    self.prg_ram_enabled = value & 0x80 != 0;
+                }
+                0xC000..=0xC003 => self.prg_bank_8k = value & 0x1F,
+                register @ 0xD000..=0xEFFF => {
+                    let window = ((register >> 12) - 0xD) as usize * 4 + (register & 0b11) as usize;
+                    self.chr_banks[window] = value;
+                }
+                0xF000 => self.irq.write_latch(value),
+                0xF001 => self.irq.write_control(value),
+                0xF002 => self.irq.acknowledge(),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn chr_banks(&self) -> [usize; CHR_WINDOWS] {
+        std::array::from_fn(|window| self.chr_banks[window] as usize * CHR_WINDOW_SIZE)
+    }
+
+    fn mirroring(&self) -> Option<ScreenMirroring> {
+        Some(self.mirroring.clone())
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+        if !self.halt {
+            for pulse in self.pulses.iter_mut() {
+                pulse.clock(self.frequency_shift);
+            }
+            self.sawtooth.clock(self.frequency_shift);
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn needs_dot_rendering(&self) -> bool {
+        true
+    }
+
+    // https://www.nesdev.org/wiki/VRC6_audio
+    // the chip mixes its channels linearly
+    fn audio_output(&self) -> f32 {
+        let sum = self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output();
+        sum as f32 * OUTPUT_LEVEL
+    }
+}
+
+// 16 step sequence with 8 duty cycles, clocked by the CPU
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    // plays the volume as a constant level
+    digitized: bool,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    // MDDD VVVV
+    fn write_control(&mut self, value: u8) {
+        self.digitized = value & 0x80 != 0;
+        self.duty = (value >> 4) & 0b111;
+        self.volume = value & 0x0F;
+    }
+
+    fn write_period_low(&mut self, value: u8) {
+        self.period = (self.period & 0x0F00) | value as u16;
+    }
+
+    // E--- PPPP
+    fn write_period_high(&mut self, value: u8) {
+        self.period = (self.period & 0x00FF) | ((value & 0x0F) as u16) << 8;
+        self.enabled = value & 0x80 != 0;
+        if !self.enabled {
+            self.step = 0;
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = (self.step + 1) % 16;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.digitized || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+// an accumulator that adds the rate every other clock and restarts after 7 additions
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Vrc6Sawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Sawtooth {
+    fn write_period_low(&mut self, value: u8) {
+        self.period = (self.period & 0x0F00) | value as u16;
+    }
+
+    fn write_period_high(&mut self, value: u8) {
+        self.period = (self.period & 0x00FF) | ((value & 0x0F) as u16) << 8;
+        self.enabled = value & 0x80 != 0;
+        if !self.enabled {
+            self.step = 0;
+            self.accumulator = 0;
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    // the top 5 bits of the accumulator
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
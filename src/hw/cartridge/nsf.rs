@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::CartridgeError;
+use crate::hw::region::Region;
+
+// https://www.nesdev.org/wiki/NSF
+const NSF_TAG: [u8; 5] = [0x4E, 0x45, 0x53, 0x4D, 0x1A];
+const HEADER_SIZE: usize = 0x80;
+const TEXT_SIZE: usize = 32;
+// the player's JMP to itself, where the CPU waits for the next INIT or PLAY call
+pub const IDLE_LOOP: u16 = 0x5FF0;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Nsf {
+    pub version: u8,
+    pub songs: u8,
+    // 1 based, like the header
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    // microseconds between PLAY calls
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    // initial 4 KiB banks at $8000-$FFFF, all zero when the tune isn't bankswitched
+    pub bankswitch: [u8; 8],
+    pub region: Region,
+    // VRC6, VRC7, FDS, MMC5, Namco 163 and Sunsoft 5B bits
+    pub expansion_audio: u8,
+    pub data: Vec<u8>,
+}
+
+impl Nsf {
+    pub fn is_nsf(raw: &[u8]) -> bool {
+        raw.starts_with(&NSF_TAG)
+    }
+
+    pub fn new(raw: &[u8]) -> Result<Self, CartridgeError> {
+        if !Self::is_nsf(raw) {
+            return Err(CartridgeError::InvalidHeader {
+                expected: NSF_TAG.to_vec(),
+                found: raw[..NSF_TAG.len().min(raw.len())].to_vec(),
+            });
+        }
+        if raw.len() <= HEADER_SIZE {
+            return Err(CartridgeError::TruncatedNsf(raw.len()));
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+        let text = |offset: usize| {
+            let field = &raw[offset..offset + TEXT_SIZE];
+            let end = field.iter().position(|&byte| byte == 0).unwrap_or(TEXT_SIZE);
+            String::from_utf8_lossy(&field[..end]).into_owned()
+        };
+
+        let mut data = raw[HEADER_SIZE..].to_vec();
+        // NSF2 can store metadata after the program data
+        let data_size = raw[0x7D] as usize | (raw[0x7E] as usize) << 8 | (raw[0x7F] as usize) << 16;
+        if raw[0x05] >= 2 && data_size > 0 {
+            data.truncate(data_size);
+        }
+
+        Ok(Nsf {
+            version: raw[0x05],
+            songs: raw[0x06],
+            starting_song: raw[0x07].max(1),
+            load_addr: word(0x08),
+            init_addr: word(0x0A),
+            play_addr: word(0x0C),
+            title: text(0x0E),
+            artist: text(0x2E),
+            copyright: text(0x4E),
+            ntsc_speed: word(0x6E),
+            bankswitch: raw[0x70..0x78].try_into().unwrap(),
+            pal_speed: word(0x78),
+            // dual region tunes play as NTSC
+            region: if raw[0x7A] & 0b11 == 0b01 { Region::PAL } else { Region::NTSC },
+            expansion_audio: raw[0x7B],
+            data,
+        })
+    }
+
+    pub fn bankswitched(&self) -> bool {
+        self.bankswitch.iter().any(|&bank| bank != 0)
+    }
+
+    pub fn play_speed(&self) -> u16 {
+        if self.region == Region::PAL { self.pal_speed } else { self.ntsc_speed }
+    }
+}
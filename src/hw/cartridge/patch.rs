@@ -0,0 +1,276 @@
+use thiserror::Error;
+use crate::hw::cartridge::hash::crc32;
+
+// https://zerosoft.zophar.net/ips.php
+const IPS_TAG: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+// a record at this offset would read as the end of the patch
+const IPS_EOF_OFFSET: usize = 0x454F46;
+const IPS_MAX_OFFSET: usize = 0xFFFFFF;
+const IPS_MAX_RECORD: usize = 0xFFFF;
+// https://www.romhacking.net/documents/392/
+const UPS_TAG: &[u8] = b"UPS1";
+// https://www.romhacking.net/documents/746/
+const BPS_TAG: &[u8] = b"BPS1";
+// UPS and BPS end with the CRC32 of the source, the target and the patch itself
+const FOOTER_SIZE: usize = 12;
+// far past the largest NES ROM or disk image, a bigger target size is a broken patch
+const MAX_TARGET_SIZE: usize = 0x2000000;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PatchError {
+    #[error("Invalid patch header")]
+    InvalidHeader,
+    #[error("Patch ends in the middle of a record")]
+    Truncated,
+    #[error("Patch offset {0:#X} is out of range")]
+    OffsetOutOfRange(usize),
+    #[error("Patch target size of {0} bytes is too large")]
+    TargetTooLarge(usize),
+    #[error("Patch {checksum} checksum mismatch (expected {expected:#010X}, got {found:#010X})")]
+    ChecksumMismatch {
+        checksum: &'static str,
+        expected: u32,
+        found: u32,
+    },
+}
+
+// picks the format from the patch header
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_TAG) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(UPS_TAG) {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(BPS_TAG) {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::InvalidHeader)
+    }
+}
+
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if !patch.starts_with(IPS_TAG) {
+        return Err(PatchError::InvalidHeader);
+    }
+    let mut output = rom.to_vec();
+    let mut position = IPS_TAG.len();
+    let take = |position: &mut usize, size: usize| -> Result<&[u8], PatchError> {
+        let bytes = patch.get(*position..*position + size).ok_or(PatchError::Truncated)?;
+        *position += size;
+        Ok(bytes)
+    };
+
+    loop {
+        let record = take(&mut position, 3)?;
+        if record == IPS_EOF {
+            break;
+        }
+        let offset = (record[0] as usize) << 16 | (record[1] as usize) << 8 | record[2] as usize;
+        let size = take(&mut position, 2)?;
+        let size = (size[0] as usize) << 8 | size[1] as usize;
+        let data = if size == 0 {
+            // run length encoded
+            let run = take(&mut position, 3)?;
+            vec![run[2]; (run[0] as usize) << 8 | run[1] as usize]
+        } else {
+            take(&mut position, size)?.to_vec()
+        };
+        if output.len() < offset + data.len() {
+            output.resize(offset + data.len(), 0);
+        }
+        output[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    // some patchers append the size to truncate the output to
+    if let Ok(size) = take(&mut position, 3) {
+        output.truncate((size[0] as usize) << 16 | (size[1] as usize) << 8 | size[2] as usize);
+    }
+    Ok(output)
+}
+
+// records every run of bytes that differs between the two images
+pub fn create_ips(original: &[u8], modified: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut patch = IPS_TAG.to_vec();
+    let mut offset = 0;
+    while offset < modified.len() {
+        if original.get(offset) == Some(&modified[offset]) {
+            offset += 1;
+            continue;
+        }
+        let mut start = offset;
+        if start == IPS_EOF_OFFSET {
+            start -= 1;
+        }
+        if start > IPS_MAX_OFFSET {
+            return Err(PatchError::OffsetOutOfRange(start));
+        }
+        let mut end = offset;
+        while end < modified.len() && end - start < IPS_MAX_RECORD && original.get(end) != Some(&modified[end]) {
+            end += 1;
+        }
+        patch.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        patch.extend_from_slice(&((end - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&modified[start..end]);
+        offset = end;
+    }
+    patch.extend_from_slice(IPS_EOF);
+    if modified.len() < original.len() {
+        patch.extend_from_slice(&(modified.len() as u32).to_be_bytes()[1..]);
+    }
+    Ok(patch)
+}
+
+pub fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = PatchReader::new(patch, UPS_TAG, rom)?;
+    // the source size, the source checksum already covers it
+    reader.number()?;
+    let target_size = reader.target_size()?;
+
+    // records are the distance from the end of the last one and the bytes to XOR up to a zero
+    let mut output = rom.to_vec();
+    output.resize(target_size.max(rom.len()), 0);
+    let mut offset = 0;
+    while !reader.at_footer() {
+        offset += reader.number()?;
+        loop {
+            let byte = reader.bytes(1)?[0];
+            if byte == 0 {
+                break;
+            }
+            *output.get_mut(offset).ok_or(PatchError::OffsetOutOfRange(offset))? ^= byte;
+            offset += 1;
+        }
+        offset += 1;
+    }
+    output.truncate(target_size);
+    reader.check_target(&output)?;
+    Ok(output)
+}
+
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = PatchReader::new(patch, BPS_TAG, rom)?;
+    reader.number()?;
+    let target_size = reader.target_size()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+
+    let mut output = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0usize, 0usize);
+    while !reader.at_footer() {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+        if length > target_size - output.len() {
+            return Err(PatchError::OffsetOutOfRange(output.len()));
+        }
+        match action & 0b11 {
+            // SourceRead, the source bytes at the same position
+            0 => {
+                let start = output.len();
+                output.extend_from_slice(rom.get(start..start + length).ok_or(PatchError::OffsetOutOfRange(start))?);
+            }
+            // TargetRead, bytes from the patch
+            1 => output.extend_from_slice(reader.bytes(length)?),
+            // SourceCopy, from anywhere in the source
+            2 => {
+                source_offset = reader.relative_offset(source_offset)?;
+                let bytes = rom.get(source_offset..source_offset + length).ok_or(PatchError::OffsetOutOfRange(source_offset))?;
+                output.extend_from_slice(bytes);
+                source_offset += length;
+            }
+            // TargetCopy, from the output written so far, a byte at a time since the runs can overlap
+            _ => {
+                target_offset = reader.relative_offset(target_offset)?;
+                for _ in 0..length {
+                    let byte = *output.get(target_offset).ok_or(PatchError::OffsetOutOfRange(target_offset))?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    if output.len() != target_size {
+        return Err(PatchError::Truncated);
+    }
+    reader.check_target(&output)?;
+    Ok(output)
+}
+
+// walks a UPS or BPS patch after checking its own and the source's checksums
+struct PatchReader<'a> {
+    patch: &'a [u8],
+    position: usize,
+    target_crc: u32,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(patch: &'a [u8], tag: &[u8], rom: &[u8]) -> Result<Self, PatchError> {
+        if !patch.starts_with(tag) {
+            return Err(PatchError::InvalidHeader);
+        }
+        if patch.len() < tag.len() + FOOTER_SIZE {
+            return Err(PatchError::Truncated);
+        }
+        let footer = patch.len() - FOOTER_SIZE;
+        let word = |offset: usize| u32::from_le_bytes(patch[offset..offset + 4].try_into().unwrap());
+        let (source_crc, target_crc, patch_crc) = (word(footer), word(footer + 4), word(footer + 8));
+
+        let found = crc32(&patch[..footer + 8]);
+        if found != patch_crc {
+            return Err(PatchError::ChecksumMismatch { checksum: "patch", expected: patch_crc, found });
+        }
+        let found = crc32(rom);
+        if found != source_crc {
+            return Err(PatchError::ChecksumMismatch { checksum: "source", expected: source_crc, found });
+        }
+        Ok(PatchReader { patch: &patch[..footer], position: tag.len(), target_crc })
+    }
+
+    fn at_footer(&self) -> bool {
+        self.position >= self.patch.len()
+    }
+
+    fn bytes(&mut self, size: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self.patch.get(self.position..self.position + size).ok_or(PatchError::Truncated)?;
+        self.position += size;
+        Ok(bytes)
+    }
+
+    // variable length numbers, 7 bits per byte with the high bit on the last one
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let (mut value, mut shift) = (0usize, 1usize);
+        loop {
+            let byte = self.bytes(1)?[0] as usize;
+            value = value.checked_add((byte & 0x7F).checked_mul(shift).ok_or(PatchError::Truncated)?)
+                .ok_or(PatchError::Truncated)?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::Truncated)?;
+            value += shift;
+        }
+    }
+
+    fn target_size(&mut self) -> Result<usize, PatchError> {
+        let size = self.number()?;
+        if size > MAX_TARGET_SIZE {
+            return Err(PatchError::TargetTooLarge(size));
+        }
+        Ok(size)
+    }
+
+    // BPS copy offsets are signed distances from the previous copy, the low bit is the sign
+    fn relative_offset(&mut self, offset: usize) -> Result<usize, PatchError> {
+        let number = self.number()?;
+        let distance = number >> 1;
+        let result = if number & 1 != 0 { offset.checked_sub(distance) } else { offset.checked_add(distance) };
+        result.ok_or(PatchError::OffsetOutOfRange(offset))
+    }
+
+    fn check_target(&self, output: &[u8]) -> Result<(), PatchError> {
+        let found = crc32(output);
+        if found != self.target_crc {
+            return Err(PatchError::ChecksumMismatch { checksum: "target", expected: self.target_crc, found });
+        }
+        Ok(())
+    }
+}
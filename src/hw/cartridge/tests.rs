@@ -1,6 +1,14 @@
 #[cfg(test)]
 mod cartridge_tests {
-    use crate::hw::cartridge::{Cartridge, CartridgeError, ScreenMirroring};
+    use crate::hw::bus::Bus;
+    use crate::hw::cartridge::{fds, unif, Cartridge, CartridgeError, ScreenMirroring};
+    use crate::hw::cartridge::database::GameDatabase;
+    use crate::hw::cartridge::hash::{crc32, sha1};
+    use crate::hw::cartridge::mapper::MapperBoard;
+    use crate::hw::cartridge::patch::{apply_bps, apply_ips, apply_patch, apply_ups, create_ips, PatchError};
+    use crate::hw::memory::{CpuBus, Memory};
+    use crate::hw::region::Region;
+    use crate::rendering::palette;
 
     fn create_valid_ines_header() -> Vec<u8> {
         vec![
@@ -64,9 +72,18 @@ mod cartridge_tests {
     }
 
     #[test]
-    fn test_nes2_format_not_supported() {
+    fn test_truncated_rom() {
+        let error = |data: Vec<u8>| Cartridge::new(data).unwrap_err().downcast::<CartridgeError>().unwrap();
+        assert!(matches!(error(vec![0x4E, 0x45]), CartridgeError::InvalidHeader { found, .. } if found == [0x4E, 0x45]));
+        assert!(matches!(error(vec![0x4E, 0x45, 0x53, 0x1A, 0x01]), CartridgeError::TruncatedRom { expected: 16, found: 5 }));
+        let data = create_test_cartridge_data(create_valid_ines_header(), 16384, 4096);
+        assert!(matches!(error(data), CartridgeError::TruncatedRom { expected: 24592, found: 20496 }));
+    }
+
+    #[test]
+    fn test_unsupported_ines_version() {
         let mut header = create_valid_ines_header();
-        header[7] = 0b0000_1000;
+        header[7] = 0b0000_0100;
         let data = create_test_cartridge_data(header, 16384, 8192);
 
         let result = Cartridge::new(data);
@@ -81,6 +98,79 @@ mod cartridge_tests {
         }
     }
 
+    #[test]
+    fn test_nes2_header() {
+        let mut header = create_valid_ines_header();
+        header[6] = 0b0001_0000;
+        header[7] = 0b0010_1000;
+        header[8] = 0b0011_0001;
+        let data = create_test_cartridge_data(header, 16384, 8192);
+
+        let cartridge = Cartridge::new(data).unwrap();
+        assert_eq!(cartridge.mapper, 0x121);
+        assert_eq!(cartridge.submapper, 3);
+        assert_eq!(cartridge.region, Region::NTSC);
+    }
+
+    #[test]
+    fn test_nes2_timing() {
+        let test_cases = vec![
+            (0, Region::NTSC),
+            (1, Region::PAL),
+            (2, Region::NTSC),
+            (3, Region::Dendy),
+        ];
+
+        for (timing, expected_region) in test_cases {
+            let mut header = create_valid_ines_header();
+            header[7] = 0b0000_1000;
+            header[12] = timing;
+            let data = create_test_cartridge_data(header, 16384, 8192);
+
+            let cartridge = Cartridge::new(data).unwrap();
+            assert_eq!(cartridge.region, expected_region);
+        }
+    }
+
+    #[test]
+    fn test_ines1_defaults_to_ntsc() {
+        let mut header = create_valid_ines_header();
+        header[12] = 1;
+        let data = create_test_cartridge_data(header, 16384, 8192);
+
+        let cartridge = Cartridge::new(data).unwrap();
+        assert_eq!(cartridge.region, Region::NTSC);
+    }
+
+    #[test]
+    fn test_ines_battery() {
+        let mut header = create_valid_ines_header();
+        header[6] = 0b0000_0010;
+        let cartridge = Cartridge::new(create_test_cartridge_data(header, 16384, 8192)).unwrap();
+        assert!(cartridge.battery);
+        let cartridge = Cartridge::new(create_test_cartridge_data(create_valid_ines_header(), 16384, 8192)).unwrap();
+        assert!(!cartridge.battery);
+    }
+
+    #[test]
+    fn test_ines_battery_keeps_mirroring() {
+        // battery backed boards used to be rejected when their mirroring was horizontal
+        let mut header = create_valid_ines_header();
+        header[6] = 0b0000_0010;
+        let cartridge = Cartridge::new(create_test_cartridge_data(header.clone(), 16384, 8192)).unwrap();
+        assert_eq!(cartridge.screen_mirroring, ScreenMirroring::Horizontal);
+
+        header[6] = 0b0000_0011;
+        let cartridge = Cartridge::new(create_test_cartridge_data(header.clone(), 16384, 8192)).unwrap();
+        assert_eq!(cartridge.screen_mirroring, ScreenMirroring::Vertical);
+        assert!(cartridge.battery);
+
+        header[6] = 0b0000_1010;
+        let cartridge = Cartridge::new(create_test_cartridge_data(header, 16384, 8192)).unwrap();
+        assert_eq!(cartridge.screen_mirroring, ScreenMirroring::FourScreen);
+        assert!(cartridge.battery);
+    }
+
     #[test]
     fn test_vertical_mirroring() {
         let mut header = create_valid_ines_header();
@@ -234,7 +324,7 @@ mod cartridge_tests {
         assert!(error_msg.contains("got"));
 
         let version_error = CartridgeError::UnsupportedINESVersion;
-        assert_eq!(format!("{}", version_error), "NES2.0 format is not supported");
+        assert_eq!(format!("{}", version_error), "Unsupported INES version");
 
         let mirroring_error = CartridgeError::IllegalScreenMirroring;
         assert_eq!(format!("{}", mirroring_error), "Illegal screen mirroring found");
@@ -273,4 +363,1351 @@ mod cartridge_tests {
         assert_eq!(cartridge.mapper, 0);
         assert_eq!(cartridge.screen_mirroring, ScreenMirroring::Horizontal);
     }
-}
\ No newline at end of file
+
+    // PRG is filled with the index of each 8 KiB bank, CHR with the index of each 1 KiB bank
+    fn create_mapper_rom(mapper: u8, prg_pages: u8, chr_pages: u8) -> Vec<u8> {
+        let mut header = create_valid_ines_header();
+        header[4] = prg_pages;
+        header[5] = chr_pages;
+        header[6] = (mapper & 0x0F) << 4;
+        header[7] = mapper & 0xF0;
+
+        let mut data = header;
+        for i in 0..prg_pages as usize * Cartridge::PRG_ROM_PAGE_SIZE {
+            data.push((i / 0x2000) as u8);
+        }
+        for i in 0..chr_pages as usize * Cartridge::CHR_ROM_PAGE_SIZE {
+            data.push((i / 0x400) as u8);
+        }
+        data
+    }
+
+    fn create_mapper_bus<'a>(mapper: u8, prg_pages: u8, chr_pages: u8) -> Bus<'a> {
+        let cartridge = Cartridge::new(create_mapper_rom(mapper, prg_pages, chr_pages)).unwrap();
+        Bus::new(Some(cartridge), move |_, _| {})
+    }
+
+    fn create_submapper_bus<'a>(mapper: u8, submapper: u8, prg_pages: u8, chr_pages: u8) -> Bus<'a> {
+        let mut data = create_mapper_rom(mapper, prg_pages, chr_pages);
+        data[7] |= 0b0000_1000;
+        data[8] = submapper << 4;
+        Bus::new(Some(Cartridge::new(data).unwrap()), move |_, _| {})
+    }
+
+    #[test]
+    fn test_unsupported_mapper() {
+        let cartridge = Cartridge::new(create_mapper_rom(255, 1, 1)).unwrap();
+        let err = MapperBoard::new(&cartridge).unwrap_err();
+        assert!(matches!(err, CartridgeError::UnsupportedMapper(255)));
+    }
+
+    #[test]
+    fn test_nrom_16k_mirrored() {
+        let bus = &mut create_mapper_bus(0, 1, 1);
+        assert_eq!(bus.mem_peek(0x8000), 0);
+        assert_eq!(bus.mem_peek(0xE000), 1);
+    }
+
+    #[test]
+    fn test_mmc2_prg_banks() {
+        let bus = &mut create_mapper_bus(9, 4, 2);
+        bus.mem_write(0xA000, 2);
+        assert_eq!(bus.mem_peek(0x8000), 2);
+        // the last three 8 KiB banks are fixed
+        assert_eq!(bus.mem_peek(0xA000), 5);
+        assert_eq!(bus.mem_peek(0xC000), 6);
+        assert_eq!(bus.mem_peek(0xE000), 7);
+    }
+
+    #[test]
+    fn test_mmc2_chr_latches() {
+        let bus = &mut create_mapper_bus(9, 4, 2);
+        assert!(bus.ppu.dot_rendering());
+        // 4 KiB banks, 4 windows of 1 KiB each
+        bus.mem_write(0xB000, 0);
+        bus.mem_write(0xC000, 1);
+        bus.mem_write(0xD000, 2);
+        bus.mem_write(0xE000, 3);
+        assert_eq!(bus.ppu.chr_tile(0x0000)[0], 4);
+        assert_eq!(bus.ppu.chr_tile(0x1000)[0], 12);
+
+        // fetching the first row of tile $FD flips the left latch
+        bus.mem_write(0x2006, 0x0F);
+        bus.mem_write(0x2006, 0xD8);
+        bus.mem_read(0x2007);
+        bus.mem_read(0x0000);
+        assert_eq!(bus.ppu.chr_tile(0x0000)[0], 0);
+        assert_eq!(bus.ppu.chr_tile(0x1000)[0], 12);
+
+        bus.mem_write(0x2006, 0x1F);
+        bus.mem_write(0x2006, 0xDC);
+        bus.mem_read(0x2007);
+        bus.mem_read(0x0000);
+        assert_eq!(bus.ppu.chr_tile(0x1C00)[0], 11);
+    }
+
+    #[test]
+    fn test_mmc2_mirroring() {
+        let bus = &mut create_mapper_bus(9, 4, 2);
+        bus.mem_write(0xF000, 1);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Horizontal);
+        bus.mem_write(0xF000, 0);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Vertical);
+    }
+
+    #[test]
+    fn test_mmc4_prg_banks_and_ram() {
+        let bus = &mut create_mapper_bus(10, 4, 2);
+        bus.mem_write(0xA000, 1);
+        assert_eq!(bus.mem_peek(0x8000), 2);
+        assert_eq!(bus.mem_peek(0xA000), 3);
+        // the last 16 KiB are fixed
+        assert_eq!(bus.mem_peek(0xC000), 6);
+
+        bus.mem_write(0x6000, 0x42);
+        assert_eq!(bus.mem_peek(0x6000), 0x42);
+    }
+
+    #[test]
+    fn test_mmc4_latch_reacts_to_whole_tile_row() {
+        let bus = &mut create_mapper_bus(10, 4, 2);
+        bus.mem_write(0xB000, 0);
+        bus.mem_write(0xC000, 1);
+        bus.mem_write(0x2006, 0x0F);
+        bus.mem_write(0x2006, 0xDA);
+        bus.mem_read(0x2007);
+        bus.mem_read(0x0000);
+        assert_eq!(bus.ppu.chr_tile(0x0000)[0], 0);
+    }
+
+    fn run_to_vblank(bus: &mut Bus) {
+        while bus.ppu.scanline() == 241 {
+            bus.mem_read(0x0000);
+        }
+        while bus.ppu.scanline() != 241 {
+            bus.mem_read(0x0000);
+        }
+    }
+
+    fn pixel(bus: &Bus, x: usize, y: usize) -> (u8, u8, u8) {
+        let base = (y * 256 + x) * 3;
+        let data = &bus.ppu.current_frame.data;
+        (data[base], data[base + 1], data[base + 2])
+    }
+
+    fn write_palette(bus: &mut Bus, index: u8, color: u8) {
+        bus.mem_write(0x2006, 0x3F);
+        bus.mem_write(0x2006, index);
+        bus.mem_write(0x2007, color);
+    }
+
+    #[test]
+    fn test_mmc5_multiplier() {
+        let bus = &mut create_mapper_bus(5, 8, 8);
+        bus.mem_write(0x5205, 12);
+        bus.mem_write(0x5206, 34);
+        assert_eq!(bus.mem_read(0x5205), 0x98);
+        assert_eq!(bus.mem_read(0x5206), 0x01);
+    }
+
+    #[test]
+    fn test_mmc5_prg_modes() {
+        let bus = &mut create_mapper_bus(5, 8, 8);
+        // 8 KiB mode with the last bank at $E000 on power on
+        assert_eq!(bus.mem_peek(0xE000), 15);
+        bus.mem_write(0x5114, 0x82);
+        assert_eq!(bus.mem_peek(0x8000), 2);
+
+        bus.mem_write(0x5100, 1);
+        bus.mem_write(0x5115, 0x85);
+        bus.mem_write(0x5117, 0x09);
+        assert_eq!(bus.mem_peek(0x8000), 4);
+        assert_eq!(bus.mem_peek(0xA000), 5);
+        assert_eq!(bus.mem_peek(0xC000), 8);
+        assert_eq!(bus.mem_peek(0xE000), 9);
+
+        bus.mem_write(0x5100, 0);
+        bus.mem_write(0x5117, 0x0B);
+        assert_eq!(bus.mem_peek(0x8000), 8);
+        assert_eq!(bus.mem_peek(0xE000), 11);
+    }
+
+    #[test]
+    fn test_mmc5_prg_ram_protect_and_banks() {
+        let bus = &mut create_mapper_bus(5, 8, 8);
+        bus.mem_write(0x5113, 1);
+        bus.mem_write(0x6000, 0x42);
+        assert_eq!(bus.mem_peek(0x6000), 0);
+
+        bus.mem_write(0x5102, 0b10);
+        bus.mem_write(0x5103, 0b01);
+        bus.mem_write(0x6000, 0x42);
+        // a RAM bank mapped into the ROM area
+        bus.mem_write(0x5114, 0x01);
+        assert_eq!(bus.mem_peek(0x8000), 0x42);
+    }
+
+    #[test]
+    fn test_mmc5_exram_cpu_access() {
+        let bus = &mut create_mapper_bus(5, 8, 8);
+        bus.mem_write(0x5104, 2);
+        bus.mem_write(0x5C10, 0x42);
+        assert_eq!(bus.mem_read(0x5C10), 0x42);
+        // read only
+        bus.mem_write(0x5104, 3);
+        bus.mem_write(0x5C10, 0x24);
+        assert_eq!(bus.mem_read(0x5C10), 0x42);
+        // nametable modes aren't readable and write 0 outside of rendering
+        bus.mem_write(0x5104, 0);
+        bus.mem_write(0x5C10, 0x24);
+        bus.mem_write(0x5104, 2);
+        assert_eq!(bus.mem_read(0x5C10), 0);
+    }
+
+    #[test]
+    fn test_mmc5_chr_sets() {
+        let bus = &mut create_mapper_bus(5, 8, 8);
+        bus.mem_write(0x5101, 3);
+        for i in 0..8 {
+            bus.mem_write(0x5120 + i, 8 + i as u8);
+        }
+        assert_eq!(bus.ppu.chr_tile(0x1400)[0], 13);
+
+        // set B covers 4 KiB and is repeated in both pattern tables
+        for i in 0..4 {
+            bus.mem_write(0x5128 + i, 20 + i as u8);
+        }
+        assert_eq!(bus.ppu.chr_tile(0x0400)[0], 21);
+        assert_eq!(bus.ppu.chr_tile(0x1400)[0], 21);
+
+        bus.mem_write(0x5101, 1);
+        bus.mem_write(0x5130, 1);
+        bus.mem_write(0x512B, 0);
+        // 4 KiB bank $100
+        assert_eq!(bus.ppu.chr_tile(0x0000)[0], 0);
+    }
+
+    #[test]
+    fn test_mmc5_scanline_irq() {
+        let bus = &mut create_mapper_bus(5, 8, 8);
+        // no APU frame IRQ
+        bus.mem_write(0x4017, 0x40);
+        bus.mem_write(0x5203, 10);
+        bus.mem_write(0x5204, 0x80);
+        bus.mem_write(0x2001, 0b0001_1000);
+        run_to_vblank(bus);
+        // acknowledge the IRQ of the partial first frame
+        bus.mem_read(0x5204);
+
+        while !bus.poll_irq_status() {
+            bus.mem_read(0x0000);
+        }
+        // the pre-render line is counted as scanline 0
+        assert_eq!(bus.ppu.scanline(), 9);
+        assert_eq!(bus.mem_read(0x5204), 0xC0);
+        assert!(!bus.poll_irq_status());
+
+        // in frame goes away once the PPU stops fetching
+        run_to_vblank(bus);
+        bus.mem_read(0x0000);
+        assert_eq!(bus.mem_read(0x5204), 0x00);
+    }
+
+    #[test]
+    fn test_mmc5_fill_mode() {
+        let bus = &mut create_mapper_bus(5, 8, 8);
+        // every row of the background tiles in CHR bank 1 is $01, pixel 7 has color 3
+        bus.mem_write(0x5101, 3);
+        bus.mem_write(0x5128, 1);
+        bus.mem_write(0x5105, 0xFF);
+        bus.mem_write(0x5106, 0);
+        bus.mem_write(0x5107, 2);
+        write_palette(bus, 0x00, 0x0F);
+        write_palette(bus, 0x0B, 0x30);
+        bus.mem_write(0x2001, 0b0000_1010);
+        run_to_vblank(bus);
+        run_to_vblank(bus);
+
+        assert_eq!(pixel(bus, 7, 100), palette::SYSTEM_PALLETE[0x30]);
+        assert_eq!(pixel(bus, 6, 100), palette::SYSTEM_PALLETE[0x0F]);
+    }
+
+    #[test]
+    fn test_mmc5_extended_attributes() {
+        let bus = &mut create_mapper_bus(5, 8, 8);
+        // palette 2 and 4 KiB CHR bank 3, whose first 1 KiB has rows of $0C
+        bus.mem_write(0x5104, 2);
+        for addr in 0x5C00..0x5FC0 {
+            bus.mem_write(addr, 0x80 | 3);
+        }
+        bus.mem_write(0x5104, 1);
+        write_palette(bus, 0x00, 0x0F);
+        write_palette(bus, 0x0B, 0x30);
+        bus.mem_write(0x2001, 0b0000_1010);
+        run_to_vblank(bus);
+        run_to_vblank(bus);
+
+        assert_eq!(pixel(bus, 4, 100), palette::SYSTEM_PALLETE[0x30]);
+        assert_eq!(pixel(bus, 5, 100), palette::SYSTEM_PALLETE[0x30]);
+        assert_eq!(pixel(bus, 7, 100), palette::SYSTEM_PALLETE[0x0F]);
+    }
+
+    #[test]
+    fn test_mmc5_vertical_split() {
+        let bus = &mut create_mapper_bus(5, 8, 8);
+        // tile 0 of 4 KiB bank 3 is solid $0C rows, the background set B points at bank 1 with $01 rows
+        bus.mem_write(0x5101, 3);
+        bus.mem_write(0x5128, 1);
+        bus.mem_write(0x5104, 2);
+        for addr in 0x5C00..0x6000 {
+            bus.mem_write(addr, 0);
+        }
+        bus.mem_write(0x5104, 0);
+        // the left 4 tiles come from ExRAM, scrolled by 8 lines
+        bus.mem_write(0x5200, 0x80 | 4);
+        bus.mem_write(0x5201, 8);
+        bus.mem_write(0x5202, 3);
+        write_palette(bus, 0x00, 0x0F);
+        write_palette(bus, 0x03, 0x30);
+        bus.mem_write(0x2001, 0b0000_1010);
+        run_to_vblank(bus);
+        run_to_vblank(bus);
+
+        assert_eq!(pixel(bus, 4, 100), palette::SYSTEM_PALLETE[0x30]);
+        assert_eq!(pixel(bus, 7, 100), palette::SYSTEM_PALLETE[0x0F]);
+        assert_eq!(pixel(bus, 36, 100), palette::SYSTEM_PALLETE[0x0F]);
+        assert_eq!(pixel(bus, 39, 100), palette::SYSTEM_PALLETE[0x30]);
+    }
+
+    #[test]
+    fn test_vrc4_prg_banks_and_swap() {
+        // VRC4e selects registers with A2 and A3
+        let bus = &mut create_submapper_bus(23, 2, 8, 8);
+        bus.mem_write(0x8000, 3);
+        bus.mem_write(0xA000, 5);
+        assert_eq!(bus.mem_peek(0x8000), 3);
+        assert_eq!(bus.mem_peek(0xA000), 5);
+        assert_eq!(bus.mem_peek(0xC000), 14);
+        assert_eq!(bus.mem_peek(0xE000), 15);
+
+        bus.mem_write(0x9008, 0b10);
+        assert_eq!(bus.mem_peek(0x8000), 14);
+        assert_eq!(bus.mem_peek(0xC000), 3);
+    }
+
+    #[test]
+    fn test_vrc4_chr_banks_and_mirroring() {
+        let bus = &mut create_submapper_bus(23, 2, 8, 32);
+        // bank 0 low and high nibble, then bank 7 at $E00C
+        bus.mem_write(0xB000, 0x02);
+        bus.mem_write(0xB004, 0x01);
+        bus.mem_write(0xE008, 0x05);
+        bus.mem_write(0xE00C, 0x00);
+        assert_eq!(bus.ppu.chr_tile(0x0000)[0], 0x12);
+        assert_eq!(bus.ppu.chr_tile(0x1C00)[0], 0x05);
+
+        bus.mem_write(0x9000, 1);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Horizontal);
+        bus.mem_write(0x9000, 3);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Custom([1; 4]));
+    }
+
+    #[test]
+    fn test_vrc2a_chr_ignores_low_bit() {
+        let bus = &mut create_mapper_bus(22, 8, 8);
+        bus.mem_write(0xB000, 0x05);
+        assert_eq!(bus.ppu.chr_tile(0x0000)[0], 2);
+        bus.mem_write(0x9000, 1);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_vrc4_ambiguous_submapper_uses_both_lines() {
+        let bus = &mut create_mapper_bus(25, 8, 8);
+        // the low nibble of bank 1 is $B001 on VRC4b and $B004 on VRC4d
+        bus.mem_write(0xB004, 0x04);
+        assert_eq!(bus.ppu.chr_tile(0x0400)[0], 0x04);
+        bus.mem_write(0xB001, 0x05);
+        assert_eq!(bus.ppu.chr_tile(0x0400)[0], 0x05);
+    }
+
+    #[test]
+    fn test_vrc_irq_cycle_mode() {
+        let bus = &mut create_submapper_bus(21, 1, 8, 8);
+        bus.mem_write(0x4017, 0x40);
+        // latch $FC, VRC4a selects with A1 and A2
+        bus.mem_write(0xF000, 0x0C);
+        bus.mem_write(0xF002, 0x0F);
+        bus.mem_write(0xF004, 0b110);
+        for _ in 0..3 {
+            bus.mem_read(0x0000);
+            assert!(!bus.poll_irq_status());
+        }
+        bus.mem_read(0x0000);
+        assert!(bus.poll_irq_status());
+        bus.mem_write(0xF006, 0);
+        assert!(!bus.poll_irq_status());
+    }
+
+    #[test]
+    fn test_vrc_irq_scanline_mode() {
+        let bus = &mut create_mapper_bus(24, 8, 8);
+        bus.mem_write(0x4017, 0x40);
+        bus.mem_write(0xF000, 0xFE);
+        bus.mem_write(0xF001, 0b010);
+        let mut cycles = 0;
+        while !bus.poll_irq_status() {
+            bus.mem_read(0x0000);
+            cycles += 1;
+        }
+        // two scanlines of 113.667 CPU cycles
+        assert_eq!(cycles, 228);
+    }
+
+    #[test]
+    fn test_vrc6_banks() {
+        let bus = &mut create_mapper_bus(24, 8, 32);
+        bus.mem_write(0x8000, 2);
+        bus.mem_write(0xC000, 9);
+        assert_eq!(bus.mem_peek(0x8000), 4);
+        assert_eq!(bus.mem_peek(0xA000), 5);
+        assert_eq!(bus.mem_peek(0xC000), 9);
+        assert_eq!(bus.mem_peek(0xE000), 15);
+
+        bus.mem_write(0xD001, 0x21);
+        bus.mem_write(0xE003, 0x42);
+        assert_eq!(bus.ppu.chr_tile(0x0400)[0], 0x21);
+        assert_eq!(bus.ppu.chr_tile(0x1C00)[0], 0x42);
+
+        bus.mem_write(0xB003, 0x84);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Horizontal);
+        bus.mem_write(0x6000, 0x42);
+        assert_eq!(bus.mem_peek(0x6000), 0x42);
+    }
+
+    #[test]
+    fn test_vrc6b_swaps_register_lines() {
+        let bus = &mut create_mapper_bus(26, 8, 32);
+        // $D001 on VRC6a is $D002 on VRC6b
+        bus.mem_write(0xD002, 0x21);
+        assert_eq!(bus.ppu.chr_tile(0x0400)[0], 0x21);
+    }
+
+    #[test]
+    fn test_vrc6_audio() {
+        let bus = &mut create_mapper_bus(24, 8, 8);
+        bus.mem_read(0x0000);
+        // the APU channels sit at a constant level
+        let silence = bus.apu.output();
+        let mut levels = vec![];
+        // a square wave at full volume with a 50% duty
+        bus.mem_write(0x9000, 0x7F);
+        bus.mem_write(0x9001, 0x01);
+        bus.mem_write(0x9002, 0x80);
+        for _ in 0..64 {
+            bus.mem_read(0x0000);
+            levels.push(bus.apu.output() - silence);
+        }
+        let high = levels.iter().cloned().fold(0.0, f32::max);
+        assert!(high > 0.14 && high < 0.16);
+        assert!(levels.contains(&0.0));
+
+        // the sawtooth ramps up between resets
+        bus.mem_write(0x9002, 0x00);
+        bus.mem_write(0xB000, 0x20);
+        bus.mem_write(0xB001, 0x00);
+        bus.mem_write(0xB002, 0x80);
+        let mut previous = bus.apu.output();
+        let mut ramps = 0;
+        for _ in 0..14 {
+            bus.mem_read(0x0000);
+            let level = bus.apu.output();
+            if level > previous {
+                ramps += 1;
+            }
+            previous = level;
+        }
+        assert_eq!(ramps, 6);
+    }
+
+    fn write_fme7(bus: &mut Bus, command: u8, parameter: u8) {
+        bus.mem_write(0x8000, command);
+        bus.mem_write(0xA000, parameter);
+    }
+
+    #[test]
+    fn test_fme7_banks() {
+        let bus = &mut create_mapper_bus(69, 8, 32);
+        write_fme7(bus, 0x9, 3);
+        write_fme7(bus, 0xA, 4);
+        write_fme7(bus, 0xB, 5);
+        assert_eq!(bus.mem_peek(0x8000), 3);
+        assert_eq!(bus.mem_peek(0xA000), 4);
+        assert_eq!(bus.mem_peek(0xC000), 5);
+        assert_eq!(bus.mem_peek(0xE000), 15);
+
+        // ROM, then enabled RAM at $6000
+        write_fme7(bus, 0x8, 0x06);
+        assert_eq!(bus.mem_peek(0x6000), 6);
+        write_fme7(bus, 0x8, 0xC0);
+        bus.mem_write(0x6000, 0x42);
+        assert_eq!(bus.mem_peek(0x6000), 0x42);
+
+        write_fme7(bus, 0x3, 0x21);
+        assert_eq!(bus.ppu.chr_tile(0x0C00)[0], 0x21);
+        write_fme7(bus, 0xC, 3);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Custom([1; 4]));
+    }
+
+    #[test]
+    fn test_fme7_irq() {
+        let bus = &mut create_mapper_bus(69, 8, 8);
+        bus.mem_write(0x4017, 0x40);
+        write_fme7(bus, 0xE, 3);
+        write_fme7(bus, 0xF, 0);
+        // 3, 2, 1, 0 and the IRQ when it wraps to $FFFF
+        write_fme7(bus, 0xD, 0x81);
+        for _ in 0..3 {
+            assert!(!bus.poll_irq_status());
+            bus.mem_read(0x0000);
+        }
+        bus.mem_read(0x0000);
+        assert!(bus.poll_irq_status());
+        write_fme7(bus, 0xD, 0x00);
+        assert!(!bus.poll_irq_status());
+    }
+
+    #[test]
+    fn test_sunsoft_5b_audio() {
+        let bus = &mut create_mapper_bus(69, 8, 8);
+        bus.mem_read(0x0000);
+        let silence = bus.apu.output();
+        let audio = [(0x0, 0x01), (0x1, 0x00), (0x7, 0b0011_1110), (0x8, 0x0F)];
+        for (register, value) in audio {
+            bus.mem_write(0xC000, register);
+            bus.mem_write(0xE000, value);
+        }
+        // tone A toggles every 16 CPU cycles
+        let mut levels = vec![];
+        for _ in 0..64 {
+            bus.mem_read(0x0000);
+            levels.push(bus.apu.output() - silence);
+        }
+        assert!(levels.iter().any(|&level| level > 0.14));
+        assert!(levels.contains(&0.0));
+    }
+
+    #[test]
+    fn test_namco163_banks() {
+        let bus = &mut create_mapper_bus(19, 8, 32);
+        bus.mem_write(0xE000, 3);
+        bus.mem_write(0xE800, 4);
+        bus.mem_write(0xF000, 5);
+        assert_eq!(bus.mem_peek(0x8000), 3);
+        assert_eq!(bus.mem_peek(0xA000), 4);
+        assert_eq!(bus.mem_peek(0xC000), 5);
+        assert_eq!(bus.mem_peek(0xE000), 15);
+
+        bus.mem_write(0x8800, 0x21);
+        assert_eq!(bus.ppu.chr_tile(0x0400)[0], 0x21);
+
+        bus.mem_write(0xC000, 0xE1);
+        bus.mem_write(0xC800, 0xE1);
+        bus.mem_write(0xD000, 0xE0);
+        bus.mem_write(0xD800, 0xE0);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Custom([1, 1, 0, 0]));
+    }
+
+    #[test]
+    fn test_namco163_irq() {
+        let bus = &mut create_mapper_bus(19, 8, 8);
+        bus.mem_write(0x4017, 0x40);
+        bus.mem_write(0x5000, 0xFD);
+        bus.mem_write(0x5800, 0xFF);
+        assert_eq!(bus.mem_read(0x5800), 0xFF);
+        bus.mem_read(0x0000);
+        assert!(bus.poll_irq_status());
+        // the counter stops at $7FFF
+        assert_eq!(bus.mem_read(0x5000), 0xFF);
+        bus.mem_write(0x5800, 0x80);
+        assert!(!bus.poll_irq_status());
+    }
+
+    #[test]
+    fn test_namco163_sound_ram_port() {
+        let bus = &mut create_mapper_bus(19, 8, 8);
+        bus.mem_write(0xF800, 0x80 | 0x10);
+        bus.mem_write(0x4800, 0x12);
+        bus.mem_write(0x4800, 0x34);
+        bus.mem_write(0xF800, 0x10);
+        assert_eq!(bus.mem_read(0x4800), 0x12);
+        assert_eq!(bus.mem_read(0x4800), 0x12);
+        bus.mem_write(0xF800, 0x91);
+        assert_eq!(bus.mem_read(0x4800), 0x34);
+    }
+
+    #[test]
+    fn test_namco163_audio() {
+        let bus = &mut create_mapper_bus(19, 8, 8);
+        bus.mem_read(0x0000);
+        let silence = bus.apu.output();
+        // a 2 sample wave of $F and $0 on channel 7, the only enabled channel
+        bus.mem_write(0xF800, 0x80);
+        bus.mem_write(0x4800, 0x0F);
+        bus.mem_write(0xF800, 0x80 | 0x78);
+        for value in [0x00, 0x00, 0x80, 0x00, 0xFC, 0x00, 0x00, 0x0F] {
+            bus.mem_write(0x4800, value);
+        }
+        let mut levels = vec![];
+        for _ in 0..60 {
+            bus.mem_read(0x0000);
+            levels.push(bus.apu.output() - silence);
+        }
+        assert!(levels.iter().any(|&level| level > 0.05));
+        assert!(levels.iter().any(|&level| level < -0.05));
+    }
+
+    #[test]
+    fn test_namco163_chr_rom_nametable() {
+        let bus = &mut create_mapper_bus(19, 8, 32);
+        // CHR bank $40 is filled with tile $40, which is in the second 1 KiB window
+        bus.mem_write(0x8000, 0);
+        bus.mem_write(0x8800, 3);
+        bus.mem_write(0xC000, 0x40);
+        write_palette(bus, 0x00, 0x0F);
+        write_palette(bus, 0x03, 0x30);
+        // scroll back to the top left of nametable 0
+        bus.mem_write(0x2006, 0x20);
+        bus.mem_write(0x2006, 0x00);
+        bus.mem_write(0x2001, 0b0000_1010);
+        run_to_vblank(bus);
+        run_to_vblank(bus);
+
+        assert_eq!(pixel(bus, 7, 100), palette::SYSTEM_PALLETE[0x30]);
+        assert_eq!(pixel(bus, 5, 100), palette::SYSTEM_PALLETE[0x0F]);
+    }
+
+    struct DiscreteCase {
+        name: &'static str,
+        mapper: u8,
+        submapper: u8,
+        prg_pages: u8,
+        chr_pages: u8,
+        writes: &'static [(u16, u8)],
+        // expected 8 KiB PRG bank at each address
+        prg: &'static [(u16, u8)],
+        // expected 1 KiB CHR bank at each address
+        chr: &'static [(u16, u8)],
+    }
+
+    const DISCRETE_CASES: &[DiscreteCase] = &[
+        DiscreteCase {
+            name: "GxROM",
+            mapper: 66,
+            submapper: 0,
+            prg_pages: 8,
+            chr_pages: 4,
+            writes: &[(0x8000, 0x21)],
+            prg: &[(0x8000, 8), (0xE000, 11)],
+            chr: &[(0x0000, 8), (0x1C00, 15)],
+        },
+        DiscreteCase {
+            name: "Color Dreams",
+            mapper: 11,
+            submapper: 0,
+            prg_pages: 8,
+            chr_pages: 4,
+            writes: &[(0xFFFF, 0x32)],
+            prg: &[(0x8000, 8), (0xE000, 11)],
+            chr: &[(0x0000, 24), (0x1000, 28)],
+        },
+        DiscreteCase {
+            name: "BNROM",
+            mapper: 34,
+            submapper: 0,
+            prg_pages: 8,
+            chr_pages: 0,
+            writes: &[(0x8000, 3)],
+            prg: &[(0x8000, 12), (0xE000, 15)],
+            chr: &[],
+        },
+        DiscreteCase {
+            name: "NINA-001",
+            mapper: 34,
+            submapper: 0,
+            prg_pages: 4,
+            chr_pages: 4,
+            writes: &[(0x7FFD, 1), (0x7FFE, 3), (0x7FFF, 5)],
+            prg: &[(0x8000, 4), (0xE000, 7)],
+            chr: &[(0x0000, 12), (0x0C00, 15), (0x1000, 20)],
+        },
+        DiscreteCase {
+            name: "NINA-001 by submapper",
+            mapper: 34,
+            submapper: 1,
+            prg_pages: 4,
+            chr_pages: 1,
+            writes: &[(0x7FFD, 1), (0x7FFE, 1)],
+            prg: &[(0x8000, 4)],
+            chr: &[(0x0000, 4), (0x1000, 4)],
+        },
+        DiscreteCase {
+            name: "Camerica",
+            mapper: 71,
+            submapper: 0,
+            prg_pages: 8,
+            chr_pages: 0,
+            writes: &[(0xC000, 5)],
+            prg: &[(0x8000, 10), (0xA000, 11), (0xC000, 14), (0xE000, 15)],
+            chr: &[],
+        },
+        DiscreteCase {
+            name: "NINA-03",
+            mapper: 79,
+            submapper: 0,
+            prg_pages: 4,
+            chr_pages: 8,
+            // $4200 doesn't decode A8
+            writes: &[(0x4100, 0x0D), (0x4200, 0x00)],
+            prg: &[(0x8000, 4), (0xE000, 7)],
+            chr: &[(0x0000, 40), (0x1000, 44)],
+        },
+    ];
+
+    #[test]
+    fn test_discrete_mappers() {
+        for case in DISCRETE_CASES {
+            let bus = &mut create_submapper_bus(case.mapper, case.submapper, case.prg_pages, case.chr_pages);
+            for &(addr, value) in case.writes {
+                bus.mem_write(addr, value);
+            }
+            for &(addr, bank) in case.prg {
+                assert_eq!(bus.mem_peek(addr), bank, "{} PRG at {:04X}", case.name, addr);
+            }
+            for &(addr, bank) in case.chr {
+                assert_eq!(bus.ppu.chr_tile(addr)[0], bank, "{} CHR at {:04X}", case.name, addr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_discrete_mappers_power_on_banks() {
+        for case in DISCRETE_CASES {
+            let bus = &mut create_submapper_bus(case.mapper, case.submapper, case.prg_pages, case.chr_pages);
+            assert_eq!(bus.mem_peek(0x8000), 0, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn test_camerica_fire_hawk_mirroring() {
+        let bus = &mut create_submapper_bus(71, 1, 8, 0);
+        bus.mem_write(0x9000, 0x10);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Custom([1; 4]));
+        bus.mem_write(0x9000, 0x00);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Custom([0; 4]));
+
+        // plain BF9093 boards ignore the register
+        let bus = &mut create_submapper_bus(71, 0, 8, 0);
+        bus.mem_write(0x9000, 0x10);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Horizontal);
+    }
+
+    // disk info, one file with a 4 byte payload
+    fn create_fds_side() -> Vec<u8> {
+        let mut side = vec![0x01];
+        side.extend_from_slice(b"*NINTENDO-HVC*");
+        side.resize(56, 0);
+        side.extend_from_slice(&[0x02, 0x01]);
+        let mut header = vec![0x03, 0x00, 0x00];
+        header.extend_from_slice(b"FILE0000");
+        header.extend_from_slice(&[0x00, 0x60, 0x04, 0x00, 0x00]);
+        side.extend_from_slice(&header);
+        side.extend_from_slice(&[0x04, 0xDE, 0xAD, 0xBE, 0xEF]);
+        side.resize(fds::SIDE_SIZE, 0);
+        side
+    }
+
+    fn create_fds_cartridge(sides: usize) -> Cartridge {
+        let mut image = b"FDS\x1a".to_vec();
+        image.push(sides as u8);
+        image.resize(16, 0);
+        for _ in 0..sides {
+            image.extend(create_fds_side());
+        }
+        let bios = (0..fds::BIOS_SIZE).map(|i| (i >> 8) as u8).collect();
+        Cartridge::from_fds(image, bios).unwrap()
+    }
+
+    fn run_drive(board: &mut MapperBoard, cycles: usize) {
+        for _ in 0..cycles {
+            board.mapper_mut().tick();
+        }
+    }
+
+    // bytes the drive hands over through $4031 while the motor runs
+    fn read_disk(board: &mut MapperBoard, count: usize) -> Vec<u8> {
+        let mapper = board.mapper_mut();
+        let mut bytes = vec![];
+        for _ in 0..1_000_000 {
+            mapper.tick();
+            if mapper.peek(0x4030).unwrap() & 0b10 != 0 {
+                bytes.push(mapper.read(0x4031).unwrap());
+                if bytes.len() == count {
+                    break;
+                }
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_fds_image_sides() {
+        let cartridge = create_fds_cartridge(2);
+        assert_eq!(cartridge.mapper, 20);
+        assert_eq!(cartridge.disk_sides.len(), 2);
+        assert!(cartridge.chr_rom.is_empty());
+
+        // the header is optional
+        let headerless = Cartridge::from_fds(create_fds_side(), vec![0; fds::BIOS_SIZE]).unwrap();
+        assert_eq!(headerless.disk_sides.len(), 1);
+
+        let err = Cartridge::from_fds(vec![0; 1000], vec![0; fds::BIOS_SIZE]).unwrap_err();
+        assert!(matches!(err.downcast::<CartridgeError>().unwrap(), CartridgeError::InvalidDiskImage(1000)));
+        let err = Cartridge::from_fds(create_fds_side(), vec![0; 100]).unwrap_err();
+        assert!(matches!(err.downcast::<CartridgeError>().unwrap(), CartridgeError::InvalidBios { expected: 0x2000, found: 100 }));
+    }
+
+    #[test]
+    fn test_fds_gaps_round_trip() {
+        let side = create_fds_side();
+        let raw = fds::add_gaps(&side);
+        // the lead-in gap, then the start mark of the first block
+        assert!(raw[..28300 / 8].iter().all(|&byte| byte == 0));
+        assert_eq!(raw[28300 / 8], 0x80);
+        assert_eq!(fds::remove_gaps(&raw), side);
+    }
+
+    #[test]
+    fn test_fds_ram_and_bios() {
+        let bus = &mut Bus::new(Some(create_fds_cartridge(1)), move |_, _| {});
+        bus.mem_write(0x6000, 0x12);
+        bus.mem_write(0xDFFF, 0x34);
+        assert_eq!(bus.mem_peek(0x6000), 0x12);
+        assert_eq!(bus.mem_peek(0xDFFF), 0x34);
+        assert_eq!(bus.mem_peek(0xE000), 0x00);
+        assert_eq!(bus.mem_peek(0xFFFF), 0x1F);
+
+        // $4025 bit 3 selects horizontal mirroring
+        bus.mem_write(0x4023, 0x01);
+        bus.mem_write(0x4025, 0x00);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Vertical);
+        bus.mem_write(0x4025, 0x08);
+        assert_eq!(bus.ppu.mirroring, ScreenMirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_fds_timer_irq() {
+        let bus = &mut Bus::new(Some(create_fds_cartridge(1)), move |_, _| {});
+        bus.mem_write(0x4017, 0x40);
+        bus.mem_write(0x4023, 0x01);
+        bus.mem_write(0x4020, 10);
+        bus.mem_write(0x4021, 0);
+        // repeat, enabled
+        bus.mem_write(0x4022, 0b11);
+        let mut cycles = 0;
+        while !bus.poll_irq_status() {
+            bus.mem_read(0x0000);
+            cycles += 1;
+        }
+        assert_eq!(cycles, 11);
+        assert_eq!(bus.mem_read(0x4030) & 0b01, 0b01);
+        assert!(!bus.poll_irq_status());
+
+        // the counter reloads and fires again, the $4030 read took one of the cycles
+        cycles = 0;
+        while !bus.poll_irq_status() {
+            bus.mem_read(0x0000);
+            cycles += 1;
+        }
+        assert_eq!(cycles, 10);
+    }
+
+    #[test]
+    fn test_fds_disk_read() {
+        let mut board = MapperBoard::new(&create_fds_cartridge(1)).unwrap();
+        let mapper = board.mapper_mut();
+        mapper.write(0x4023, 0x01);
+        assert_eq!(mapper.peek(0x4032).unwrap() & 0b111, 0b010);
+        // motor on, read mode, ready
+        mapper.write(0x4025, 0x45);
+        // the start mark ends the gap, then the disk info block follows
+        let bytes = read_disk(&mut board, 16);
+        assert_eq!(bytes[..3], [0x80, 0x01, b'*']);
+        assert_eq!(&bytes[3..15], b"NINTENDO-HVC");
+        assert_eq!(board.mapper().peek(0x4032).unwrap() & 0b111, 0b000);
+
+        // with the motor off the head goes back to the start
+        board.mapper_mut().write(0x4025, 0x44);
+        run_drive(&mut board, 1);
+        assert_eq!(board.mapper().peek(0x4030).unwrap() & 0x40, 0x40);
+        board.mapper_mut().write(0x4025, 0x45);
+        assert_eq!(read_disk(&mut board, 2), [0x80, 0x01]);
+    }
+
+    #[test]
+    fn test_fds_disk_irq() {
+        let mut board = MapperBoard::new(&create_fds_cartridge(1)).unwrap();
+        board.mapper_mut().write(0x4023, 0x01);
+        board.mapper_mut().write(0x4025, 0xC5);
+        let mut bytes = vec![];
+        for _ in 0..1_000_000 {
+            board.mapper_mut().tick();
+            if board.mapper().irq_pending() {
+                bytes.push(board.mapper_mut().read(0x4031).unwrap());
+                assert!(!board.mapper().irq_pending());
+                if bytes.len() == 2 {
+                    break;
+                }
+            }
+        }
+        // the start mark doesn't raise the IRQ
+        assert_eq!(bytes, [0x01, b'*']);
+    }
+
+    #[test]
+    fn test_fds_disk_write() {
+        let mut board = MapperBoard::new(&create_fds_cartridge(1)).unwrap();
+        board.mapper_mut().write(0x4023, 0x01);
+        board.mapper_mut().write(0x4024, 0x00);
+        // motor on, write mode, ready
+        board.mapper_mut().write(0x4025, 0x41);
+        // a file amount block in the lead-in gap
+        let mut data = [0x00, 0x00, 0x80, 0x02, 0x07].into_iter();
+        for _ in 0..1_000_000 {
+            board.mapper_mut().tick();
+            if board.mapper().peek(0x4030).unwrap() & 0b10 != 0 {
+                match data.next() {
+                    Some(value) => board.mapper_mut().write(0x4024, value),
+                    None => break,
+                }
+            }
+        }
+        let MapperBoard::FDS(drive) = &mut board else { panic!("Expected the FDS board") };
+        // nothing is saved until the BIOS stops writing
+        assert!(drive.take_written_sides().is_none());
+        board.mapper_mut().write(0x4025, 0x45);
+
+        let MapperBoard::FDS(drive) = &mut board else { panic!("Expected the FDS board") };
+        let sides = drive.take_written_sides().unwrap();
+        assert_eq!(sides[0][..4], [0x02, 0x07, 0x01, b'*']);
+        assert!(drive.take_written_sides().is_none());
+    }
+
+    #[test]
+    fn test_fds_side_flip() {
+        let mut board = MapperBoard::new(&create_fds_cartridge(2)).unwrap();
+        board.mapper_mut().write(0x4023, 0x01);
+        let MapperBoard::FDS(drive) = &mut board else { panic!("Expected the FDS board") };
+        assert_eq!(drive.side_count(), 2);
+        assert_eq!(drive.disk_side(), Some(0));
+
+        drive.insert(1);
+        assert_eq!(drive.disk_side(), None);
+        // no disk, not ready and write protected
+        assert_eq!(board.mapper().peek(0x4032).unwrap() & 0b111, 0b111);
+        run_drive(&mut board, 1_800_000);
+        let MapperBoard::FDS(drive) = &mut board else { panic!("Expected the FDS board") };
+        assert_eq!(drive.disk_side(), Some(1));
+        assert_eq!(board.mapper().peek(0x4032).unwrap() & 0b101, 0b000);
+    }
+
+    #[test]
+    fn test_fds_audio() {
+        let bus = &mut Bus::new(Some(create_fds_cartridge(1)), move |_, _| {});
+        bus.mem_read(0x0000);
+        let silence = bus.apu.output();
+        bus.mem_write(0x4023, 0x03);
+        // a square wave in the wavetable
+        bus.mem_write(0x4089, 0x80);
+        for i in 0..64 {
+            bus.mem_write(0x4040 + i, if i < 32 { 0x3F } else { 0x00 });
+        }
+        assert_eq!(bus.mem_peek(0x4040), 0x3F);
+        bus.mem_write(0x4089, 0x00);
+        // envelope off at full gain, a high pitch
+        bus.mem_write(0x4080, 0xA0);
+        bus.mem_write(0x4082, 0x00);
+        bus.mem_write(0x4083, 0x08);
+        assert_eq!(bus.mem_peek(0x4090), 0x60);
+
+        // 32 cycles per step, 64 steps per period
+        let mut levels = vec![];
+        for _ in 0..2048 {
+            bus.mem_read(0x0000);
+            levels.push(bus.apu.output() - silence);
+        }
+        let high = levels.iter().cloned().fold(0.0, f32::max);
+        assert!(high > 0.3 && high < 0.4);
+        assert!(levels.iter().any(|&level| level.abs() < 0.0001));
+    }
+
+    #[test]
+    fn test_ips_round_trip() {
+        let original: Vec<u8> = (0..=255).collect();
+        let mut modified = original.clone();
+        modified[3] = 0xAA;
+        modified[100..104].copy_from_slice(&[1, 2, 3, 4]);
+        modified.extend_from_slice(&[9, 9]);
+        let patch = create_ips(&original, &modified).unwrap();
+        assert!(patch.starts_with(b"PATCH"));
+        assert!(patch.ends_with(b"EOF"));
+        assert_eq!(apply_ips(&original, &patch).unwrap(), modified);
+
+        // a shorter output is truncated to the size after EOF
+        let shorter = original[..200].to_vec();
+        let patch = create_ips(&original, &shorter).unwrap();
+        assert_eq!(apply_ips(&original, &patch).unwrap(), shorter);
+    }
+
+    #[test]
+    fn test_ips_rle_and_errors() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x77]);
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(apply_ips(&[0; 6], &patch).unwrap(), [0, 0, 0x77, 0x77, 0x77, 0]);
+
+        assert_eq!(apply_ips(&[0; 6], b"PATCX").unwrap_err(), PatchError::InvalidHeader);
+        assert_eq!(apply_ips(&[0; 6], b"PATCH\x00\x00\x01\x00\x05\x01").unwrap_err(), PatchError::Truncated);
+    }
+
+    fn patch_number(patch: &mut Vec<u8>, mut value: usize) {
+        loop {
+            let bits = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                patch.push(0x80 | bits);
+                return;
+            }
+            patch.push(bits);
+            value -= 1;
+        }
+    }
+
+    fn patch_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    fn create_ups(source: &[u8], target: &[u8]) -> Vec<u8> {
+        let mut patch = b"UPS1".to_vec();
+        patch_number(&mut patch, source.len());
+        patch_number(&mut patch, target.len());
+        let xor = |offset: usize| source.get(offset).unwrap_or(&0) ^ target.get(offset).unwrap_or(&0);
+        let (mut offset, mut last) = (0, 0);
+        while offset < source.len().max(target.len()) {
+            if xor(offset) == 0 {
+                offset += 1;
+                continue;
+            }
+            patch_number(&mut patch, offset - last);
+            while offset < source.len().max(target.len()) && xor(offset) != 0 {
+                patch.push(xor(offset));
+                offset += 1;
+            }
+            patch.push(0);
+            offset += 1;
+            last = offset;
+        }
+        patch_footer(patch, source, target)
+    }
+
+    #[test]
+    fn test_ups() {
+        let source = b"Hello World, this is a ROM".to_vec();
+        let target = b"Hallo Welt, this is a ROM hack".to_vec();
+        let patch = create_ups(&source, &target);
+        assert_eq!(apply_ups(&source, &patch).unwrap(), target);
+        assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+        // shrinking the ROM
+        assert_eq!(apply_ups(&target, &create_ups(&target, &source)).unwrap(), source);
+
+        let err = apply_ups(b"Some other ROM", &patch).unwrap_err();
+        assert!(matches!(err, PatchError::ChecksumMismatch { checksum: "source", .. }));
+        let mut corrupted = patch.clone();
+        corrupted[8] ^= 0x01;
+        let err = apply_ups(&source, &corrupted).unwrap_err();
+        assert!(matches!(err, PatchError::ChecksumMismatch { checksum: "patch", .. }));
+        assert_eq!(apply_ups(&source, b"UPS1").unwrap_err(), PatchError::Truncated);
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = b"0123456789".to_vec();
+        let mut patch = b"BPS1".to_vec();
+        patch_number(&mut patch, source.len());
+        patch_number(&mut patch, 16);
+        patch_number(&mut patch, 4);
+        patch.extend_from_slice(b"meta");
+        // SourceRead "0123"
+        patch_number(&mut patch, (4 - 1) << 2);
+        // TargetRead "xy"
+        patch_number(&mut patch, (2 - 1) << 2 | 1);
+        patch.extend_from_slice(b"xy");
+        // SourceCopy "789" from 7 bytes forward, then "56" from 5 bytes back
+        patch_number(&mut patch, (3 - 1) << 2 | 2);
+        patch_number(&mut patch, 7 << 1);
+        patch_number(&mut patch, (2 - 1) << 2 | 2);
+        patch_number(&mut patch, 5 << 1 | 1);
+        // TargetCopy from the "56" just written, the run overlaps itself and repeats it
+        patch_number(&mut patch, (5 - 1) << 2 | 3);
+        patch_number(&mut patch, 9 << 1);
+        let target = b"0123xy7895656565".to_vec();
+        let patch = patch_footer(patch, &source, &target);
+        assert_eq!(apply_bps(&source, &patch).unwrap(), target);
+        assert_eq!(apply_patch(&source, &patch).unwrap(), target);
+
+        let err = apply_bps(b"9876543210", &patch).unwrap_err();
+        assert!(matches!(err, PatchError::ChecksumMismatch { checksum: "source", .. }));
+        // a patch that is consistent but produces something else
+        let wrong_target = patch_footer(patch[..patch.len() - 12].to_vec(), &source, b"0123xy7895656566");
+        let err = apply_bps(&source, &wrong_target).unwrap_err();
+        assert!(matches!(err, PatchError::ChecksumMismatch { checksum: "target", .. }));
+    }
+
+    #[test]
+    fn test_patch_target_size_limit() {
+        let source = b"0123456789".to_vec();
+        for tag in [b"UPS1", b"BPS1"] {
+            let mut patch = tag.to_vec();
+            patch_number(&mut patch, source.len());
+            patch_number(&mut patch, usize::MAX / 2);
+            patch_number(&mut patch, 0);
+            let patch = patch_footer(patch, &source, &source);
+            assert_eq!(apply_patch(&source, &patch).unwrap_err(), PatchError::TargetTooLarge(usize::MAX / 2));
+        }
+
+        // a BPS copy can't run past the target size either
+        let mut patch = b"BPS1".to_vec();
+        patch_number(&mut patch, source.len());
+        patch_number(&mut patch, 4);
+        patch_number(&mut patch, 0);
+        patch_number(&mut patch, 1);
+        patch.push(b'x');
+        patch_number(&mut patch, (0x1000000 - 1) << 2 | 3);
+        patch_number(&mut patch, 0);
+        let patch = patch_footer(patch, &source, b"xxxx");
+        assert_eq!(apply_bps(&source, &patch).unwrap_err(), PatchError::OffsetOutOfRange(1));
+    }
+
+    #[test]
+    fn test_apply_patch_format() {
+        let rom = vec![0u8; 16];
+        let ips = create_ips(&rom, &[1; 16]).unwrap();
+        assert_eq!(apply_patch(&rom, &ips).unwrap(), vec![1; 16]);
+        assert_eq!(apply_patch(&rom, b"NOPE").unwrap_err(), PatchError::InvalidHeader);
+    }
+
+    fn create_unif(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut data = b"UNIF".to_vec();
+        data.extend_from_slice(&7u32.to_le_bytes());
+        data.resize(32, 0);
+        for (id, chunk) in chunks {
+            data.extend_from_slice(*id);
+            data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            data.extend_from_slice(chunk);
+        }
+        data
+    }
+
+    #[test]
+    fn test_unif_nrom() {
+        let data = create_unif(&[
+            (b"MAPR", b"NES-NROM-256\0".to_vec()),
+            (b"NAME", b"Test\0".to_vec()),
+            (b"PRG0", vec![0x42; 0x8000]),
+            (b"CHR0", vec![0x33; 0x2000]),
+            (b"MIRR", vec![1]),
+            (b"BATR", vec![1]),
+            (b"TVCI", vec![1]),
+        ]);
+        let cartridge = Cartridge::new(data).unwrap();
+        assert_eq!(cartridge.mapper, 0);
+        assert_eq!(cartridge.prg_rom.len(), 0x8000);
+        assert_eq!(cartridge.chr_rom.len(), 0x2000);
+        assert_eq!(cartridge.screen_mirroring, ScreenMirroring::Vertical);
+        assert!(cartridge.battery);
+        assert_eq!(cartridge.region, Region::PAL);
+    }
+
+    #[test]
+    fn test_unif_rom_chunks_in_order() {
+        let data = create_unif(&[
+            (b"PRG1", vec![1; 0x4000]),
+            (b"MAPR", b"NES-GNROM\0".to_vec()),
+            (b"PRG0", vec![0; 0x4000]),
+            (b"CHR1", vec![3; 0x2000]),
+            (b"CHR0", vec![2; 0x2000]),
+        ]);
+        let cartridge = Cartridge::new(data).unwrap();
+        assert_eq!(cartridge.mapper, 66);
+        assert_eq!(cartridge.prg_rom[0], 0);
+        assert_eq!(cartridge.prg_rom[0x4000], 1);
+        assert_eq!(cartridge.chr_rom[0], 2);
+        assert_eq!(cartridge.chr_rom[0x2000], 3);
+        // mirroring defaults to horizontal without an MIRR chunk
+        assert_eq!(cartridge.screen_mirroring, ScreenMirroring::Horizontal);
+
+        // the board name selects the mapper the bus runs
+        let bus = &mut Bus::new(Some(cartridge), move |_, _| {});
+        bus.mem_write(0x8000, 0x01);
+        assert_eq!(bus.ppu.chr_tile(0x0000)[0], 3);
+    }
+
+    #[test]
+    fn test_unif_board_names() {
+        let cases = [
+            ("NES-NROM-128", (0, 0)),
+            ("NES-SLROM", (1, 0)),
+            ("NES-TLROM", (4, 0)),
+            ("NES-ELROM", (5, 0)),
+            ("NES-PNROM", (9, 0)),
+            ("HVC-FKROM", (10, 0)),
+            ("AVE-NINA-01", (34, 1)),
+            ("NES-BNROM", (34, 2)),
+            ("NES-BTR", (69, 0)),
+            ("AVE-NINA-06", (79, 0)),
+            ("nes-cnrom", (3, 0)),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(unif::board_mapper(name), Some(expected), "{}", name);
+        }
+
+        let cartridge = Cartridge::new(create_unif(&[(b"MAPR", b"AVE-NINA-01\0".to_vec()), (b"PRG0", vec![0; 0x8000])])).unwrap();
+        assert_eq!((cartridge.mapper, cartridge.submapper), (34, 1));
+        let cartridge = Cartridge::new(create_unif(&[(b"MAPR", b"NES-BTR\0".to_vec()), (b"PRG0", vec![0; 0x8000])])).unwrap();
+        assert_eq!(cartridge.mapper, 69);
+    }
+
+    #[test]
+    fn test_unif_errors() {
+        let unif_error = |data: Vec<u8>| Cartridge::new(data).unwrap_err().downcast::<CartridgeError>().unwrap();
+
+        let err = unif_error(create_unif(&[(b"PRG0", vec![0; 0x8000])]));
+        assert!(matches!(err, CartridgeError::MissingUnifChunk("MAPR")));
+
+        let err = unif_error(create_unif(&[(b"MAPR", b"UNL-MYSTERY\0".to_vec()), (b"PRG0", vec![0; 0x8000])]));
+        assert!(matches!(err, CartridgeError::UnknownUnifBoard(name) if name == "UNL-MYSTERY"));
+
+        // a known board whose mapper isn't emulated
+        let err = unif_error(create_unif(&[(b"MAPR", b"NES-SLROM\0".to_vec()), (b"PRG0", vec![0; 0x8000])]));
+        assert!(matches!(err, CartridgeError::UnsupportedMapper(1)));
+
+        let err = unif_error(create_unif(&[(b"MAPR", b"NES-NROM-256\0".to_vec())]));
+        assert!(matches!(err, CartridgeError::MissingUnifChunk("PRG0")));
+
+        let mut data = create_unif(&[(b"MAPR", b"NES-NROM-256\0".to_vec()), (b"PRG0", vec![0; 0x8000])]);
+        data.truncate(data.len() - 1);
+        assert!(matches!(unif_error(data), CartridgeError::TruncatedUnifChunk(id) if id == "PRG0"));
+
+        let err = unif_error(create_unif(&[(b"MAPR", b"NES-NROM-256\0".to_vec()), (b"MIRR", vec![9])]));
+        assert!(matches!(err, CartridgeError::IllegalScreenMirroring));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_sha1() {
+        let hex = |digest: [u8; 20]| digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // two blocks once the padding is added
+        assert_eq!(hex(sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+                   "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+    }
+
+    fn create_game_database(sha: &str) -> GameDatabase {
+        let rom = [vec![0x42; 16384], vec![0x33; 8192]].concat();
+        GameDatabase::parse(&format!(
+            "# comment\n\n{:08X};{};2;0;V;1;PAL;NES-UNROM;Test Game\n",
+            crc32(&rom), sha,
+        )).unwrap()
+    }
+
+    #[test]
+    fn test_game_database_corrects_ines_header() {
+        let mut cartridge = Cartridge::new(create_test_cartridge_data(create_valid_ines_header(), 16384, 8192)).unwrap();
+        assert_eq!(cartridge.mapper, 0);
+        assert!(cartridge.game.is_none());
+
+        cartridge.identify(&create_game_database("-"), true);
+        let game = cartridge.game.clone().unwrap();
+        assert_eq!(game.title, "Test Game");
+        assert_eq!(game.board, "NES-UNROM");
+        assert_eq!(game.region, Region::PAL);
+        assert_eq!(cartridge.mapper, 2);
+        assert_eq!(cartridge.screen_mirroring, ScreenMirroring::Vertical);
+        assert!(cartridge.battery);
+        assert_eq!(cartridge.region, Region::PAL);
+    }
+
+    #[test]
+    fn test_game_database_keeps_trusted_header() {
+        let mut cartridge = Cartridge::new(create_test_cartridge_data(create_valid_ines_header(), 16384, 8192)).unwrap();
+        cartridge.identify(&create_game_database("-"), false);
+        assert_eq!(cartridge.game.as_ref().unwrap().title, "Test Game");
+        assert_eq!(cartridge.mapper, 0);
+        assert_eq!(cartridge.screen_mirroring, ScreenMirroring::Horizontal);
+        assert!(!cartridge.battery);
+    }
+
+    #[test]
+    fn test_game_database_sha1() {
+        let rom = [vec![0x42; 16384], vec![0x33; 8192]].concat();
+        let sha: String = sha1(&rom).iter().map(|byte| format!("{:02X}", byte)).collect();
+        let mut cartridge = Cartridge::new(create_test_cartridge_data(create_valid_ines_header(), 16384, 8192)).unwrap();
+        cartridge.identify(&create_game_database(&sha), true);
+        assert_eq!(cartridge.mapper, 2);
+
+        // same crc32, different ROM
+        let mut cartridge = Cartridge::new(create_test_cartridge_data(create_valid_ines_header(), 16384, 8192)).unwrap();
+        cartridge.identify(&create_game_database(&"0".repeat(40)), true);
+        assert!(cartridge.game.is_none());
+        assert_eq!(cartridge.mapper, 0);
+    }
+
+    #[test]
+    fn test_game_database_corrects_nestest_header() {
+        let database = GameDatabase::parse("158B0388;4131307F0F69F2A5C54B7D438328C5B2A5ED0820;0;0;H;0;NTSC;NES-NROM-128;nestest").unwrap();
+        let mut rom = std::fs::read("tests/nestest.nes").unwrap();
+        // mapper 1, vertical mirroring and a battery
+        rom[6] = 0x13;
+        let mut cartridge = Cartridge::new(rom).unwrap();
+        assert_eq!(cartridge.mapper, 1);
+        cartridge.identify(&database, true);
+        assert_eq!(cartridge.game.as_ref().unwrap().title, "nestest");
+        assert_eq!(cartridge.mapper, 0);
+        assert_eq!(cartridge.screen_mirroring, ScreenMirroring::Horizontal);
+        assert!(!cartridge.battery);
+    }
+
+    #[test]
+    fn test_game_database_errors() {
+        assert!(matches!(GameDatabase::parse("\n0;-;0;0;X;0;NTSC;board;title"), Err(CartridgeError::InvalidDatabaseEntry(2))));
+        assert!(matches!(GameDatabase::parse("0;-;0;0;V;0;NTSC;board"), Err(CartridgeError::InvalidDatabaseEntry(1))));
+        assert!(matches!(GameDatabase::parse("0;abc;0;0;V;0;NTSC;board;title"), Err(CartridgeError::InvalidDatabaseEntry(1))));
+        // titles can have the separator in them
+        let database = GameDatabase::parse("0;-;0;0;-;0;Dendy;board;A; B").unwrap();
+        let game = database.find(&[], &[]).unwrap();
+        assert_eq!(game.title, "A; B");
+        assert_eq!(game.mirroring, None);
+        assert_eq!(game.region, Region::Dendy);
+        // the bundled database parses
+        assert!(GameDatabase::bundled().find(&[0x42; 16384], &[]).is_none());
+    }
+}
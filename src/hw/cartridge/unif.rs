@@ -0,0 +1,71 @@
+use crate::hw::cartridge::CartridgeError;
+
+// https://www.nesdev.org/wiki/UNIF
+const UNIF_TAG: [u8; 4] = [0x55, 0x4E, 0x49, 0x46];
+const HEADER_SIZE: usize = 32;
+const CHUNK_HEADER_SIZE: usize = 8;
+// the maker part of board names like NES-NROM-256 or UNL-... doesn't change the board
+const BOARD_PREFIXES: [&str; 6] = ["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "AVE-"];
+
+// chunk id and its data
+pub type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+pub fn is_unif(raw: &[u8]) -> bool {
+    raw.starts_with(&UNIF_TAG)
+}
+
+// chunk ids with their data, in file order
+pub fn chunks(raw: &[u8]) -> Result<Vec<Chunk<'_>>, CartridgeError> {
+    let mut chunks = vec![];
+    let mut position = HEADER_SIZE;
+    while position + CHUNK_HEADER_SIZE <= raw.len() {
+        let id: [u8; 4] = raw[position..position + 4].try_into().unwrap();
+        let size = u32::from_le_bytes(raw[position + 4..position + 8].try_into().unwrap()) as usize;
+        let start = position + CHUNK_HEADER_SIZE;
+        let data = raw.get(start..start.saturating_add(size))
+            .ok_or_else(|| CartridgeError::TruncatedUnifChunk(String::from_utf8_lossy(&id).into_owned()))?;
+        chunks.push((id, data));
+        position = start + size;
+    }
+    Ok(chunks)
+}
+
+// the MAPR chunk is a null terminated board name
+pub fn board_name(data: &[u8]) -> String {
+    let end = data.iter().position(|&byte| byte == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).trim().to_string()
+}
+
+// https://www.nesdev.org/wiki/UNIF_to_NES_2.0_Mapping
+// mapper and submapper of the iNES number that covers the board
+pub fn board_mapper(name: &str) -> Option<(u16, u8)> {
+    let name = name.to_ascii_uppercase();
+    let board = BOARD_PREFIXES.iter().find_map(|prefix| name.strip_prefix(prefix)).unwrap_or(&name);
+    Some(match board {
+        "NROM" | "NROM-128" | "NROM-256" | "HROM" | "RROM" | "RROM-128" | "SROM" | "RTROM" | "STROM" => (0, 0),
+        "SAROM" | "SBROM" | "SCROM" | "SEROM" | "SFROM" | "SGROM" | "SHROM" | "SJROM" | "SKROM" | "SLROM"
+        | "SL1ROM" | "SNROM" | "SOROM" | "SUROM" | "SXROM" => (1, 0),
+        "UNROM" | "UOROM" => (2, 0),
+        "CNROM" => (3, 0),
+        "TBROM" | "TEROM" | "TFROM" | "TGROM" | "TKROM" | "TLROM" | "TL1ROM" | "TNROM" | "TR1ROM" | "TSROM"
+        | "TVROM" => (4, 0),
+        "EKROM" | "ELROM" | "ETROM" | "EWROM" => (5, 0),
+        "AMROM" | "ANROM" | "AOROM" => (7, 0),
+        "PNROM" | "PEEOROM" => (9, 0),
+        "FJROM" | "FKROM" => (10, 0),
+        "NINA-01" | "NINA-001" => (34, 1),
+        "BNROM" => (34, 2),
+        "GNROM" | "MHROM" => (66, 0),
+        "BTR" | "JLROM" | "JSROM" => (69, 0),
+        "NINA-03" | "NINA-06" => (79, 0),
+        _ => return None,
+    })
+}
+
+// PRG0-PRGF and CHR0-CHRF, the digit is the order of the chunk in the ROM
+pub fn rom_chunk_index(id: &[u8; 4], kind: &[u8; 3]) -> Option<usize> {
+    if &id[..3] != kind {
+        return None;
+    }
+    (id[3] as char).to_digit(16).map(|index| index as usize)
+}
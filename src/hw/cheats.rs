@@ -0,0 +1,169 @@
+mod tests;
+
+use thiserror::Error;
+
+// https://www.nesdev.org/wiki/Game_Genie
+const GAME_GENIE_LETTERS: &str = "APZLGITYEOXUKSVN";
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CheatError {
+    #[error("Invalid cheat code {0}")]
+    InvalidCode(String),
+    #[error("RAM freezes need an address in internal or cartridge RAM, got {0:#06X}")]
+    NotRam(u16),
+    #[error("No cheat number {0}")]
+    NoSuchCheat(usize),
+    #[error("Line {0}: {1}")]
+    InvalidLine(usize, Box<CheatError>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatKind {
+    // replaces what the CPU reads from PRG ROM, 8 letter codes only when the ROM holds the compare
+    // value, which keeps them from hitting other banks
+    GameGenie { addr: u16, value: u8, compare: Option<u8> },
+    // Pro Action Replay style, the value is written to RAM every frame
+    Freeze { addr: u16, value: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    pub code: String,
+    pub description: String,
+    pub kind: CheatKind,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    // Game Genie codes are 6 or 8 letters, freezes are written AAAA:VV in hex
+    pub fn new(code: &str, description: &str) -> Result<Self, CheatError> {
+        let code = code.trim().to_ascii_uppercase();
+        let kind = match code.split_once(':') {
+            Some((addr, value)) => Self::decode_freeze(addr, value),
+            None => Self::decode_game_genie(&code),
+        }.ok_or_else(|| CheatError::InvalidCode(code.clone()))?;
+        if let CheatKind::Freeze { addr, .. } = kind {
+            if !matches!(addr, 0x0000..=0x1FFF | 0x6000..=0x7FFF) {
+                return Err(CheatError::NotRam(addr));
+            }
+        }
+        Ok(Cheat { code, description: description.trim().to_string(), kind, enabled: true })
+    }
+
+    fn decode_freeze(addr: &str, value: &str) -> Option<CheatKind> {
+        if addr.len() != 4 || value.len() != 2 {
+            return None;
+        }
+        Some(CheatKind::Freeze {
+            addr: u16::from_str_radix(addr, 16).ok()?,
+            value: u8::from_str_radix(value, 16).ok()?,
+        })
+    }
+
+    // each letter is 4 bits, scrambled into a 15 bit address in $8000-$FFFF, the value and the
+    // compare value
+    fn decode_game_genie(code: &str) -> Option<CheatKind> {
+        let n = code.chars()
+            .map(|letter| GAME_GENIE_LETTERS.find(letter).map(|index| index as u16))
+            .collect::<Option<Vec<u16>>>()?;
+        if n.len() != 6 && n.len() != 8 {
+            return None;
+        }
+
+        let addr = 0x8000
+            | (n[3] & 7) << 12
+            | (n[5] & 7) << 8 | (n[4] & 8) << 8
+            | (n[2] & 7) << 4 | (n[1] & 8) << 4
+            | (n[4] & 7) | (n[3] & 8);
+        let value = (n[1] & 7) << 4 | (n[0] & 8) << 4 | (n[0] & 7);
+        if n.len() == 6 {
+            let value = value | (n[5] & 8);
+            return Some(CheatKind::GameGenie { addr, value: value as u8, compare: None });
+        }
+        let value = value | (n[7] & 8);
+        let compare = (n[7] & 7) << 4 | (n[6] & 8) << 4 | (n[6] & 7) | (n[5] & 8);
+        Some(CheatKind::GameGenie { addr, value: value as u8, compare: Some(compare as u8) })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn add(&mut self, cheat: Cheat) -> usize {
+        self.cheats.push(cheat);
+        self.cheats.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<Cheat, CheatError> {
+        if index >= self.cheats.len() {
+            return Err(CheatError::NoSuchCheat(index));
+        }
+        Ok(self.cheats.remove(index))
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> Result<(), CheatError> {
+        let cheat = self.cheats.get_mut(index).ok_or(CheatError::NoSuchCheat(index))?;
+        cheat.enabled = enabled;
+        Ok(())
+    }
+
+    // one code per line with an optional description after it, # starts a comment. nothing is
+    // added when a line is invalid
+    pub fn load(&mut self, text: &str) -> Result<(), CheatError> {
+        let cheats = Self::parse(text).collect::<Result<Vec<_>, _>>()?;
+        self.cheats.extend(cheats);
+        Ok(())
+    }
+
+    // adds the valid lines and returns the errors of the others
+    pub fn load_valid(&mut self, text: &str) -> Vec<CheatError> {
+        let mut errors = vec![];
+        for cheat in Self::parse(text) {
+            match cheat {
+                Ok(cheat) => { self.add(cheat); }
+                Err(err) => errors.push(err),
+            }
+        }
+        errors
+    }
+
+    fn parse(text: &str) -> impl Iterator<Item = Result<Cheat, CheatError>> + '_ {
+        text.lines().enumerate().filter_map(|(number, line)| {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                return None;
+            }
+            let (code, description) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            Some(Cheat::new(code, description).map_err(|err| CheatError::InvalidLine(number + 1, Box::new(err))))
+        })
+    }
+
+    // the value the CPU sees when it reads PRG ROM
+    pub fn read(&self, addr: u16, value: u8) -> u8 {
+        self.cheats.iter()
+            .filter(|cheat| cheat.enabled)
+            .find_map(|cheat| match cheat.kind {
+                CheatKind::GameGenie { addr: target, value: replacement, compare }
+                if target == addr && compare.is_none_or(|compare| compare == value) => Some(replacement),
+                _ => None,
+            })
+            .unwrap_or(value)
+    }
+
+    // the RAM writes to make every frame
+    pub fn freezes(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.cheats.iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.kind {
+                CheatKind::Freeze { addr, value } => Some((addr, value)),
+                _ => None,
+            })
+    }
+}
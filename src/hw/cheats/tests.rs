@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod cheats_tests {
+    use crate::hw::bus::Bus;
+    use crate::hw::cartridge::Cartridge;
+    use crate::hw::cheats::{Cheat, CheatError, CheatKind, Cheats};
+    use crate::hw::memory::Memory;
+
+    // NROM-256 whose PRG ROM holds the low byte of each address
+    fn create_bus() -> Bus<'static> {
+        let mut rom = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0];
+        rom.extend((0..0x8000).map(|addr| addr as u8));
+        rom.extend(vec![0; 0x2000]);
+        Bus::new(Some(Cartridge::new(rom).unwrap()), |_, _| {})
+    }
+
+    #[test]
+    fn test_game_genie_decoding() {
+        // https://www.nesdev.org/wiki/Game_Genie
+        assert_eq!(Cheat::new("SXIOPO", "").unwrap().kind, CheatKind::GameGenie { addr: 0x91D9, value: 0xAD, compare: None });
+        assert_eq!(Cheat::new("zexpygla", "").unwrap().kind, CheatKind::GameGenie { addr: 0x94A7, value: 0x02, compare: Some(0x03) });
+        assert_eq!(Cheat::new("GOSSIP", "").unwrap().code, "GOSSIP");
+
+        assert_eq!(Cheat::new("SXIOP", ""), Err(CheatError::InvalidCode(String::from("SXIOP"))));
+        assert_eq!(Cheat::new("SXIOPOO", ""), Err(CheatError::InvalidCode(String::from("SXIOPOO"))));
+        assert_eq!(Cheat::new("SXIOPB", ""), Err(CheatError::InvalidCode(String::from("SXIOPB"))));
+    }
+
+    #[test]
+    fn test_freeze_decoding() {
+        let cheat = Cheat::new("075a:09", " Lives ").unwrap();
+        assert_eq!(cheat.kind, CheatKind::Freeze { addr: 0x075A, value: 0x09 });
+        assert_eq!(cheat.description, "Lives");
+        assert!(cheat.enabled);
+        assert_eq!(Cheat::new("7FFF:01", "").unwrap().kind, CheatKind::Freeze { addr: 0x7FFF, value: 0x01 });
+
+        assert_eq!(Cheat::new("8000:01", ""), Err(CheatError::NotRam(0x8000)));
+        assert_eq!(Cheat::new("2000:01", ""), Err(CheatError::NotRam(0x2000)));
+        assert!(matches!(Cheat::new("75A:09", ""), Err(CheatError::InvalidCode(_))));
+        assert!(matches!(Cheat::new("075A:G9", ""), Err(CheatError::InvalidCode(_))));
+    }
+
+    #[test]
+    fn test_game_genie_reads() {
+        let mut cheats = Cheats::default();
+        cheats.add(Cheat::new("SXIOPO", "").unwrap());
+        cheats.add(Cheat::new("ZEXPYGLA", "").unwrap());
+        assert_eq!(cheats.read(0x91D9, 0xD9), 0xAD);
+        assert_eq!(cheats.read(0x91DA, 0xDA), 0xDA);
+        // the compare value has to match
+        assert_eq!(cheats.read(0x94A7, 0x03), 0x02);
+        assert_eq!(cheats.read(0x94A7, 0x04), 0x04);
+
+        cheats.set_enabled(0, false).unwrap();
+        assert_eq!(cheats.read(0x91D9, 0xD9), 0xD9);
+        assert_eq!(cheats.set_enabled(2, true), Err(CheatError::NoSuchCheat(2)));
+        assert_eq!(cheats.remove(0).unwrap().code, "SXIOPO");
+        assert_eq!(cheats.list().len(), 1);
+    }
+
+    #[test]
+    fn test_cheats_on_the_bus() {
+        let mut bus = create_bus();
+        assert_eq!(bus.mem_read(0x91D9), 0xD9);
+        bus.cheats.add(Cheat::new("SXIOPO", "").unwrap());
+        assert_eq!(bus.mem_read(0x91D9), 0xAD);
+        assert_eq!(bus.mem_peek(0x91D9), 0xAD);
+        // RAM isn't touched by Game Genie codes
+        bus.mem_write(0x11D9, 0x42);
+        assert_eq!(bus.mem_read(0x11D9), 0x42);
+
+        bus.cheats.add(Cheat::new("0010:63", "").unwrap());
+        bus.mem_write(0x0010, 0x01);
+        assert_eq!(bus.mem_read(0x0010), 0x01);
+        // the freeze lands once a frame
+        for _ in 0..30000 {
+            bus.mem_read(0x0000);
+        }
+        assert_eq!(bus.mem_read(0x0010), 0x63);
+    }
+
+    #[test]
+    fn test_cheat_file() {
+        let mut cheats = Cheats::default();
+        cheats.load("# Super Mario Bros.\nSXIOPO Infinite lives\n\n075A:09   Nine lives # at the start\n").unwrap();
+        let list = cheats.list();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].description, "Infinite lives");
+        assert_eq!(list[1].code, "075A:09");
+        assert_eq!(list[1].description, "Nine lives");
+        assert_eq!(cheats.freezes().collect::<Vec<_>>(), [(0x075A, 0x09)]);
+
+        assert_eq!(cheats.load("SXIOPO\nNOTACODE"),
+                   Err(CheatError::InvalidLine(2, Box::new(CheatError::InvalidCode("NOTACODE".to_string())))));
+        // nothing from a file with an invalid line is added
+        assert_eq!(cheats.list().len(), 2);
+    }
+
+    #[test]
+    fn test_load_valid_skips_invalid_lines() {
+        let mut cheats = Cheats::default();
+        let errors = cheats.load_valid("SXIOPO\n2000:01\n075A:09\nNOTACODE");
+        assert_eq!(errors, [
+            CheatError::InvalidLine(2, Box::new(CheatError::NotRam(0x2000))),
+            CheatError::InvalidLine(4, Box::new(CheatError::InvalidCode("NOTACODE".to_string()))),
+        ]);
+        assert_eq!(cheats.list().len(), 2);
+        assert_eq!(cheats.list()[1].code, "075A:09");
+    }
+}
@@ -2,13 +2,15 @@ mod opcodes;
 mod tests;
 pub mod tracer;
 mod interrupt;
+pub mod variant;
 
 use std::cmp::PartialEq;
 use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
-use crate::hw::bus::Bus;
-use crate::hw::cpu::opcodes::{Instruction, OPCODES};
-use crate::hw::memory::Memory;
+use thiserror::Error;
+use crate::hw::cpu::opcodes::Instruction;
+use crate::hw::cpu::variant::CpuVariant;
+use crate::hw::memory::{CpuBus, Memory};
 
 bitflags! {
     // Status Register Flags (bit 7 to bit 0)
@@ -40,15 +42,26 @@ bitflags! {
 const STACK_PAGE: u16 = 0x0100;
 const STACK_START: u8 = 0xfd;
 
+// reasons for the CPU to stop executing, only cleared by a reset
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CpuFault {
+    #[error("CPU jammed by opcode 0x{opcode:02X} at 0x{addr:04X}")]
+    Jammed { opcode: u8, addr: u16 },
+    #[error("Illegal instruction 0x{opcode:02X} at 0x{addr:04X}")]
+    IllegalInstruction { opcode: u8, addr: u16 },
+}
+
 #[derive(Serialize, Deserialize)]
-pub struct CPU<'a> {
+pub struct CPU<B> {
     pub register_a: u8,
     pub register_x: u8,
     pub register_y: u8,
     pub status: CpuFlags,
     pub stack_pointer: u8,
     pub program_counter: u16,
-    pub bus: Bus<'a>,
+    pub variant: CpuVariant,
+    pub fault: Option<CpuFault>,
+    pub bus: B,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -65,9 +78,12 @@ pub enum AddressingMode {
     IndirectY,
     Implicit,
     Relative,
+    // 65C02 only
+    ZeroPageIndirect,
+    AbsoluteIndexedIndirect,
 }
 
-impl<'a> Memory for CPU<'a> {
+impl<B: CpuBus> Memory for CPU<B> {
     fn mem_read(&mut self, addr: u16) -> u8 {
         self.bus.mem_read(addr)
     }
@@ -76,6 +92,10 @@ impl<'a> Memory for CPU<'a> {
         self.bus.mem_write(addr, data);
     }
 
+    fn mem_peek(&mut self, addr: u16) -> u8 {
+        self.bus.mem_peek(addr)
+    }
+
     fn mem_read_u16(&mut self, addr: u16) -> u16 {
         self.bus.mem_read_u16(addr)
     }
@@ -85,8 +105,8 @@ impl<'a> Memory for CPU<'a> {
     }
 }
 
-impl<'a> CPU<'a> {
-    pub fn new(bus: Bus) -> CPU {
+impl<B: CpuBus> CPU<B> {
+    pub fn new(bus: B) -> Self {
         CPU {
             register_a: 0,
             register_x: 0,
@@ -94,10 +114,16 @@ impl<'a> CPU<'a> {
             status: CpuFlags::from_bits_truncate(0b100100),
             stack_pointer: STACK_START,
             program_counter: 0,
+            variant: CpuVariant::RP2A03,
+            fault: None,
             bus,
         }
     }
 
+    pub fn set_variant(&mut self, variant: CpuVariant) {
+        self.variant = variant;
+    }
+
     fn stack_push(&mut self, data: u8) {
         self.mem_write(STACK_PAGE + self.stack_pointer as u16, data);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
@@ -108,7 +134,7 @@ impl<'a> CPU<'a> {
         self.mem_read(STACK_PAGE + self.stack_pointer as u16)
     }
 
-    fn stack_push_u16(&mut self, data: u16) {
+    pub(crate) fn stack_push_u16(&mut self, data: u16) {
         let hi = (data >> 8) as u8;
         let lo = (data & 0xff) as u8;
         self.stack_push(hi);
@@ -144,12 +170,102 @@ impl<'a> CPU<'a> {
         self.update_z_and_n_flags(self.register_a);
     }
 
+    fn decimal_mode(&self) -> bool {
+        self.variant.has_decimal_mode() && self.status.contains(CpuFlags::DECIMAL)
+    }
+
+    // http://www.6502.org/tutorials/decimal_mode.html#A
+    fn add_decimal_to_register_a(&mut self, value: u8) {
+        let a = self.register_a as i16;
+        let b = value as i16;
+        let carry = if self.status.contains(CpuFlags::CARRY) { 1 } else { 0 };
+
+        let mut lo = (a & 0x0F) + (b & 0x0F) + carry;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+
+        // N and V are taken before the high digit is adjusted, treating both high digits as signed
+        let signed = ((a & 0xF0) as u8 as i8) as i16 + ((b & 0xF0) as u8 as i8) as i16 + lo;
+        if !(-128..=127).contains(&signed) {
+            self.status.insert(CpuFlags::OVERFLOW);
+        } else {
+            self.status.remove(CpuFlags::OVERFLOW);
+        }
+
+        let mut sum = (a & 0xF0) + (b & 0xF0) + lo;
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+
+        if sum >= 0x100 {
+            self.status.insert(CpuFlags::CARRY);
+        } else {
+            self.status.remove(CpuFlags::CARRY);
+        }
+
+        let result = sum as u8;
+        if self.variant == CpuVariant::CMOS65C02 {
+            self.update_z_and_n_flags(result);
+        } else {
+            // NMOS sets Z from the binary sum
+            self.update_z_and_n_flags(signed as u8);
+            self.status.set(CpuFlags::ZERO, (a + b + carry) as u8 == 0);
+        }
+        self.register_a = result;
+    }
+
+    // http://www.6502.org/tutorials/decimal_mode.html#A
+    fn sub_decimal_from_register_a(&mut self, value: u8) {
+        let a = self.register_a as i16;
+        let b = value as i16;
+        let borrow = if self.status.contains(CpuFlags::CARRY) { 0 } else { 1 };
+
+        let lo = (a & 0x0F) - (b & 0x0F) - borrow;
+        let result = if self.variant == CpuVariant::CMOS65C02 {
+            let mut diff = a - b - borrow;
+            if diff < 0 {
+                diff -= 0x60;
+            }
+            if lo < 0 {
+                diff -= 0x06;
+            }
+            diff as u8
+        } else {
+            let lo = if lo < 0 { ((lo - 0x06) & 0x0F) - 0x10 } else { lo };
+            let mut diff = (a & 0xF0) - (b & 0xF0) + lo;
+            if diff < 0 {
+                diff -= 0x60;
+            }
+            diff as u8
+        };
+
+        // carry and overflow (and N, Z on NMOS) are the same as for binary subtraction
+        self.add_to_register_a(!value);
+        self.register_a = result;
+        if self.variant == CpuVariant::CMOS65C02 {
+            self.update_z_and_n_flags(result);
+        }
+    }
+
     fn get_operand_value(&mut self, mode: AddressingMode) -> u8 {
         let address = self.get_operand_address(mode);
         self.mem_read(address)
     }
 
     fn get_operand_address(&mut self, mode: AddressingMode) -> u16 {
+        self.operand_address(mode, false)
+    }
+
+    // stores and read-modify-write instructions always spend the cycle that fixes the high byte of
+    // an indexed address, reads only when indexing crosses a page
+    fn get_write_address(&mut self, mode: AddressingMode) -> u16 {
+        self.operand_address(mode, true)
+    }
+
+    // https://www.nesdev.org/6502_cpu.txt
+    // every cycle is a bus access, the cycles spent on indexing read whatever the bus is pointing at
+    fn operand_address(&mut self, mode: AddressingMode, write: bool) -> u16 {
         match mode {
             AddressingMode::Immediate => self.program_counter,
 
@@ -159,22 +275,24 @@ impl<'a> CPU<'a> {
 
             AddressingMode::ZeroPageX => {
                 let pos = self.mem_read(self.program_counter);
+                self.mem_read(pos as u16);
                 pos.wrapping_add(self.register_x) as u16
             }
 
             AddressingMode::ZeroPageY => {
                 let pos = self.mem_read(self.program_counter);
+                self.mem_read(pos as u16);
                 pos.wrapping_add(self.register_y) as u16
             }
 
             AddressingMode::AbsoluteX => {
                 let base = self.mem_read_u16(self.program_counter);
-                base.wrapping_add(self.register_x as u16)
+                self.index(base, self.register_x, write)
             }
 
             AddressingMode::AbsoluteY => {
                 let base = self.mem_read_u16(self.program_counter);
-                base.wrapping_add(self.register_y as u16)
+                self.index(base, self.register_y, write)
             }
 
             AddressingMode::Indirect => {
@@ -187,6 +305,7 @@ impl<'a> CPU<'a> {
 
             AddressingMode::IndirectX => {
                 let base = self.mem_read(self.program_counter);
+                self.mem_read(base as u16);
 
                 let ptr: u8 = base.wrapping_add(self.register_x);
                 let lo = self.mem_read(ptr as u16);
@@ -200,8 +319,20 @@ impl<'a> CPU<'a> {
                 let lo = self.mem_read(base as u16);
                 let hi = self.mem_read(base.wrapping_add(1) as u16);
                 let deref_base = (hi as u16) << 8 | (lo as u16);
-                let deref = deref_base.wrapping_add(self.register_y as u16);
-                deref
+                self.index(deref_base, self.register_y, write)
+            }
+
+            AddressingMode::ZeroPageIndirect => {
+                let ptr = self.mem_read(self.program_counter);
+
+                let lo = self.mem_read(ptr as u16);
+                let hi = self.mem_read(ptr.wrapping_add(1) as u16);
+                (hi as u16) << 8 | (lo as u16)
+            }
+
+            AddressingMode::AbsoluteIndexedIndirect => {
+                let base = self.mem_read_u16(self.program_counter);
+                self.mem_read_u16(base.wrapping_add(self.register_x as u16))
             }
 
             AddressingMode::Implicit | AddressingMode::Relative => {
@@ -210,6 +341,36 @@ impl<'a> CPU<'a> {
         }
     }
 
+    // the low byte is added first, the CPU reads from the unfixed address while it carries into the
+    // high byte
+    fn index(&mut self, base: u16, index: u8, write: bool) -> u16 {
+        let address = base.wrapping_add(index as u16);
+        if write || address & 0xFF00 != base & 0xFF00 {
+            self.mem_read(base & 0xFF00 | address & 0x00FF);
+        }
+        address
+    }
+
+    // the NMOS chips write the unmodified value back while they compute the result, the 65C02
+    // reads it again instead
+    fn read_modify_write(&mut self, mode: AddressingMode, operation: fn(&mut Self, u8) -> u8) -> u8 {
+        let address = self.get_write_address(mode);
+        let value = self.mem_read(address);
+        if self.variant == CpuVariant::CMOS65C02 {
+            self.mem_read(address);
+        } else {
+            self.mem_write(address, value);
+        }
+        let result = operation(self, value);
+        self.mem_write(address, result);
+        result
+    }
+
+    // pulls spend a cycle reading the stack before the stack pointer moves
+    fn stack_dummy_read(&mut self) {
+        self.mem_read(STACK_PAGE + self.stack_pointer as u16);
+    }
+
     fn update_z_and_n_flags(&mut self, result: u8) {
         if result == 0 {
             self.status.insert(CpuFlags::ZERO);
@@ -274,17 +435,17 @@ impl<'a> CPU<'a> {
     }
 
     fn sta(&mut self, mode: AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let address = self.get_write_address(mode);
         self.mem_write(address, self.register_a);
     }
 
     fn stx(&mut self, mode: AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let address = self.get_write_address(mode);
         self.mem_write(address, self.register_x);
     }
 
     fn sty(&mut self, mode: AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let address = self.get_write_address(mode);
         self.mem_write(address, self.register_y);
     }
 
@@ -298,21 +459,20 @@ impl<'a> CPU<'a> {
     }
 
     fn pla(&mut self, _: AddressingMode) {
+        self.stack_dummy_read();
         self.register_a = self.stack_pop();
         self.update_z_and_n_flags(self.register_a)
     }
 
     fn plp(&mut self, _: AddressingMode) {
-        self.status = CpuFlags::from_bits(self.stack_pop()).unwrap_or_else(|| panic!("invalid status register"));
+        self.stack_dummy_read();
+        self.status = CpuFlags::from_bits_truncate(self.stack_pop());
         self.status.insert(CpuFlags::BIT5);
         self.status.remove(CpuFlags::BREAK);
     }
 
     fn dec(&mut self, mode: AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let mut value = self.mem_read(addr);
-        value = value.wrapping_sub(1);
-        self.mem_write(addr, value);
+        let value = self.read_modify_write(mode, |_, value| value.wrapping_sub(1));
         self.update_z_and_n_flags(value);
     }
 
@@ -327,10 +487,7 @@ impl<'a> CPU<'a> {
     }
 
     fn inc(&mut self, mode: AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let mut value = self.mem_read(addr);
-        value = value.wrapping_add(1);
-        self.mem_write(addr, value);
+        let value = self.read_modify_write(mode, |_, value| value.wrapping_add(1));
         self.update_z_and_n_flags(value);
     }
 
@@ -346,12 +503,28 @@ impl<'a> CPU<'a> {
 
     fn adc(&mut self, mode: AddressingMode) {
         let value = self.get_operand_value(mode);
-        self.add_to_register_a(value);
+        self.add_with_carry(value);
+    }
+
+    fn add_with_carry(&mut self, value: u8) {
+        if self.decimal_mode() {
+            self.add_decimal_to_register_a(value);
+        } else {
+            self.add_to_register_a(value);
+        }
     }
 
     fn sbc(&mut self, mode: AddressingMode) {
         let value = self.get_operand_value(mode);
-        self.add_to_register_a(value.wrapping_neg().wrapping_sub(1));
+        self.subtract_with_borrow(value);
+    }
+
+    fn subtract_with_borrow(&mut self, value: u8) {
+        if self.decimal_mode() {
+            self.sub_decimal_from_register_a(value);
+        } else {
+            self.add_to_register_a(value.wrapping_neg().wrapping_sub(1));
+        }
     }
 
     fn and(&mut self, mode: AddressingMode) {
@@ -373,124 +546,81 @@ impl<'a> CPU<'a> {
     }
 
     fn asl(&mut self, mode: AddressingMode) {
-        let address = self.get_operand_address(mode);
-        let mut value = self.get_operand_value(mode);
-        if value & 0b10000000 == 0b10000000 {
-            self.status.insert(CpuFlags::CARRY);
-        } else {
-            self.status.remove(CpuFlags::CARRY);
-        }
-
-        value <<= 1;
-        self.mem_write(address, value);
+        let value = self.read_modify_write(mode, Self::shift_left);
         self.update_z_and_n_flags(value);
     }
 
     fn asla(&mut self, _: AddressingMode) {
-        if self.register_a & 0b10000000 == 0b10000000 {
-            self.status.insert(CpuFlags::CARRY);
-        } else {
-            self.status.remove(CpuFlags::CARRY);
-        }
-
-        self.register_a <<= 1;
+        self.register_a = self.shift_left(self.register_a);
         self.update_z_and_n_flags(self.register_a);
     }
 
-    fn lsr(&mut self, mode: AddressingMode) {
-        let address = self.get_operand_address(mode);
-        let mut value = self.get_operand_value(mode);
-        if value & 0b00000001 == 0b00000001 {
+    fn shift_left(&mut self, value: u8) -> u8 {
+        if value & 0b10000000 == 0b10000000 {
             self.status.insert(CpuFlags::CARRY);
         } else {
             self.status.remove(CpuFlags::CARRY);
         }
 
-        value >>= 1;
-        self.mem_write(address, value);
+        value << 1
+    }
+
+    fn lsr(&mut self, mode: AddressingMode) {
+        let value = self.read_modify_write(mode, Self::shift_right);
         self.update_z_and_n_flags(value);
     }
 
     fn lsra(&mut self, _: AddressingMode) {
-        if self.register_a & 0b00000001 == 0b00000001 {
-            self.status.insert(CpuFlags::CARRY);
-        } else {
-            self.status.remove(CpuFlags::CARRY);
-        }
-
-        self.register_a >>= 1;
+        self.register_a = self.shift_right(self.register_a);
         self.update_z_and_n_flags(self.register_a);
     }
 
-    fn rol(&mut self, mode: AddressingMode) {
-        let address = self.get_operand_address(mode);
-        let mut value = self.get_operand_value(mode);
-        let old_carry = self.status.contains(CpuFlags::CARRY);
-        if value & 0b10000000 == 0b10000000 {
+    fn shift_right(&mut self, value: u8) -> u8 {
+        if value & 0b00000001 == 0b00000001 {
             self.status.insert(CpuFlags::CARRY);
         } else {
             self.status.remove(CpuFlags::CARRY);
         }
 
-        value <<= 1;
-        if old_carry {
-            value |= 1;
-        }
+        value >> 1
+    }
 
-        self.mem_write(address, value);
+    fn rol(&mut self, mode: AddressingMode) {
+        let value = self.read_modify_write(mode, Self::rotate_left);
         self.update_z_and_n_flags(value);
     }
 
     fn rola(&mut self, _: AddressingMode) {
-        let old_carry = self.status.contains(CpuFlags::CARRY);
-        if self.register_a & 0b10000000 == 0b10000000 {
-            self.status.insert(CpuFlags::CARRY);
-        } else {
-            self.status.remove(CpuFlags::CARRY);
-        }
-
-        self.register_a <<= 1;
-        if old_carry {
-            self.register_a |= 1;
-        }
-
+        self.register_a = self.rotate_left(self.register_a);
         self.update_z_and_n_flags(self.register_a);
     }
 
-
-    fn ror(&mut self, mode: AddressingMode) {
-        let address = self.get_operand_address(mode);
-        let mut value = self.get_operand_value(mode);
+    fn rotate_left(&mut self, value: u8) -> u8 {
         let old_carry = self.status.contains(CpuFlags::CARRY);
-        if value & 0b00000001 == 0b00000001 {
-            self.status.insert(CpuFlags::CARRY);
-        } else {
-            self.status.remove(CpuFlags::CARRY);
-        }
-
-        value >>= 1;
+        let mut value = self.shift_left(value);
         if old_carry {
-            value |= 0b10000000;
+            value |= 1;
         }
+        value
+    }
 
-        self.mem_write(address, value);
+    fn ror(&mut self, mode: AddressingMode) {
+        let value = self.read_modify_write(mode, Self::rotate_right);
         self.update_z_and_n_flags(value);
     }
 
     fn rora(&mut self, _: AddressingMode) {
-        let old_carry = self.status.contains(CpuFlags::CARRY);
-        if self.register_a & 0b00000001 == 0b00000001 {
-            self.status.insert(CpuFlags::CARRY);
-        } else {
-            self.status.remove(CpuFlags::CARRY);
-        }
+        self.register_a = self.rotate_right(self.register_a);
+        self.update_z_and_n_flags(self.register_a);
+    }
 
-        self.register_a >>= 1;
+    fn rotate_right(&mut self, value: u8) -> u8 {
+        let old_carry = self.status.contains(CpuFlags::CARRY);
+        let mut value = self.shift_right(value);
         if old_carry {
-            self.register_a |= 0b10000000;
+            value |= 0b10000000;
         }
-
-        self.update_z_and_n_flags(self.register_a);
+        value
     }
 
     fn clc(&mut self, _: AddressingMode) {
@@ -523,44 +653,40 @@ impl<'a> CPU<'a> {
 
     fn cmp(&mut self, mode: AddressingMode) {
         let value = self.get_operand_value(mode);
-
-        if self.register_a < value {
-            self.status.remove(CpuFlags::CARRY);
-        } else {
-            self.status.insert(CpuFlags::CARRY);
-        }
-
-        self.update_z_and_n_flags(self.register_a.wrapping_sub(value));
+        self.compare(self.register_a, value);
     }
 
     fn cpx(&mut self, mode: AddressingMode) {
         let value = self.get_operand_value(mode);
-
-        if self.register_x < value {
-            self.status.remove(CpuFlags::CARRY);
-        } else {
-            self.status.insert(CpuFlags::CARRY);
-        }
-
-        self.update_z_and_n_flags(self.register_x.wrapping_sub(value));
+        self.compare(self.register_x, value);
     }
 
     fn cpy(&mut self, mode: AddressingMode) {
         let value = self.get_operand_value(mode);
+        self.compare(self.register_y, value);
+    }
 
-        if self.register_y < value {
+    fn compare(&mut self, register: u8, value: u8) {
+        if register < value {
             self.status.remove(CpuFlags::CARRY);
         } else {
             self.status.insert(CpuFlags::CARRY);
         }
 
-        self.update_z_and_n_flags(self.register_y.wrapping_sub(value));
+        self.update_z_and_n_flags(register.wrapping_sub(value));
     }
 
+    // a taken branch reads the next opcode while it adds the offset, and again when the target is
+    // on another page
     fn branch(&mut self, condition: bool) {
+        let offset: i8 = self.mem_read(self.program_counter) as i8;
         if condition {
-            let offset: i8 = self.mem_read(self.program_counter) as i8;
-            let jump_addr = self.program_counter.wrapping_add(1).wrapping_add(offset as u16);
+            let next = self.program_counter.wrapping_add(1);
+            let jump_addr = next.wrapping_add(offset as u16);
+            self.mem_read(next);
+            if jump_addr & 0xFF00 != next & 0xFF00 {
+                self.mem_read(next & 0xFF00 | jump_addr & 0x00FF);
+            }
             self.program_counter = jump_addr;
         }
     }
@@ -604,8 +730,8 @@ impl<'a> CPU<'a> {
             let mem_address = self.mem_read_u16(self.program_counter);
 
             // let indirect_ref = self.mem_read_u16(mem_address);
-            // 6502 bug with page boundary (http://www.6502.org/tutorials/6502opcodes.html#JMP)
-            let indirect_ref = if mem_address & 0x00FF == 0x00FF {
+            // 6502 bug with page boundary (http://www.6502.org/tutorials/6502opcodes.html#JMP), fixed on the 65C02
+            let indirect_ref = if mem_address & 0x00FF == 0x00FF && self.variant != CpuVariant::CMOS65C02 {
                 let lo = self.mem_read(mem_address);
                 let hi = self.mem_read(mem_address & 0xFF00);
                 (hi as u16) << 8 | (lo as u16)
@@ -614,27 +740,43 @@ impl<'a> CPU<'a> {
             };
 
             self.program_counter = indirect_ref;
+        } else if mode == AddressingMode::AbsoluteIndexedIndirect {
+            self.program_counter = self.get_operand_address(mode);
         }
     }
 
-    fn jsr(&mut self, mode: AddressingMode) {
+    // the high byte of the target is read last, after the return address is pushed
+    fn jsr(&mut self, _: AddressingMode) {
+        let lo = self.mem_read(self.program_counter);
+        self.stack_dummy_read();
         self.stack_push_u16(self.program_counter + 2 - 1);
-        let address = self.get_operand_address(mode);
-        self.program_counter = address
+        let hi = self.mem_read(self.program_counter + 1);
+        self.program_counter = (hi as u16) << 8 | lo as u16;
     }
 
     fn rts(&mut self, _: AddressingMode) {
-        self.program_counter = self.stack_pop_u16() + 1;
+        self.stack_dummy_read();
+        let address = self.stack_pop_u16();
+        // one more cycle to step past the JSR
+        self.mem_read(address);
+        self.program_counter = address + 1;
     }
 
     fn rti(&mut self, _: AddressingMode) {
-        self.status = CpuFlags::from_bits(self.stack_pop()).unwrap_or_else(|| panic!("invalid status register"));
+        self.stack_dummy_read();
+        self.status = CpuFlags::from_bits_truncate(self.stack_pop());
         self.status.insert(CpuFlags::BIT5);
         self.program_counter = self.stack_pop_u16();
     }
 
     fn bit(&mut self, mode: AddressingMode) {
         let value = self.get_operand_value(mode);
+        // the 65C02 immediate mode only affects the zero flag
+        if mode == AddressingMode::Immediate {
+            self.status.set(CpuFlags::ZERO, self.register_a & value == 0);
+            return;
+        }
+
         let negative = (value & 0b1000_0000) == 0b1000_0000;
         if negative {
             self.status.insert(CpuFlags::NEGATIVE);
@@ -664,38 +806,41 @@ impl<'a> CPU<'a> {
     }
 
     fn aax(&mut self, mode: AddressingMode) {
-        let address = self.get_operand_address(mode);
+        let address = self.get_write_address(mode);
         self.mem_write(address, self.register_a & self.register_x);
     }
 
     fn dcp(&mut self, mode: AddressingMode) {
-        self.dec(mode);
-        self.cmp(mode);
+        let value = self.read_modify_write(mode, |_, value| value.wrapping_sub(1));
+        self.compare(self.register_a, value);
     }
 
     fn isc(&mut self, mode: AddressingMode) {
-        self.inc(mode);
-        self.sbc(mode);
+        let value = self.read_modify_write(mode, |_, value| value.wrapping_add(1));
+        self.subtract_with_borrow(value);
     }
 
     fn slo(&mut self, mode: AddressingMode) {
-        self.asl(mode);
-        self.ora(mode);
+        let value = self.read_modify_write(mode, Self::shift_left);
+        self.register_a |= value;
+        self.update_z_and_n_flags(self.register_a);
     }
 
     fn rla(&mut self, mode: AddressingMode) {
-        self.rol(mode);
-        self.and(mode);
+        let value = self.read_modify_write(mode, Self::rotate_left);
+        self.register_a &= value;
+        self.update_z_and_n_flags(self.register_a);
     }
 
     fn sre(&mut self, mode: AddressingMode) {
-        self.lsr(mode);
-        self.eor(mode);
+        let value = self.read_modify_write(mode, Self::shift_right);
+        self.register_a ^= value;
+        self.update_z_and_n_flags(self.register_a);
     }
 
     fn rra(&mut self, mode: AddressingMode) {
-        self.ror(mode);
-        self.adc(mode);
+        let value = self.read_modify_write(mode, Self::rotate_right);
+        self.add_with_carry(value);
     }
 
     fn anc(&mut self, mode: AddressingMode) {
@@ -734,10 +879,132 @@ impl<'a> CPU<'a> {
         self.and(mode);
         self.txa(mode);
     }
+
+    fn axs(&mut self, mode: AddressingMode) {
+        let value = self.get_operand_value(mode);
+        let and = self.register_a & self.register_x;
+
+        if and < value {
+            self.status.remove(CpuFlags::CARRY);
+        } else {
+            self.status.insert(CpuFlags::CARRY);
+        }
+
+        self.register_x = and.wrapping_sub(value);
+        self.update_z_and_n_flags(self.register_x);
+    }
+
+    fn las(&mut self, mode: AddressingMode) {
+        let value = self.get_operand_value(mode) & self.stack_pointer;
+        self.register_a = value;
+        self.register_x = value;
+        self.stack_pointer = value;
+        self.update_z_and_n_flags(value);
+    }
+
+    // the magic constant depends on the chip and temperature, 0xEE is the commonly accepted value
+    // https://www.nesdev.org/wiki/Visual6502wiki/6502_Opcode_8B_(XAA,_ANE)
+    fn xaa(&mut self, mode: AddressingMode) {
+        let value = self.get_operand_value(mode);
+        self.register_a = (self.register_a | 0xEE) & self.register_x & value;
+        self.update_z_and_n_flags(self.register_a);
+    }
+
+    fn shx(&mut self, mode: AddressingMode) {
+        self.store_and_high_byte(mode, self.register_x);
+    }
+
+    fn shy(&mut self, mode: AddressingMode) {
+        self.store_and_high_byte(mode, self.register_y);
+    }
+
+    fn ahx(&mut self, mode: AddressingMode) {
+        self.store_and_high_byte(mode, self.register_a & self.register_x);
+    }
+
+    fn tas(&mut self, mode: AddressingMode) {
+        self.stack_pointer = self.register_a & self.register_x;
+        self.store_and_high_byte(mode, self.stack_pointer);
+    }
+
+    // SHX, SHY, AHX and TAS store the value ANDed with the high byte of the base address + 1,
+    // when indexing crosses a page the stored value also replaces the high byte of the target address
+    fn store_and_high_byte(&mut self, mode: AddressingMode, value: u8) {
+        let (base, index) = match mode {
+            AddressingMode::AbsoluteX => (self.mem_read_u16(self.program_counter), self.register_x),
+            AddressingMode::AbsoluteY => (self.mem_read_u16(self.program_counter), self.register_y),
+            AddressingMode::IndirectY => {
+                let ptr = self.mem_read(self.program_counter);
+                let lo = self.mem_read(ptr as u16);
+                let hi = self.mem_read(ptr.wrapping_add(1) as u16);
+                ((hi as u16) << 8 | (lo as u16), self.register_y)
+            }
+            _ => unreachable!("mode {:?} is not used by the unstable store opcodes", mode),
+        };
+
+        let address = self.index(base, index, true);
+        let value = value & ((base >> 8) as u8).wrapping_add(1);
+        let address = if address & 0xFF00 != base & 0xFF00 {
+            (value as u16) << 8 | (address & 0x00FF)
+        } else {
+            address
+        };
+        self.mem_write(address, value);
+    }
+
+    /* ----- 65C02 ----- */
+    fn bra(&mut self, _: AddressingMode) {
+        self.branch(true);
+    }
+
+    fn phx(&mut self, _: AddressingMode) { self.stack_push(self.register_x); }
+
+    fn phy(&mut self, _: AddressingMode) { self.stack_push(self.register_y); }
+
+    fn plx(&mut self, _: AddressingMode) {
+        self.stack_dummy_read();
+        self.register_x = self.stack_pop();
+        self.update_z_and_n_flags(self.register_x)
+    }
+
+    fn ply(&mut self, _: AddressingMode) {
+        self.stack_dummy_read();
+        self.register_y = self.stack_pop();
+        self.update_z_and_n_flags(self.register_y)
+    }
+
+    fn stz(&mut self, mode: AddressingMode) {
+        let address = self.get_write_address(mode);
+        self.mem_write(address, 0);
+    }
+
+    fn trb(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, |cpu, value| {
+            cpu.status.set(CpuFlags::ZERO, cpu.register_a & value == 0);
+            value & !cpu.register_a
+        });
+    }
+
+    fn tsb(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, |cpu, value| {
+            cpu.status.set(CpuFlags::ZERO, cpu.register_a & value == 0);
+            value | cpu.register_a
+        });
+    }
+
+    fn inca(&mut self, _: AddressingMode) {
+        self.register_a = self.register_a.wrapping_add(1);
+        self.update_z_and_n_flags(self.register_a);
+    }
+
+    fn deca(&mut self, _: AddressingMode) {
+        self.register_a = self.register_a.wrapping_sub(1);
+        self.update_z_and_n_flags(self.register_a);
+    }
     /* ----------------------------------------- */
 
     pub fn reset_and_run(&mut self) {
-        self.reset();
+        self.power_on();
         self.run();
     }
 
@@ -748,27 +1015,50 @@ impl<'a> CPU<'a> {
         self.mem_write_u16(0xFFFC, 0x0000);
     }
 
-    pub fn reset(&mut self) {
+    // https://www.nesdev.org/wiki/CPU_power_up_state
+    pub fn power_on(&mut self) {
+        self.bus.power_on();
         self.register_a = 0;
         self.register_x = 0;
         self.register_y = 0;
         self.status = CpuFlags::from_bits_truncate(0b100100);
-        self.stack_pointer = STACK_START;
+        // the stack pointer powers up as $00, the reset sequence brings it down to $FD
+        self.stack_pointer = 0;
+        self.restart();
+    }
+
+    // the reset button, registers and RAM keep their values
+    pub fn reset(&mut self) {
+        self.bus.reset();
+        self.restart();
+    }
+
+    // the reset sequence runs the interrupt sequence with the stack writes turned into reads
+    fn restart(&mut self) {
+        self.stack_pointer = self.stack_pointer.wrapping_sub(3);
+        self.status.insert(CpuFlags::INTERRUPT);
+        self.fault = None;
 
         self.program_counter = self.mem_read_u16(0xFFFC);
     }
 
     fn interrupt(&mut self, interrupt: interrupt::Interrupt) {
+        // the opcode fetch and the operand read that are thrown away
+        self.mem_read(self.program_counter);
+        self.mem_read(self.program_counter);
         self.stack_push_u16(self.program_counter);
         let mut flag = self.status.clone();
-        flag.set(CpuFlags::BREAK, interrupt.b_flag_mask & 0b010000 == 1);
-        flag.set(CpuFlags::BIT5, interrupt.b_flag_mask & 0b100000 == 1);
+        flag.set(CpuFlags::BREAK, interrupt.b_flag_mask & 0b010000 != 0);
+        flag.set(CpuFlags::BIT5, interrupt.b_flag_mask & 0b100000 != 0);
 
         self.stack_push(flag.bits());
         self.status.insert(CpuFlags::INTERRUPT);
+        if self.variant == CpuVariant::CMOS65C02 {
+            self.status.remove(CpuFlags::DECIMAL);
+        }
 
-        self.bus.tick(interrupt.cpu_cycles);
         self.program_counter = self.mem_read_u16(interrupt.vector_addr);
+        self.bus.tick(interrupt.cpu_cycles);
     }
 
     pub fn run(&mut self) {
@@ -777,7 +1067,7 @@ impl<'a> CPU<'a> {
 
     pub fn run_with_callback<F>(&mut self, mut callback: F)
     where
-        F: FnMut(&mut CPU),
+        F: FnMut(&mut CPU<B>),
     {
         loop {
             if self.step(&mut callback) {
@@ -786,12 +1076,19 @@ impl<'a> CPU<'a> {
         }
     }
 
+    // returns true when the CPU stops, either on BRK or on a fault
     pub fn step<F>(&mut self, mut callback: F) -> bool
     where
-        F: FnMut(&mut CPU),
+        F: FnMut(&mut CPU<B>),
     {
+        if self.fault.is_some() {
+            return true;
+        }
+
         if let Some(_nmi) = self.bus.poll_nmi_status() {
             self.interrupt(interrupt::NMI);
+        } else if self.bus.poll_irq_status() && !self.status.contains(CpuFlags::INTERRUPT) {
+            self.interrupt(interrupt::IRQ);
         }
 
         callback(self);
@@ -799,7 +1096,11 @@ impl<'a> CPU<'a> {
         self.program_counter += 1;
         let old_counter = self.program_counter;
 
-        if let Some(opcode) = OPCODES.get(&opcode_byte) {
+        if let Some(opcode) = self.variant.opcodes().get(&opcode_byte) {
+            // single byte instructions read the next byte anyway
+            if opcode.bytes == 1 {
+                self.mem_read(self.program_counter);
+            }
             match opcode.instruction {
                 Instruction::TAX => {
                     self.tax(opcode.addressing_mode);
@@ -978,7 +1279,11 @@ impl<'a> CPU<'a> {
                 Instruction::BIT => {
                     self.bit(opcode.addressing_mode);
                 }
-                Instruction::NOP | Instruction::DOP | Instruction::TOP => {}
+                Instruction::NOP | Instruction::DOP | Instruction::TOP => {
+                    if opcode.addressing_mode != AddressingMode::Implicit {
+                        self.get_operand_value(opcode.addressing_mode);
+                    }
+                }
                 Instruction::LAX => {
                     self.lax(opcode.addressing_mode);
                 }
@@ -1018,6 +1323,63 @@ impl<'a> CPU<'a> {
                 Instruction::ATX => {
                     self.atx(opcode.addressing_mode);
                 }
+                Instruction::AXS => {
+                    self.axs(opcode.addressing_mode);
+                }
+                Instruction::LAS => {
+                    self.las(opcode.addressing_mode);
+                }
+                Instruction::XAA => {
+                    self.xaa(opcode.addressing_mode);
+                }
+                Instruction::SHX => {
+                    self.shx(opcode.addressing_mode);
+                }
+                Instruction::SHY => {
+                    self.shy(opcode.addressing_mode);
+                }
+                Instruction::AHX => {
+                    self.ahx(opcode.addressing_mode);
+                }
+                Instruction::TAS => {
+                    self.tas(opcode.addressing_mode);
+                }
+                Instruction::BRA => {
+                    self.bra(opcode.addressing_mode);
+                }
+                Instruction::PHX => {
+                    self.phx(opcode.addressing_mode);
+                }
+                Instruction::PHY => {
+                    self.phy(opcode.addressing_mode);
+                }
+                Instruction::PLX => {
+                    self.plx(opcode.addressing_mode);
+                }
+                Instruction::PLY => {
+                    self.ply(opcode.addressing_mode);
+                }
+                Instruction::STZ => {
+                    self.stz(opcode.addressing_mode);
+                }
+                Instruction::TRB => {
+                    self.trb(opcode.addressing_mode);
+                }
+                Instruction::TSB => {
+                    self.tsb(opcode.addressing_mode);
+                }
+                Instruction::INCA => {
+                    self.inca(opcode.addressing_mode);
+                }
+                Instruction::DECA => {
+                    self.deca(opcode.addressing_mode);
+                }
+                Instruction::KIL => {
+                    // the program counter stays on the KIL opcode until reset
+                    self.program_counter -= 1;
+                    self.fault = Some(CpuFault::Jammed { opcode: opcode_byte, addr: self.program_counter });
+                    return true;
+                }
             }
 
             self.bus.tick(opcode.cycles);
@@ -1025,7 +1387,9 @@ impl<'a> CPU<'a> {
                 self.program_counter += opcode.bytes - 1;
             }
         } else {
-            panic!("Illegal instruction: 0x{:02X}", opcode_byte);
+            self.program_counter -= 1;
+            self.fault = Some(CpuFault::IllegalInstruction { opcode: opcode_byte, addr: self.program_counter });
+            return true;
         }
 
         false
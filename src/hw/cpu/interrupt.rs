@@ -1,6 +1,7 @@
 #[derive(PartialEq, Eq)]
 pub enum InterruptType {
     NMI,
+    IRQ,
 }
 
 #[derive(PartialEq, Eq)]
@@ -14,5 +15,11 @@ pub(super) const NMI: Interrupt = Interrupt {
     itype: InterruptType::NMI,
     vector_addr: 0xfffA,
     b_flag_mask: 0b00100000,
-    cpu_cycles: 2,
+    cpu_cycles: 7,
+};
+pub(super) const IRQ: Interrupt = Interrupt {
+    itype: InterruptType::IRQ,
+    vector_addr: 0xFFFE,
+    b_flag_mask: 0b00100000,
+    cpu_cycles: 7,
 };
\ No newline at end of file
@@ -201,6 +201,54 @@ pub enum Instruction {
     #[strum(serialize = "*ATX")]
     // ATX - AND byte with accumulator, then transfer accumulator to X register
     ATX,
+    #[strum(serialize = "*KIL")]
+    // KIL - Stop program counter (processor lock up)
+    KIL,
+    #[strum(serialize = "*AXS")]
+    // AXS - AND X register with accumulator, subtract byte from it and store result in X register
+    AXS,
+    #[strum(serialize = "*LAS")]
+    // LAS - AND memory with stack pointer, transfer result to accumulator, X register and stack pointer
+    LAS,
+    #[strum(serialize = "*XAA")]
+    // XAA - unstable, (accumulator OR magic constant) AND X register AND byte into accumulator
+    XAA,
+    #[strum(serialize = "*SHX")]
+    // SHX - unstable, store X register AND (high byte of address + 1)
+    SHX,
+    #[strum(serialize = "*SHY")]
+    // SHY - unstable, store Y register AND (high byte of address + 1)
+    SHY,
+    #[strum(serialize = "*AHX")]
+    // AHX - unstable, store accumulator AND X register AND (high byte of address + 1)
+    AHX,
+    #[strum(serialize = "*TAS")]
+    // TAS - unstable, transfer accumulator AND X register to stack pointer, then store it like AHX
+    TAS,
+
+    /* ----- 65C02 ----- */
+    // BRA - branch always
+    BRA,
+    // PHX - push X register on stack
+    PHX,
+    // PHY - push Y register on stack
+    PHY,
+    // PLX - pull X register
+    PLX,
+    // PLY - pull Y register
+    PLY,
+    // STZ - store zero into memory
+    STZ,
+    // TRB - test and reset memory bits with accumulator
+    TRB,
+    // TSB - test and set memory bits with accumulator
+    TSB,
+    // INCA - increment accumulator
+    #[strum(serialize = "INC A")]
+    INCA,
+    // DECA - decrement accumulator
+    #[strum(serialize = "DEC A")]
+    DECA,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -504,7 +552,7 @@ lazy_static::lazy_static! {
         map.insert(0xFA, OpCode::new(Instruction::DOP, 1, 2, AddressingMode::Implicit));
         map.insert(0xDA, OpCode::new(Instruction::DOP, 1, 2, AddressingMode::Implicit));
         map.insert(0x1A, OpCode::new(Instruction::DOP, 1, 2, AddressingMode::Implicit));
-        map.insert(0x04, OpCode::new(Instruction::DOP, 2, 4, AddressingMode::ZeroPage));
+        map.insert(0x04, OpCode::new(Instruction::DOP, 2, 3, AddressingMode::ZeroPage));
         map.insert(0x14, OpCode::new(Instruction::DOP, 2, 4, AddressingMode::ZeroPageX));
         map.insert(0x34, OpCode::new(Instruction::DOP, 2, 4, AddressingMode::ZeroPageX));
         map.insert(0x44, OpCode::new(Instruction::DOP, 2, 3, AddressingMode::ZeroPage));
@@ -612,6 +660,108 @@ lazy_static::lazy_static! {
         // ATX
         map.insert(0xAB, OpCode::new(Instruction::ATX, 2, 2, AddressingMode::Immediate));
 
+        // AXS
+        map.insert(0xCB, OpCode::new(Instruction::AXS, 2, 2, AddressingMode::Immediate));
+
+        // LAS
+        map.insert(0xBB, OpCode::new(Instruction::LAS, 3, 4, AddressingMode::AbsoluteY));
+
+        // XAA
+        map.insert(0x8B, OpCode::new(Instruction::XAA, 2, 2, AddressingMode::Immediate));
+
+        // SHX
+        map.insert(0x9E, OpCode::new(Instruction::SHX, 3, 5, AddressingMode::AbsoluteY));
+
+        // SHY
+        map.insert(0x9C, OpCode::new(Instruction::SHY, 3, 5, AddressingMode::AbsoluteX));
+
+        // AHX
+        map.insert(0x9F, OpCode::new(Instruction::AHX, 3, 5, AddressingMode::AbsoluteY));
+        map.insert(0x93, OpCode::new(Instruction::AHX, 2, 6, AddressingMode::IndirectY));
+
+        // TAS
+        map.insert(0x9B, OpCode::new(Instruction::TAS, 3, 5, AddressingMode::AbsoluteY));
+
+        // KIL
+        for code in [0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2] {
+            map.insert(code, OpCode::new(Instruction::KIL, 1, 2, AddressingMode::Implicit));
+        }
+
+        map
+    };
+}
+
+lazy_static::lazy_static! {
+    // documented NMOS opcodes plus the 65C02 additions, undefined opcodes are NOPs
+    // http://www.6502.org/tutorials/65c02opcodes.html
+    pub static ref OPCODES_65C02: HashMap<u8, OpCode> = {
+        let mut map: HashMap<u8, OpCode> = OPCODES.iter()
+            .filter(|(_, opcode)| !opcode.instruction.to_string().starts_with('*'))
+            .map(|(code, opcode)| (*code, *opcode))
+            .collect();
+
+        // JMP (abs) reads across the page boundary and takes a cycle longer
+        map.insert(0x6C, OpCode::new(Instruction::JMP, 3, 6, AddressingMode::Indirect));
+        map.insert(0x7C, OpCode::new(Instruction::JMP, 3, 6, AddressingMode::AbsoluteIndexedIndirect));
+
+        // (zp) addressing
+        map.insert(0x12, OpCode::new(Instruction::ORA, 2, 5, AddressingMode::ZeroPageIndirect));
+        map.insert(0x32, OpCode::new(Instruction::AND, 2, 5, AddressingMode::ZeroPageIndirect));
+        map.insert(0x52, OpCode::new(Instruction::EOR, 2, 5, AddressingMode::ZeroPageIndirect));
+        map.insert(0x72, OpCode::new(Instruction::ADC, 2, 5, AddressingMode::ZeroPageIndirect));
+        map.insert(0x92, OpCode::new(Instruction::STA, 2, 5, AddressingMode::ZeroPageIndirect));
+        map.insert(0xB2, OpCode::new(Instruction::LDA, 2, 5, AddressingMode::ZeroPageIndirect));
+        map.insert(0xD2, OpCode::new(Instruction::CMP, 2, 5, AddressingMode::ZeroPageIndirect));
+        map.insert(0xF2, OpCode::new(Instruction::SBC, 2, 5, AddressingMode::ZeroPageIndirect));
+
+        // BIT
+        map.insert(0x89, OpCode::new(Instruction::BIT, 2, 2, AddressingMode::Immediate));
+        map.insert(0x34, OpCode::new(Instruction::BIT, 2, 4, AddressingMode::ZeroPageX));
+        map.insert(0x3C, OpCode::new(Instruction::BIT, 3, 4, AddressingMode::AbsoluteX));
+
+        // BRA
+        map.insert(0x80, OpCode::new(Instruction::BRA, 2, 3, AddressingMode::Relative));
+
+        // PHX, PHY, PLX, PLY
+        map.insert(0xDA, OpCode::new(Instruction::PHX, 1, 3, AddressingMode::Implicit));
+        map.insert(0x5A, OpCode::new(Instruction::PHY, 1, 3, AddressingMode::Implicit));
+        map.insert(0xFA, OpCode::new(Instruction::PLX, 1, 4, AddressingMode::Implicit));
+        map.insert(0x7A, OpCode::new(Instruction::PLY, 1, 4, AddressingMode::Implicit));
+
+        // STZ
+        map.insert(0x64, OpCode::new(Instruction::STZ, 2, 3, AddressingMode::ZeroPage));
+        map.insert(0x74, OpCode::new(Instruction::STZ, 2, 4, AddressingMode::ZeroPageX));
+        map.insert(0x9C, OpCode::new(Instruction::STZ, 3, 4, AddressingMode::Absolute));
+        map.insert(0x9E, OpCode::new(Instruction::STZ, 3, 5, AddressingMode::AbsoluteX));
+
+        // TRB, TSB
+        map.insert(0x14, OpCode::new(Instruction::TRB, 2, 5, AddressingMode::ZeroPage));
+        map.insert(0x1C, OpCode::new(Instruction::TRB, 3, 6, AddressingMode::Absolute));
+        map.insert(0x04, OpCode::new(Instruction::TSB, 2, 5, AddressingMode::ZeroPage));
+        map.insert(0x0C, OpCode::new(Instruction::TSB, 3, 6, AddressingMode::Absolute));
+
+        // INC A, DEC A
+        map.insert(0x1A, OpCode::new(Instruction::INCA, 1, 2, AddressingMode::Implicit));
+        map.insert(0x3A, OpCode::new(Instruction::DECA, 1, 2, AddressingMode::Implicit));
+
+        // undefined opcodes
+        for row in 0..16u8 {
+            for column in [0x03, 0x07, 0x0B, 0x0F] {
+                map.insert(row << 4 | column, OpCode::new(Instruction::NOP, 1, 1, AddressingMode::Implicit));
+            }
+        }
+        for code in [0x02, 0x22, 0x42, 0x62, 0x82, 0xC2, 0xE2] {
+            map.insert(code, OpCode::new(Instruction::NOP, 2, 2, AddressingMode::Immediate));
+        }
+        map.insert(0x44, OpCode::new(Instruction::NOP, 2, 3, AddressingMode::ZeroPage));
+        for code in [0x54, 0xD4, 0xF4] {
+            map.insert(code, OpCode::new(Instruction::NOP, 2, 4, AddressingMode::ZeroPageX));
+        }
+        map.insert(0x5C, OpCode::new(Instruction::NOP, 3, 8, AddressingMode::Absolute));
+        for code in [0xDC, 0xFC] {
+            map.insert(code, OpCode::new(Instruction::NOP, 3, 4, AddressingMode::Absolute));
+        }
+
         map
     };
 }
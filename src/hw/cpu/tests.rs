@@ -6,7 +6,10 @@ mod test {
     use crate::hw::cpu::STACK_START;
     use crate::hw::cpu::tracer::trace;
     use crate::hw::memory::Memory;
-    use crate::hw::cpu::{CpuFlags, CPU};
+    use crate::hw::ram_bus::RamBus;
+    use crate::hw::cpu::{AddressingMode, CpuFault, CpuFlags, CPU};
+    use crate::hw::cpu::opcodes::{Instruction, OPCODES};
+    use crate::hw::cpu::variant::CpuVariant;
 
     struct TestCartridge {
         header: Vec<u8>,
@@ -49,7 +52,7 @@ mod test {
         Cartridge::new(test_rom).unwrap()
     }
 
-    fn create_cpu<'a>(program: Vec<u8>) -> CPU<'a> {
+    fn create_cpu<'a>(program: Vec<u8>) -> CPU<Bus<'a>> {
         CPU::new(Bus::new(Some(test_cartridge(program)), move |_, _| {}))
     }
 
@@ -116,7 +119,7 @@ mod test {
     #[test]
     fn test_0xa9_lda_zero_flag() {
         let mut cpu = create_cpu(vec![0xa9, 0x00, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.status.bitand(CpuFlags::ZERO).bits(), 0b10);
@@ -125,7 +128,7 @@ mod test {
     #[test]
     fn test_0xa2_ldx_zero_flag() {
         let mut cpu = create_cpu(vec![0xa2, 0x00, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.status.bitand(CpuFlags::ZERO).bits(), 0b10);
@@ -134,7 +137,7 @@ mod test {
     #[test]
     fn test_0xa2_ldy_zero_flag() {
         let mut cpu = create_cpu(vec![0xa0, 0x00, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.status.bitand(CpuFlags::ZERO).bits(), 0b10);
@@ -143,7 +146,7 @@ mod test {
     #[test]
     fn test_0xaa_tax_move_a_to_x() {
         let mut cpu = create_cpu(vec![0xaa, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_x, 0)
@@ -152,7 +155,7 @@ mod test {
     #[test]
     fn test_0xa8_tay_move_a_to_y() {
         let mut cpu = create_cpu(vec![0xa8, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_y, 0)
@@ -161,7 +164,7 @@ mod test {
     #[test]
     fn test_0xba_tsx_move_sp_to_x() {
         let mut cpu = create_cpu(vec![0xba, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_x, STACK_START)
@@ -170,7 +173,7 @@ mod test {
     #[test]
     fn test_0x8a_txa_move_x_to_a() {
         let mut cpu = create_cpu(vec![0x8a, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0)
@@ -179,7 +182,7 @@ mod test {
     #[test]
     fn test_0x9a_txs_move_x_to_sp() {
         let mut cpu = create_cpu(vec![0x9a, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.stack_pointer, 0)
@@ -188,7 +191,7 @@ mod test {
     #[test]
     fn test_0x98_tya_move_y_to_a() {
         let mut cpu = create_cpu(vec![0x98, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0)
@@ -197,7 +200,7 @@ mod test {
     #[test]
     fn test_0xa9_lda_immediate_load_data() {
         let mut cpu = create_cpu(vec![0xa9, 0x05, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x05);
@@ -208,7 +211,7 @@ mod test {
     #[test]
     fn test_inx_overflow() {
         let mut cpu = create_cpu(vec![0xe8, 0xe8, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_x, 2)
@@ -220,7 +223,7 @@ mod test {
                                       0xca,       // DEX
                                       0xca,       // DEX
                                       0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_x, 0);
@@ -231,7 +234,7 @@ mod test {
         let mut cpu = create_cpu(vec![0xa2, 0x00,  // LDX #$00
                                       0xca,       // DEX (should wrap to 0xff)
                                       0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_x, 0xff);
@@ -243,7 +246,7 @@ mod test {
                                       0x88,       // DEY
                                       0x88,       // DEY
                                       0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_y, 0);
@@ -254,7 +257,7 @@ mod test {
         let mut cpu = create_cpu(vec![0xa0, 0x00,  // LDY #$00
                                       0x88,       // DEY (should wrap to 0xff)
                                       0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_y, 0xff);
@@ -267,7 +270,7 @@ mod test {
                                       0xe6, 0x10,  // INC $10
                                       0xe6, 0x10,  // INC $10
                                       0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.mem_read(0x10), 0x00);
@@ -279,7 +282,7 @@ mod test {
                                       0xc8,       // INY
                                       0xc8,       // INY
                                       0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_y, 0x00);
@@ -292,7 +295,7 @@ mod test {
                                       0xc6, 0x10,  // DEC $10
                                       0xc6, 0x10,  // DEC $10
                                       0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.mem_read(0x10), 0x00);
@@ -304,7 +307,7 @@ mod test {
                                       0x85, 0x10,  // STA $10
                                       0xc6, 0x10,  // DEC $10 (should wrap to 0xff)
                                       0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.mem_read(0x10), 0xff);
@@ -313,7 +316,7 @@ mod test {
     #[test]
     fn test_5_ops_working_together() {
         let mut cpu = create_cpu(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_x, 0xc1)
@@ -333,7 +336,7 @@ mod test {
     #[test]
     fn test_0x48_pha_pushes_accumulator_to_stack() {
         let mut cpu = create_cpu(vec![0x48, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.mem_read(0x01FF), 0);
@@ -343,7 +346,7 @@ mod test {
     #[test]
     fn test_0x48_pha_with_zero_accumulator() {
         let mut cpu = create_cpu(vec![0x48, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.mem_read(0x01FF), 0x00);
@@ -353,7 +356,7 @@ mod test {
     #[test]
     fn test_0x48_pha_multiple_pushes() {
         let mut cpu = create_cpu(vec![0x48, 0xa9, 0x22, 0x48, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.mem_read(0x01FD), 0);
@@ -364,7 +367,7 @@ mod test {
     #[test]
     fn test_0x08_php_pushes_status_to_stack() {
         let mut cpu = create_cpu(vec![0x08, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         let pushed_status = cpu.mem_read(0x01FF);
@@ -376,7 +379,7 @@ mod test {
     #[test]
     fn test_0x08_php_sets_break_flag() {
         let mut cpu = create_cpu(vec![0x08, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         let pushed_status = cpu.mem_read(0x01FF);
@@ -386,7 +389,7 @@ mod test {
     #[test]
     fn test_0x08_php_with_empty_status() {
         let mut cpu = create_cpu(vec![0x08, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.stack_pointer, 0xFC);
@@ -395,7 +398,7 @@ mod test {
     #[test]
     fn test_0x68_pla_pulls_from_stack_to_accumulator() {
         let mut cpu = create_cpu(vec![0x48, 0xa9, 0x00, 0x68, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0);
@@ -405,7 +408,7 @@ mod test {
     #[test]
     fn test_0x68_pla_sets_zero_flag() {
         let mut cpu = create_cpu(vec![0x48, 0xa9, 0x42, 0x68, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x00);
@@ -415,7 +418,7 @@ mod test {
     #[test]
     fn test_0x28_plp_pulls_status_from_stack() {
         let mut cpu = create_cpu(vec![0x08, 0x28, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.status.clone().bitand(CpuFlags::ZERO).bits(), 0);
@@ -426,7 +429,7 @@ mod test {
     #[test]
     fn test_pha_pla_round_trip() {
         let mut cpu = create_cpu(vec![0x48, 0x68, 0x00]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0);
@@ -443,7 +446,7 @@ mod test {
             0x68,       // PLA - restore accumulator
             0x00        // BRK
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0);
@@ -454,7 +457,7 @@ mod test {
     #[test]
     fn test_stack_underflow_behavior() {
         let mut cpu = create_cpu(vec![0x68, 0x68, 0x68, 0x00]); // PLA, BRK
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x00);
@@ -468,7 +471,7 @@ mod test {
             0x29, 0x55,     // AND #$55
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x05);
@@ -483,7 +486,7 @@ mod test {
             0x29, 0x00,     // AND #$00
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x00);
@@ -497,7 +500,7 @@ mod test {
             0x29, 0xff,     // AND #$ff
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x80);
@@ -511,7 +514,7 @@ mod test {
             0x49, 0x55,     // EOR #$55
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0xff);
@@ -525,7 +528,7 @@ mod test {
             0x49, 0x55,     // EOR #$55
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x00);
@@ -539,7 +542,7 @@ mod test {
             0x09, 0xf0,     // ORA #$f0
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0xff);
@@ -553,7 +556,7 @@ mod test {
             0x09, 0x00,     // ORA #$00
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x00);
@@ -569,7 +572,7 @@ mod test {
             0x05, 0x10,     // ORA $10
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x3f);
@@ -585,7 +588,7 @@ mod test {
             0x25, 0x20,     // AND $20
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0xaa);
@@ -601,7 +604,7 @@ mod test {
             0x45, 0x30,     // EOR $30
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0xff);
@@ -616,7 +619,7 @@ mod test {
             0x0a,           // ASL A
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x84);
@@ -632,7 +635,7 @@ mod test {
             0x0a,           // ASL A
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x02);
@@ -648,7 +651,7 @@ mod test {
             0x06, 0x20,     // ASL $20
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.mem_read(0x20), 0x66);
@@ -662,7 +665,7 @@ mod test {
             0x4a,           // LSR A
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x42);
@@ -677,7 +680,7 @@ mod test {
             0x4a,           // LSR A
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x00);
@@ -694,7 +697,7 @@ mod test {
             0x2a,           // ROL A (rotate with carry)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0);
@@ -710,7 +713,7 @@ mod test {
             0x6a,           // ROR A (rotate with carry)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x40);
@@ -725,7 +728,7 @@ mod test {
             0x66, 0x40,     // ROR $40
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.mem_read(0x40), 0x00);
@@ -740,7 +743,7 @@ mod test {
             0x18,           // CLC
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::CARRY));
@@ -753,7 +756,7 @@ mod test {
             0x38,           // SEC
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(cpu.status.contains(CpuFlags::CARRY));
@@ -766,7 +769,7 @@ mod test {
             0xd8,           // CLD
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::DECIMAL));
@@ -779,7 +782,7 @@ mod test {
             0xf8,           // SED
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(cpu.status.contains(CpuFlags::DECIMAL));
@@ -792,7 +795,7 @@ mod test {
             0x58,           // CLI
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::INTERRUPT));
@@ -805,7 +808,7 @@ mod test {
             0x78,           // SEI
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(cpu.status.contains(CpuFlags::INTERRUPT));
@@ -822,7 +825,7 @@ mod test {
             0x58,           // CLI
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::CARRY));
@@ -837,7 +840,7 @@ mod test {
             0xc9, 0x42,     // CMP #$42
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(cpu.status.contains(CpuFlags::ZERO));
@@ -852,7 +855,7 @@ mod test {
             0xc9, 0x7f,     // CMP #$7f
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::ZERO));
@@ -867,7 +870,7 @@ mod test {
             0xc9, 0x41,     // CMP #$41
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::ZERO));
@@ -884,7 +887,7 @@ mod test {
             0xc5, 0x20,     // CMP $20
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::ZERO));
@@ -899,7 +902,7 @@ mod test {
             0xe0, 0xfe,     // CPX #$fe
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::ZERO));
@@ -916,7 +919,7 @@ mod test {
             0xe4, 0x10,     // CPX $10
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::ZERO));
@@ -931,7 +934,7 @@ mod test {
             0xc0, 0x37,     // CPY #$37
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(cpu.status.contains(CpuFlags::ZERO));
@@ -948,7 +951,7 @@ mod test {
             0xcc, 0x00, 0x02, // CPY $0200
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::ZERO));
@@ -963,7 +966,7 @@ mod test {
             0xc9, 0x80,     // CMP #$80
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::ZERO));
@@ -978,7 +981,7 @@ mod test {
             0xe0, 0xff,     // CPX #$ff
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(!cpu.status.contains(CpuFlags::ZERO));
@@ -995,7 +998,7 @@ mod test {
             0xa9, 0x42,     // LDA #$42 (executed)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x42);
@@ -1009,7 +1012,7 @@ mod test {
             0xa9, 0x42,     // LDA #$42 (executed)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x42);
@@ -1024,7 +1027,7 @@ mod test {
             0xa9, 0x42,     // LDA #$42 (executed)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x42);
@@ -1039,7 +1042,7 @@ mod test {
             0xa9, 0x42,     // LDA #$42 (executed)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x42);
@@ -1054,7 +1057,7 @@ mod test {
             0xa9, 0x42,     // LDA #$42 (executed)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x42);
@@ -1069,7 +1072,7 @@ mod test {
             0xa9, 0x42,     // LDA #$42 (executed)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x42);
@@ -1084,7 +1087,7 @@ mod test {
             0xa9, 0x42,     // LDA #$42 (executed)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x42);
@@ -1099,7 +1102,7 @@ mod test {
             0xa9, 0x42,     // LDA #$42
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_x, 0x00);
@@ -1113,7 +1116,7 @@ mod test {
             0xf0, 0x7e,     // BEQ +126 (will actually wrap to $0580)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert!(cpu.program_counter > 0x0600);
@@ -1127,7 +1130,7 @@ mod test {
             0xA9, 0x42,     // LDA #$42 (executed)
             0x00            // BRK
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x42);
@@ -1160,7 +1163,7 @@ mod test {
             0x60,           // RTS
             0x00            // BRK
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x01);
@@ -1199,7 +1202,7 @@ mod test {
             0x60,           // RTS (from sub2)
             0x00            // BRK
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x01);
@@ -1214,7 +1217,7 @@ mod test {
             0x60,           // RTS (should return to $8003)
             0x00            // BRK
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.program_counter, 0x8004);
@@ -1262,7 +1265,7 @@ mod test {
             0x00
         ]);
         cpu.mem_write(0x30, 0x0F);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
 
@@ -1313,7 +1316,7 @@ mod test {
             0x00
         ]);
         cpu.mem_write(0x70, 0xFF);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x55);
@@ -1342,7 +1345,7 @@ mod test {
             0x69, 0x20,     // ADC #$20
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x30);
@@ -1360,7 +1363,7 @@ mod test {
             0x69, 0x20,     // ADC #$20 (should add $21 with carry)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x31);
@@ -1373,7 +1376,7 @@ mod test {
             0x69, 0x01,     // ADC #$01
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x00);
@@ -1388,7 +1391,7 @@ mod test {
             0x69, 0x01,     // ADC #$01 (results in 128, which is negative in signed)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x80);
@@ -1403,7 +1406,7 @@ mod test {
             0x69, 0xFF,     // ADC #$FF (-1)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x7F);
@@ -1434,7 +1437,7 @@ mod test {
             0xE9, 0x30,     // SBC #$30
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x20);
@@ -1450,7 +1453,7 @@ mod test {
             0xE9, 0x30,     // SBC #$30 (actually subtracts $31)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x1F);
@@ -1465,7 +1468,7 @@ mod test {
             0xE9, 0x20,     // SBC #$20
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0xF0);
@@ -1481,7 +1484,7 @@ mod test {
             0xE9, 0xB0,     // SBC #$B0 (-80) (80 - (-80) = 160)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0xA0); // 160 in unsigned
@@ -1498,7 +1501,7 @@ mod test {
             0xE9, 0x70,     // SBC #$70 (112) (-112 - 112 = -224)
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x20);
@@ -1515,11 +1518,564 @@ mod test {
             0xE9, 0x40,     // SBC #$40
             0x00
         ]);
-        cpu.reset();
+        cpu.power_on();
         cpu.program_counter = 0x8000;
         cpu.run();
         assert_eq!(cpu.register_a, 0x00);
         assert!(cpu.status.contains(CpuFlags::ZERO));
         assert!(cpu.status.contains(CpuFlags::CARRY));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_kil_jams_cpu() {
+        let mut cpu = create_cpu(vec![0xe8, 0x02, 0xe8, 0x00]);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        assert_eq!(cpu.register_x, 1);
+        assert_eq!(cpu.program_counter, 0x8001);
+        assert_eq!(cpu.fault, Some(CpuFault::Jammed { opcode: 0x02, addr: 0x8001 }));
+
+        // a jammed CPU doesn't execute anything until reset
+        assert!(cpu.step(|_| {}));
+        assert_eq!(cpu.program_counter, 0x8001);
+        assert_eq!(cpu.register_x, 1);
+
+        cpu.power_on();
+        assert_eq!(cpu.fault, None);
+    }
+
+    #[test]
+    fn test_rti_accepts_any_status() {
+        let mut cpu = create_cpu(vec![0x40, 0x00]);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.stack_push_u16(0x8001);
+        cpu.stack_push(0xFF);
+        cpu.run();
+        assert_eq!(cpu.status.bits(), 0xFF);
+        assert_eq!(cpu.fault, None);
+    }
+
+    #[test]
+    fn test_0x9e_shx() {
+        let mut cpu = create_cpu(vec![
+            0xa2, 0xff,         // LDX #$FF
+            0xa0, 0x05,         // LDY #$05
+            0x9e, 0x00, 0x03,   // SHX $0300,Y
+            0x00
+        ]);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        assert_eq!(cpu.mem_read(0x0305), 0x04);
+    }
+
+    #[test]
+    fn test_0x9e_shx_page_cross() {
+        let mut cpu = create_cpu(vec![
+            0xa2, 0x01,         // LDX #$01
+            0xa0, 0x20,         // LDY #$20
+            0x9e, 0xf0, 0x02,   // SHX $02F0,Y
+            0x00
+        ]);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        // the stored value replaces the high byte of $0310
+        assert_eq!(cpu.mem_read(0x0110), 0x01);
+        assert_eq!(cpu.mem_read(0x0310), 0x00);
+    }
+
+    #[test]
+    fn test_0x9c_shy() {
+        let mut cpu = create_cpu(vec![
+            0xa0, 0xff,         // LDY #$FF
+            0xa2, 0x05,         // LDX #$05
+            0x9c, 0x00, 0x03,   // SHY $0300,X
+            0x00
+        ]);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        assert_eq!(cpu.mem_read(0x0305), 0x04);
+    }
+
+    #[test]
+    fn test_0x9f_ahx_absolute_y() {
+        let mut cpu = create_cpu(vec![
+            0xa9, 0xf7,         // LDA #$F7
+            0xa2, 0xfc,         // LDX #$FC
+            0xa0, 0x01,         // LDY #$01
+            0x9f, 0x00, 0x03,   // AHX $0300,Y
+            0x00
+        ]);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        assert_eq!(cpu.mem_read(0x0301), 0x04);
+    }
+
+    #[test]
+    fn test_0x93_ahx_indirect_y() {
+        let mut cpu = create_cpu(vec![
+            0xa9, 0xff,         // LDA #$FF
+            0xa2, 0xff,         // LDX #$FF
+            0xa0, 0x02,         // LDY #$02
+            0x93, 0x10,         // AHX ($10),Y
+            0x00
+        ]);
+        cpu.power_on();
+        cpu.mem_write_u16(0x10, 0x0300);
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        assert_eq!(cpu.mem_read(0x0302), 0x04);
+    }
+
+    #[test]
+    fn test_0x9b_tas() {
+        let mut cpu = create_cpu(vec![
+            0xa9, 0xf7,         // LDA #$F7
+            0xa2, 0x3f,         // LDX #$3F
+            0xa0, 0x00,         // LDY #$00
+            0x9b, 0x00, 0x03,   // TAS $0300,Y
+            0x00
+        ]);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        assert_eq!(cpu.stack_pointer, 0x37);
+        assert_eq!(cpu.mem_read(0x0300), 0x04);
+    }
+
+    #[test]
+    fn test_0xbb_las() {
+        let mut cpu = create_cpu(vec![
+            0xa0, 0x05,         // LDY #$05
+            0xbb, 0x00, 0x03,   // LAS $0300,Y
+            0x00
+        ]);
+        cpu.power_on();
+        cpu.mem_write(0x0305, 0xf3);
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        assert_eq!(cpu.register_a, 0xf1);
+        assert_eq!(cpu.register_x, 0xf1);
+        assert_eq!(cpu.stack_pointer, 0xf1);
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_0x8b_xaa() {
+        let mut cpu = create_cpu(vec![
+            0xa9, 0x01,         // LDA #$01
+            0xa2, 0xff,         // LDX #$FF
+            0x8b, 0xf0,         // XAA #$F0
+            0x00
+        ]);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        assert_eq!(cpu.register_a, 0xe0);
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_0xcb_axs() {
+        let mut cpu = create_cpu(vec![
+            0xa9, 0x0f,         // LDA #$0F
+            0xa2, 0xf7,         // LDX #$F7
+            0xcb, 0x02,         // AXS #$02
+            0x00
+        ]);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        assert_eq!(cpu.register_x, 0x05);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_0xcb_axs_borrow() {
+        let mut cpu = create_cpu(vec![
+            0xa9, 0x01,         // LDA #$01
+            0xa2, 0xff,         // LDX #$FF
+            0xcb, 0x02,         // AXS #$02
+            0x00
+        ]);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu.run();
+        assert_eq!(cpu.register_x, 0xff);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_0xcb_axs_ignores_decimal_mode() {
+        let mut cpu = create_cpu_variant(vec![
+            0xf8,           // SED
+            0xa9, 0x10,     // LDA #$10
+            0xa2, 0x10,     // LDX #$10
+            0xcb, 0x01,     // AXS #$01
+            0x00
+        ], CpuVariant::NMOS6502);
+        cpu.run();
+        assert_eq!(cpu.register_x, 0x0f);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    fn create_cpu_variant<'a>(program: Vec<u8>, variant: CpuVariant) -> CPU<Bus<'a>> {
+        let mut cpu = create_cpu(program);
+        cpu.set_variant(variant);
+        cpu.power_on();
+        cpu.program_counter = 0x8000;
+        cpu
+    }
+
+    #[test]
+    fn test_adc_decimal_ignored_on_2a03() {
+        let mut cpu = create_cpu_variant(vec![
+            0xf8,           // SED
+            0x18,           // CLC
+            0xa9, 0x09,     // LDA #$09
+            0x69, 0x01,     // ADC #$01
+            0x00
+        ], CpuVariant::RP2A03);
+        cpu.run();
+        assert_eq!(cpu.register_a, 0x0a);
+    }
+
+    #[test]
+    fn test_adc_decimal() {
+        for variant in [CpuVariant::NMOS6502, CpuVariant::CMOS65C02] {
+            let mut cpu = create_cpu_variant(vec![
+                0xf8,           // SED
+                0x18,           // CLC
+                0xa9, 0x12,     // LDA #$12
+                0x69, 0x34,     // ADC #$34
+                0x00
+            ], variant);
+            cpu.run();
+            assert_eq!(cpu.register_a, 0x46);
+            assert!(!cpu.status.contains(CpuFlags::CARRY));
+
+            let mut cpu = create_cpu_variant(vec![
+                0xf8,           // SED
+                0x38,           // SEC
+                0xa9, 0x58,     // LDA #$58
+                0x69, 0x46,     // ADC #$46
+                0x00
+            ], variant);
+            cpu.run();
+            assert_eq!(cpu.register_a, 0x05);
+            assert!(cpu.status.contains(CpuFlags::CARRY));
+        }
+    }
+
+    #[test]
+    fn test_adc_decimal_flags() {
+        let program = vec![
+            0xf8,           // SED
+            0x18,           // CLC
+            0xa9, 0x99,     // LDA #$99
+            0x69, 0x01,     // ADC #$01
+            0x00
+        ];
+
+        // NMOS takes Z from the binary sum and N from the intermediate result
+        let mut cpu = create_cpu_variant(program.clone(), CpuVariant::NMOS6502);
+        cpu.run();
+        assert_eq!(cpu.register_a, 0x00);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        assert!(!cpu.status.contains(CpuFlags::ZERO));
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+
+        let mut cpu = create_cpu_variant(program, CpuVariant::CMOS65C02);
+        cpu.run();
+        assert_eq!(cpu.register_a, 0x00);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+        assert!(!cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_sbc_decimal() {
+        for variant in [CpuVariant::NMOS6502, CpuVariant::CMOS65C02] {
+            let mut cpu = create_cpu_variant(vec![
+                0xf8,           // SED
+                0x38,           // SEC
+                0xa9, 0x46,     // LDA #$46
+                0xe9, 0x12,     // SBC #$12
+                0x00
+            ], variant);
+            cpu.run();
+            assert_eq!(cpu.register_a, 0x34);
+            assert!(cpu.status.contains(CpuFlags::CARRY));
+
+            let mut cpu = create_cpu_variant(vec![
+                0xf8,           // SED
+                0x38,           // SEC
+                0xa9, 0x00,     // LDA #$00
+                0xe9, 0x01,     // SBC #$01
+                0x00
+            ], variant);
+            cpu.run();
+            assert_eq!(cpu.register_a, 0x99);
+            assert!(!cpu.status.contains(CpuFlags::CARRY));
+        }
+    }
+
+    #[test]
+    fn test_jmp_indirect_page_wrap_per_variant() {
+        let program = vec![0x6c, 0xff, 0x02]; // JMP ($02FF)
+        for (variant, target) in [(CpuVariant::NMOS6502, 0x8000), (CpuVariant::CMOS65C02, 0x9000)] {
+            let mut cpu = create_cpu_variant(program.clone(), variant);
+            cpu.mem_write(0x02ff, 0x00);
+            cpu.mem_write(0x0300, 0x90);
+            cpu.mem_write(0x0200, 0x80);
+            cpu.step(|_| {});
+            assert_eq!(cpu.program_counter, target);
+        }
+    }
+
+    #[test]
+    fn test_65c02_opcode_table_complete() {
+        assert_eq!(CpuVariant::CMOS65C02.opcodes().len(), 256);
+    }
+
+    #[test]
+    fn test_65c02_stack_transfers() {
+        let mut cpu = create_cpu_variant(vec![
+            0xa2, 0x42,     // LDX #$42
+            0xa0, 0x24,     // LDY #$24
+            0xda,           // PHX
+            0x5a,           // PHY
+            0xfa,           // PLX
+            0x7a,           // PLY
+            0x00
+        ], CpuVariant::CMOS65C02);
+        cpu.run();
+        assert_eq!(cpu.register_x, 0x24);
+        assert_eq!(cpu.register_y, 0x42);
+    }
+
+    #[test]
+    fn test_65c02_stz_tsb_trb() {
+        let mut cpu = create_cpu_variant(vec![
+            0x64, 0x10,     // STZ $10
+            0xa9, 0x0f,     // LDA #$0F
+            0x04, 0x11,     // TSB $11
+            0xa9, 0x03,     // LDA #$03
+            0x14, 0x12,     // TRB $12
+            0x00
+        ], CpuVariant::CMOS65C02);
+        cpu.mem_write(0x10, 0x55);
+        cpu.mem_write(0x11, 0xf0);
+        cpu.mem_write(0x12, 0xff);
+        cpu.run();
+        assert_eq!(cpu.mem_read(0x10), 0x00);
+        assert_eq!(cpu.mem_read(0x11), 0xff);
+        assert_eq!(cpu.mem_read(0x12), 0xfc);
+        assert!(!cpu.status.contains(CpuFlags::ZERO));
+    }
+
+    #[test]
+    fn test_65c02_accumulator_inc_dec_and_bra() {
+        let mut cpu = create_cpu_variant(vec![
+            0x1a,           // INC A
+            0x1a,           // INC A
+            0x3a,           // DEC A
+            0x80, 0x01,     // BRA +1
+            0x1a,           // INC A (skipped)
+            0x00
+        ], CpuVariant::CMOS65C02);
+        cpu.run();
+        assert_eq!(cpu.register_a, 0x01);
+    }
+
+    #[test]
+    fn test_65c02_zero_page_indirect() {
+        let mut cpu = create_cpu_variant(vec![
+            0xb2, 0x20,     // LDA ($20)
+            0x00
+        ], CpuVariant::CMOS65C02);
+        cpu.mem_write_u16(0x20, 0x0300);
+        cpu.mem_write(0x0300, 0x77);
+        cpu.run();
+        assert_eq!(cpu.register_a, 0x77);
+    }
+
+    #[test]
+    fn test_65c02_jmp_absolute_indexed_indirect() {
+        let mut cpu = create_cpu_variant(vec![
+            0xa2, 0x02,         // LDX #$02
+            0x7c, 0x00, 0x03,   // JMP ($0300,X)
+        ], CpuVariant::CMOS65C02);
+        cpu.mem_write_u16(0x0302, 0x1234);
+        cpu.step(|_| {});
+        cpu.step(|_| {});
+        assert_eq!(cpu.program_counter, 0x1234);
+    }
+
+    #[test]
+    fn test_65c02_bit_immediate_only_sets_zero() {
+        let mut cpu = create_cpu_variant(vec![
+            0xa9, 0x01,     // LDA #$01
+            0x89, 0xc0,     // BIT #$C0
+            0x00
+        ], CpuVariant::CMOS65C02);
+        cpu.run();
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+        assert!(!cpu.status.contains(CpuFlags::NEGATIVE));
+        assert!(!cpu.status.contains(CpuFlags::OVERFLOW));
+    }
+
+    #[test]
+    fn test_nmi_entry_takes_seven_cycles() {
+        let mut program = vec![0xea; 2 * Cartridge::PRG_ROM_PAGE_SIZE];
+        program[0x7FFA..0x7FFC].copy_from_slice(&[0x00, 0x90]);
+        let mut cpu = create_cpu_variant(program, CpuVariant::RP2A03);
+        cpu.step(|_| {});
+
+        cpu.bus.ppu.nmi_interrupt = Some(1);
+        let asserted = cpu.bus.cycles();
+        let mut handler_fetch = None;
+        cpu.step(|cpu| handler_fetch = Some((cpu.program_counter, cpu.bus.cycles())));
+        assert_eq!(handler_fetch, Some((0x9000, asserted + 7)));
+        // the fetch and the NOP itself follow
+        assert_eq!(cpu.bus.cycles(), asserted + 9);
+    }
+
+    fn create_ram_cpu(program: &[u8]) -> CPU<RamBus> {
+        let mut bus = RamBus::new();
+        bus.load(0x0200, program);
+        bus.load(0xFFFC, &[0x00, 0x02]);
+        let mut cpu = CPU::new(bus);
+        cpu.power_on();
+        cpu
+    }
+
+    // https://www.nesdev.org/6502_cpu.txt
+    // the 6502 reads or writes on every cycle, so the accesses of an opcode are its cycle count
+    #[test]
+    fn test_opcode_accesses_match_cycles() {
+        let mut wrong = vec![];
+        for (&code, opcode) in OPCODES.iter() {
+            if matches!(opcode.instruction, Instruction::BRK | Instruction::KIL) || opcode.addressing_mode == AddressingMode::Relative {
+                continue;
+            }
+            // the operand is $10, or $0010, where a pointer to $0300 is stored
+            let mut cpu = create_ram_cpu(&[code, 0x10, 0x00]);
+            cpu.bus.load(0x0010, &[0x00, 0x03]);
+            cpu.bus.accesses = 0;
+            cpu.step(|_| {});
+            if cpu.bus.accesses != opcode.cycles as usize {
+                wrong.push(format!("${:02X} {:?}: {} accesses for {} cycles", code, opcode.instruction, cpu.bus.accesses, opcode.cycles));
+            }
+        }
+        wrong.sort();
+        assert!(wrong.is_empty(), "{:#?}", wrong);
+    }
+
+    fn accesses(program: &[u8], x: u8, carry: bool) -> usize {
+        let mut cpu = create_ram_cpu(program);
+        cpu.register_x = x;
+        cpu.register_y = x;
+        cpu.status.set(CpuFlags::CARRY, carry);
+        cpu.bus.load(0x0010, &[0xFF, 0x02]);
+        cpu.bus.accesses = 0;
+        cpu.step(|_| {});
+        cpu.bus.accesses
+    }
+
+    #[test]
+    fn test_page_crossing_and_branch_cycles() {
+        // reads take a cycle more when indexing crosses a page
+        assert_eq!(accesses(&[0xbd, 0xfe, 0x02], 1, false), 4);   // LDA $02FE,X
+        assert_eq!(accesses(&[0xbd, 0xff, 0x02], 1, false), 5);   // LDA $02FF,X
+        assert_eq!(accesses(&[0xb1, 0x10], 1, false), 6);         // LDA ($10),Y
+        // stores and read-modify-write instructions always take it
+        assert_eq!(accesses(&[0x9d, 0xff, 0x02], 1, false), 5);   // STA $02FF,X
+        assert_eq!(accesses(&[0xfe, 0xff, 0x02], 1, false), 7);   // INC $02FF,X
+        // branches take one more when taken, two when they land on another page
+        assert_eq!(accesses(&[0xb0, 0x10], 0, false), 2);         // BCS +$10
+        assert_eq!(accesses(&[0xb0, 0x10], 0, true), 3);
+        assert_eq!(accesses(&[0xb0, 0x80], 0, true), 4);
+    }
+
+    #[test]
+    fn test_ram_bus_runs_program() {
+        let mut cpu = create_ram_cpu(&[
+            0xa9, 0x42,         // LDA #$42
+            0x8d, 0x00, 0xc0,   // STA $C000
+            0x00
+        ]);
+        cpu.run();
+        assert_eq!(cpu.mem_read(0xc000), 0x42);
+        assert_eq!(cpu.bus.cycles, 6);
+    }
+
+    #[test]
+    fn test_ram_bus_nmi() {
+        let mut cpu = create_ram_cpu(&[0xea, 0x00]);
+        cpu.bus.load(0xFFFA, &[0x00, 0x03]);
+        cpu.bus.trigger_nmi();
+        cpu.step(|_| {});
+        // NMI is taken before the next instruction and pushes P with bit 5 set and B clear
+        assert_eq!(cpu.program_counter, 0x0301);
+        assert_eq!(cpu.mem_read(0x01fb), 0b0010_0100);
+        assert_eq!(cpu.stack_pointer, STACK_START.wrapping_sub(3));
+    }
+
+    #[test]
+    fn test_ram_bus_irq_masked_by_interrupt_flag() {
+        let mut cpu = create_ram_cpu(&[
+            0xea,       // NOP
+            0x58,       // CLI
+            0xea,       // NOP
+            0x00
+        ]);
+        cpu.bus.load(0xFFFE, &[0x00, 0x03]);
+        cpu.bus.irq_line = true;
+
+        cpu.step(|_| {});
+        cpu.step(|_| {});
+        assert_eq!(cpu.program_counter, 0x0202);
+
+        cpu.step(|_| {});
+        assert_eq!(cpu.program_counter, 0x0301);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT));
+    }
+
+    #[test]
+    fn test_power_on_state() {
+        let mut cpu = create_cpu(vec![0xEA]);
+        cpu.register_a = 0x12;
+        cpu.stack_pointer = 0x40;
+        cpu.power_on();
+
+        assert_eq!(cpu.register_a, 0);
+        assert_eq!(cpu.stack_pointer, 0xFD);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT));
+        let vector = cpu.mem_read_u16(0xFFFC);
+        assert_eq!(cpu.program_counter, vector);
+    }
+
+    #[test]
+    fn test_soft_reset_keeps_registers() {
+        let mut cpu = create_cpu(vec![0xEA]);
+        cpu.power_on();
+        cpu.register_a = 0x12;
+        cpu.register_x = 0x34;
+        cpu.status.remove(CpuFlags::INTERRUPT);
+        cpu.mem_write(0x0010, 0x56);
+        cpu.reset();
+
+        assert_eq!(cpu.register_a, 0x12);
+        assert_eq!(cpu.register_x, 0x34);
+        assert_eq!(cpu.stack_pointer, 0xFA);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT));
+        assert_eq!(cpu.mem_read(0x0010), 0x56);
+    }
+}
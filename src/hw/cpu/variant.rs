@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::hw::cpu::opcodes::{OpCode, OPCODES, OPCODES_65C02};
+
+// http://www.6502.org/tutorials/65c02opcodes.html
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum CpuVariant {
+    // Ricoh 2A03/2A07 used by the NES, a NMOS 6502 with decimal mode disconnected
+    #[default]
+    RP2A03,
+    // stock NMOS 6502 with BCD arithmetic and the undocumented opcodes
+    NMOS6502,
+    // CMOS 65C02 with the extra opcodes, valid BCD flags and fixed JMP indirect page wrap
+    CMOS65C02,
+}
+
+impl CpuVariant {
+    pub fn has_decimal_mode(&self) -> bool {
+        *self != CpuVariant::RP2A03
+    }
+
+    pub(crate) fn opcodes(&self) -> &'static HashMap<u8, OpCode> {
+        match self {
+            CpuVariant::RP2A03 | CpuVariant::NMOS6502 => &OPCODES,
+            CpuVariant::CMOS65C02 => &OPCODES_65C02,
+        }
+    }
+}
@@ -1,16 +1,41 @@
 pub trait Memory {
     fn mem_read(&mut self, addr: u16) -> u8;
     fn mem_write(&mut self, addr: u16, data: u8);
+    // reads without side effects on registers or the data bus, for debugging tools
+    fn mem_peek(&mut self, addr: u16) -> u8 {
+        self.mem_read(addr)
+    }
     fn mem_read_u16(&mut self, addr: u16) -> u16 {
         let lo = self.mem_read(addr) as u16;
         let hi = self.mem_read(addr + 1) as u16;
         (hi << 8) | lo
     }
 
+    fn mem_peek_u16(&mut self, addr: u16) -> u16 {
+        let lo = self.mem_peek(addr) as u16;
+        let hi = self.mem_peek(addr + 1) as u16;
+        (hi << 8) | lo
+    }
+
     fn mem_write_u16(&mut self, addr: u16, data: u16) {
         let hi = (data >> 8) as u8;
         let lo = (data & 0xFF) as u8;
         self.mem_write(addr, lo);
         self.mem_write(addr + 1, hi);
     }
+}
+
+// the system the CPU is plugged into, memory plus the clock and interrupt lines
+pub trait CpuBus: Memory {
+    // advances the rest of the system by the given number of CPU cycles
+    fn tick(&mut self, cycles: u8);
+    fn poll_nmi_status(&mut self) -> Option<u8>;
+    // level-triggered, serviced only while the interrupt disable flag is clear
+    fn poll_irq_status(&mut self) -> bool {
+        false
+    }
+    // cold start, devices and memory take their power-up state
+    fn power_on(&mut self) {}
+    // the reset button, memory keeps its contents
+    fn reset(&mut self) {}
 }
\ No newline at end of file
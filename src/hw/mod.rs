@@ -2,5 +2,10 @@ pub mod cpu;
 pub mod bus;
 pub mod memory;
 pub mod cartridge;
+pub mod cheats;
 pub mod ppu;
-pub mod joypad;
\ No newline at end of file
+pub mod apu;
+pub mod joypad;
+pub mod region;
+pub mod ram_bus;
+pub mod ram_init;
\ No newline at end of file
@@ -1,24 +1,32 @@
 mod address_register;
 mod controller_register;
 mod mask_register;
+mod pipeline;
 mod scroll_register;
 mod status_register;
 mod tests;
 
 use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::{Mapper, CHR_WINDOWS, CHR_WINDOW_SIZE};
 use crate::hw::cartridge::ScreenMirroring;
 use crate::hw::ppu::address_register::AddressRegister;
 use crate::hw::ppu::controller_register::ControllerRegister;
 use crate::hw::ppu::mask_register::MaskRegister;
+use crate::hw::ppu::pipeline::FetchPipeline;
 use crate::hw::ppu::scroll_register::ScrollRegister;
 use crate::hw::ppu::status_register::StatusRegister;
+use crate::hw::region::Region;
 use crate::rendering::frame::Frame;
 use serde_big_array::BigArray;
 
+// the PPU address bus is 14 bits wide
+const PPU_ADDR_MASK: u16 = 0x3fff;
+
 #[derive(Serialize, Deserialize)]
 pub struct PPU {
     pub chr_rom: Vec<u8>,
     pub mirroring: ScreenMirroring,
+    pub region: Region,
     pub address_register: AddressRegister,
     pub controller_register: ControllerRegister,
     pub mask_register: MaskRegister,
@@ -27,6 +35,12 @@ pub struct PPU {
 
     #[serde(with = "BigArray")]
     pub vram: [u8; 2048],
+    #[serde(with = "BigArray")]
+    four_screen_vram: [u8; 2048],
+    // cartridges without CHR ROM have 8 KiB of CHR RAM instead
+    chr_writable: bool,
+    // set by the mapper, offset into chr_rom of each 1 KiB window of the pattern tables
+    chr_banks: [usize; CHR_WINDOWS],
 
     pub oam_address: u8,
     #[serde(with = "BigArray")]
@@ -34,82 +48,239 @@ pub struct PPU {
     pub palette_table: [u8; 32],
 
     internal_data_buf: u8,
+    // value last driven onto the PPU data bus by a $2000-$2007 access, bits decay when not refreshed
+    io_latch: u8,
+    io_latch_decay: [u8; 8],
 
     scanline: u16,
     cycles: usize,
+    odd_frame: bool,
+    // set by a $2002 read one dot before vblank starts
+    suppress_vblank: bool,
+    // renders each dot through the fetch pipeline instead of the whole frame at vblank
+    dot_rendering: bool,
+    pipeline: FetchPipeline,
     pub nmi_interrupt: Option<u8>,
     pub current_frame: Frame,
 }
 
 impl PPU {
+    const IO_LATCH_DECAY_SECONDS: f64 = 0.6;
+    const OAM_ATTRIBUTE_UNUSED_BITS: u8 = 0b0001_1100;
+    const CHR_RAM_SIZE: usize = 8192;
+
     pub fn new_empty_rom() -> Self {
         PPU::new(vec![0; 2048], ScreenMirroring::Horizontal)
     }
 
     pub fn new(chr_rom: Vec<u8>, mirroring: ScreenMirroring) -> Self {
+        let chr_writable = chr_rom.is_empty();
+        let chr_rom = if chr_writable { vec![0; Self::CHR_RAM_SIZE] } else { chr_rom };
         PPU {
             chr_rom,
+            chr_writable,
+            chr_banks: std::array::from_fn(|window| window * CHR_WINDOW_SIZE),
             mirroring,
+            region: Region::NTSC,
             palette_table: [0; 32],
             vram: [0; 2048],
+            four_screen_vram: [0; 2048],
             address_register: AddressRegister::new(),
             controller_register: ControllerRegister::new(),
             mask_register: MaskRegister::new(),
             scroll_register: ScrollRegister::new(),
             status_register: StatusRegister::new(),
             internal_data_buf: 0,
+            io_latch: 0,
+            io_latch_decay: [0; 8],
             oam_data: [0; 256],
             oam_address: 0,
             scanline: 0,
             cycles: 0,
+            odd_frame: false,
+            suppress_vblank: false,
+            dot_rendering: false,
+            pipeline: FetchPipeline::default(),
             nmi_interrupt: None,
             current_frame: Frame::new(),
         }
     }
-    pub fn tick(&mut self, cycles: u8) -> bool {
-        self.cycles += cycles as usize;
-        if self.cycles >= 341 {
-            if self.is_sprite_0_hit(self.cycles) {
-                self.status_register.set_sprite_zero_hit(true);
-            }
+    pub fn tick(&mut self, dots: u8) -> bool {
+        self.tick_with_mapper(dots, None)
+    }
 
-            self.cycles = self.cycles - 341;
-            self.scanline += 1;
+    // the mapper can replace what the fetch pipeline reads, see Mapper::ppu_read
+    pub fn tick_with_mapper(&mut self, dots: u8, mut mapper: Option<&mut dyn Mapper>) -> bool {
+        let mut frame_complete = false;
+        for _ in 0..dots {
+            frame_complete |= self.tick_dot(pipeline::reborrow(&mut mapper));
+        }
+        frame_complete
+    }
 
-            if self.scanline == 241 {
-                self.status_register.set_vblank_status(true);
-                self.status_register.set_sprite_zero_hit(false);
-                if self.controller_register.generate_vblank_nmi() {
-                    self.nmi_interrupt = Some(1);
-                }
-            }
+    // https://www.nesdev.org/wiki/PPU_frame_timing
+    fn tick_dot(&mut self, mapper: Option<&mut dyn Mapper>) -> bool {
+        let prerender_scanline = self.region.scanlines_per_frame() - 1;
+        // odd NTSC frames skip the last dot of the pre-render line while rendering is enabled
+        let line_dots = if self.scanline == prerender_scanline && self.odd_frame
+            && self.region == Region::NTSC && self.is_rendering_enabled() { 340 } else { 341 };
 
-            if self.scanline >= 262 {
+        self.cycles += 1;
+        if self.cycles >= line_dots {
+            self.cycles = 0;
+            self.scanline += 1;
+            if self.scanline > prerender_scanline {
                 self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+                self.decay_io_latch();
+                return true;
+            }
+            return false;
+        }
+
+        if self.cycles == 1 {
+            if self.scanline == self.region.vblank_scanline() {
+                // a $2002 read on the dot before keeps the flag and the NMI from being raised
+                if !self.suppress_vblank {
+                    self.status_register.set_vblank_status(true);
+                    if self.controller_register.generate_vblank_nmi() {
+                        self.nmi_interrupt = Some(1);
+                    }
+                }
+                self.suppress_vblank = false;
+            } else if self.scanline == prerender_scanline {
                 self.nmi_interrupt = None;
-                self.status_register.set_sprite_zero_hit(false);
                 self.status_register.reset_vblank_status();
-                return true;
+                self.status_register.set_sprite_zero_hit(false);
+                self.status_register.set_sprite_overflow(false);
             }
         }
+
+        if self.dot_rendering {
+            self.tick_pipeline(mapper);
+        } else if self.is_sprite_0_hit(self.cycles) {
+            self.status_register.set_sprite_zero_hit(true);
+        }
         false
     }
 
+    // https://www.nesdev.org/wiki/PPU_power_up_state
+    // VRAM, OAM and palette contents are left as they were
+    pub fn power_on(&mut self) {
+        self.reset();
+        self.status_register = StatusRegister::new();
+        self.address_register = AddressRegister::new();
+        self.oam_address = 0;
+        self.scanline = 0;
+        self.cycles = 0;
+        self.suppress_vblank = false;
+        self.nmi_interrupt = None;
+        self.pipeline = FetchPipeline::default();
+    }
+
+    pub fn reset(&mut self) {
+        self.controller_register = ControllerRegister::new();
+        self.mask_register = MaskRegister::new();
+        self.scroll_register = ScrollRegister::new();
+        self.address_register.reset_latch();
+        self.pipeline.reset_latch();
+        self.internal_data_buf = 0;
+        self.odd_frame = false;
+    }
+
+    pub fn dot_rendering(&self) -> bool {
+        self.dot_rendering
+    }
+
+    // frames are drawn into current_frame dot by dot, needed by mappers that switch CHR banks mid-frame
+    pub fn set_dot_rendering(&mut self, enabled: bool) {
+        self.dot_rendering = enabled;
+    }
+
+    // last address the PPU put on its bus while rendering or accessing $2007
+    pub fn bus_address(&self) -> u16 {
+        self.pipeline.bus_address()
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    // dot of the frame on which the vblank flag is raised, whether or not a $2002 read suppressed it
+    pub fn is_vblank_start(&self) -> bool {
+        self.scanline == self.region.vblank_scanline() && self.cycles == 1
+    }
+
+    fn is_rendering_enabled(&self) -> bool {
+        self.mask_register.show_background() || self.mask_register.show_sprites()
+    }
+
     fn is_sprite_0_hit(&self, cycle: usize) -> bool {
         let y = self.oam_data[0] as usize;
         let x = self.oam_data[3] as usize;
         (y == self.scanline as usize) && x <= cycle && self.mask_register.show_sprites()
     }
 
+    // https://www.nesdev.org/wiki/Open_bus_behavior#PPU_open_bus
+    // bits driven by a register access are refreshed, the others keep their old value
+    fn refresh_io_latch(&mut self, value: u8, mask: u8) {
+        self.io_latch = (self.io_latch & !mask) | (value & mask);
+        // a bit set to 1 holds its charge for roughly 600ms
+        let decay_frames = (Self::IO_LATCH_DECAY_SECONDS * self.region.frame_rate()) as u8;
+        for bit in 0..8 {
+            if mask & (1 << bit) != 0 {
+                self.io_latch_decay[bit] = decay_frames;
+            }
+        }
+    }
+
+    fn decay_io_latch(&mut self) {
+        for bit in 0..8 {
+            if self.io_latch_decay[bit] > 0 {
+                self.io_latch_decay[bit] -= 1;
+                if self.io_latch_decay[bit] == 0 {
+                    self.io_latch &= !(1 << bit);
+                }
+            }
+        }
+    }
+
+    // registers are written through the data bus, refreshing the whole latch
+    pub(crate) fn write_io_latch(&mut self, value: u8) {
+        self.refresh_io_latch(value, 0xFF);
+    }
+
+    // write-only registers return whatever is left on the data bus
+    pub(crate) fn read_io_latch(&self) -> u8 {
+        self.io_latch
+    }
+
+    // register value as the CPU would read it, without clearing flags or advancing addresses
+    pub(crate) fn peek_register(&self, addr: u16) -> u8 {
+        match addr {
+            0x2002 => (self.status_register.snapshot() & 0b1110_0000) | (self.io_latch & 0b0001_1111),
+            0x2004 => self.oam_value(),
+            0x2007 => self.internal_data_buf,
+            _ => self.io_latch,
+        }
+    }
+
     pub(crate) fn write_to_ppu_addr_reg(&mut self, value: u8) {
         self.address_register.update(value);
+        self.pipeline.write_addr(value);
     }
 
     pub(crate) fn write_to_ctrl(&mut self, value: u8) {
         let before_nmi_status = self.controller_register.generate_vblank_nmi();
         self.controller_register.update(value);
-        if !before_nmi_status && self.controller_register.generate_vblank_nmi() && self.status_register.is_in_vblank() {
+        self.pipeline.write_ctrl(value);
+        let nmi_status = self.controller_register.generate_vblank_nmi();
+        // https://www.nesdev.org/wiki/NMI#Race_condition
+        if !before_nmi_status && nmi_status && self.status_register.is_in_vblank() {
             self.nmi_interrupt = Some(1);
+        } else if before_nmi_status && !nmi_status && self.is_near_vblank_start() {
+            // disabling NMI right as vblank starts pulls the line back down before the CPU sees it
+            self.nmi_interrupt = None;
         }
     }
 
@@ -122,12 +293,25 @@ impl PPU {
         self.oam_address = self.oam_address.wrapping_add(1);
     }
 
-    pub(crate) fn read_oam_data(&self) -> u8 {
-        self.oam_data[self.oam_address as usize]
+    pub(crate) fn read_oam_data(&mut self) -> u8 {
+        let data = self.oam_value();
+        self.refresh_io_latch(data, 0xFF);
+        data
+    }
+
+    fn oam_value(&self) -> u8 {
+        let data = self.oam_data[self.oam_address as usize];
+        // bits 2-4 of the sprite attribute byte don't exist in OAM and read back as 0
+        if self.oam_address % 4 == 2 {
+            data & !Self::OAM_ATTRIBUTE_UNUSED_BITS
+        } else {
+            data
+        }
     }
 
     pub(crate) fn write_to_scroll(&mut self, value: u8) {
         self.scroll_register.write(value);
+        self.pipeline.write_scroll(value);
     }
 
     pub(crate) fn write_to_mask(&mut self, value: u8) {
@@ -135,61 +319,140 @@ impl PPU {
     }
 
     pub(crate) fn read_status(&mut self) -> u8 {
-        let data = self.status_register.snapshot();
+        // only the top 3 bits are driven, the rest come from the data bus
+        let data = (self.status_register.snapshot() & 0b1110_0000) | (self.io_latch & 0b0001_1111);
+        self.refresh_io_latch(data, 0b1110_0000);
+        // https://www.nesdev.org/wiki/PPU_frame_timing#VBL_Flag_Timing
+        if self.scanline == self.region.vblank_scanline() && self.cycles == 0 {
+            // reading one dot early returns the flag clear and it never gets set this frame
+            self.suppress_vblank = true;
+        } else if self.is_near_vblank_start() {
+            // reading on the dot the flag is set or right after returns it but suppresses the NMI
+            self.nmi_interrupt = None;
+        }
         self.status_register.reset_vblank_status();
         self.address_register.reset_latch();
         self.scroll_register.reset_latch();
+        self.pipeline.reset_latch();
         data
     }
 
+    fn is_near_vblank_start(&self) -> bool {
+        self.scanline == self.region.vblank_scanline() && (1..=2).contains(&self.cycles)
+    }
+
     fn increment_vram_addr(&mut self) {
         self.address_register.increment(self.controller_register.vram_addr_increment());
+        self.pipeline.increment(self.controller_register.vram_addr_increment());
     }
 
+    // https://www.nesdev.org/wiki/PPU_memory_map
     pub(crate) fn write_to_data(&mut self, value: u8) {
-        let addr = self.address_register.get();
+        let addr = self.address_register.get() & PPU_ADDR_MASK;
+        self.pipeline.set_bus_address(addr);
         match addr {
-            0..=0x1fff => println!("attempt to write to chr rom space {}", addr),
-            0x2000..=0x2fff => {
-                self.vram[self.mirror_vram_addr(addr) as usize] = value;
+            0..=0x1fff => {
+                // writes to CHR ROM are ignored
+                if self.chr_writable {
+                    let index = self.chr_index(addr);
+                    self.chr_rom[index] = value;
+                }
             }
-            0x3000..=0x3eff => unimplemented!("addr {} shouldn't be used", addr),
-
-            //Addresses $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
-            0x3f10 | 0x3f14 | 0x3f18 | 0x3f1c => {
-                let add_mirror = addr - 0x10;
-                self.palette_table[(add_mirror - 0x3f00) as usize] = value;
+            // 0x3000-0x3eff mirrors the nametables
+            0x2000..=0x3eff => self.write_nametable(addr, value),
+            _ => {
+                // palette RAM only stores the 6 bit color index
+                self.palette_table[Self::palette_index(addr)] = value & 0b0011_1111;
             }
-            0x3f00..=0x3fff =>
-                {
-                    self.palette_table[(addr - 0x3f00) as usize] = value;
-                }
-            _ => panic!("unexpected access to mirrored space {}", addr),
         }
         self.increment_vram_addr();
     }
 
     pub(crate) fn read_data(&mut self) -> u8 {
-        let addr = self.address_register.get();
+        let addr = self.address_register.get() & PPU_ADDR_MASK;
+        self.pipeline.set_bus_address(addr);
         self.increment_vram_addr();
 
-        match addr {
+        let data = match addr {
             0..=0x1FFF => {
                 let result = self.internal_data_buf;
-                self.internal_data_buf = self.chr_rom[addr as usize];
+                self.internal_data_buf = self.read_chr(addr);
                 result
             }
-            0x2000..=0x2FFF => {
+            0x2000..=0x3EFF => {
                 let result = self.internal_data_buf;
-                self.internal_data_buf = self.vram[self.mirror_vram_addr(addr) as usize];
+                self.internal_data_buf = self.read_nametable(addr);
                 result
             }
-            0x3000..=0x3EFF => panic!("addr space 0x3000..0x3eff is not expected to be used, requested = {} ", addr),
-            0x3f00..=0x3fff =>
-                {
-                    self.palette_table[(addr - 0x3f00) as usize]
-                }
-            _ => panic!("unexpected access to mirrored space {}", addr),
+            _ => {
+                // palette reads aren't buffered, but the buffer is still filled
+                // with the nametable byte "underneath" the palette
+                self.internal_data_buf = self.read_nametable(addr - 0x1000);
+
+                // palette entries are 6 bits wide, the top 2 bits come from the data bus
+                let result = (self.palette_table[Self::palette_index(addr)] & 0b0011_1111) | (self.io_latch & 0b1100_0000);
+                self.refresh_io_latch(result, 0b0011_1111);
+                return result;
+            }
+        };
+        self.refresh_io_latch(data, 0xFF);
+        data
+    }
+
+    pub fn set_chr_banks(&mut self, banks: [usize; CHR_WINDOWS]) {
+        self.chr_banks = banks;
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr as usize / CHR_WINDOW_SIZE) % CHR_WINDOWS];
+        (bank + addr as usize % CHR_WINDOW_SIZE) % self.chr_rom.len()
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_rom[self.chr_index(addr)]
+    }
+
+    // the 16 bytes of the tile at a pattern table address, through the current CHR banks
+    pub fn chr_tile(&self, addr: u16) -> [u8; 16] {
+        std::array::from_fn(|i| self.read_chr(addr + i as u16))
+    }
+
+    // 0x3f20-0x3fff mirrors 0x3f00-0x3f1f
+    // addresses $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
+    fn palette_index(addr: u16) -> usize {
+        let index = (addr & 0x1f) as usize;
+        match index {
+            0x10 | 0x14 | 0x18 | 0x1c => index - 0x10,
+            _ => index,
+        }
+    }
+
+    fn read_nametable(&self, addr: u16) -> u8 {
+        let index = self.mirror_vram_addr(addr) as usize;
+        if index < self.vram.len() {
+            self.vram[index]
+        } else {
+            self.four_screen_vram[index - self.vram.len()]
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, value: u8) {
+        let index = self.mirror_vram_addr(addr) as usize;
+        if index < self.vram.len() {
+            self.vram[index] = value;
+        } else {
+            self.four_screen_vram[index - self.vram.len()] = value;
+        }
+    }
+
+    // 1 KiB nametable as seen at 0x2000 + table * 0x400
+    pub fn nametable(&self, table: u16) -> &[u8] {
+        let start = self.mirror_vram_addr(0x2000 + (table & 0b11) * 0x400) as usize;
+        if start < self.vram.len() {
+            &self.vram[start..start + 0x400]
+        } else {
+            let start = start - self.vram.len();
+            &self.four_screen_vram[start..start + 0x400]
         }
     }
 
@@ -200,6 +463,12 @@ impl PPU {
     // Vertical:
     //   [ A ] [ B ]
     //   [ a ] [ b ]
+
+    // Four screen (extra 2 KiB of VRAM on the cartridge):
+    //   [ A ] [ B ]
+    //   [ C ] [ D ]
+
+    // Custom picks one of the two CIRAM pages for every nametable
     pub fn mirror_vram_addr(&self, addr: u16) -> u16 {
         let mirrored_vram = addr & 0b10111111111111; // mirror down 0x3000-0x3eff to 0x2000 - 0x2eff
         let vram_index = mirrored_vram - 0x2000; // to vram vector
@@ -209,6 +478,7 @@ impl PPU {
             (ScreenMirroring::Horizontal, 2) => vram_index - 0x400,
             (ScreenMirroring::Horizontal, 1) => vram_index - 0x400,
             (ScreenMirroring::Horizontal, 3) => vram_index - 0x800,
+            (ScreenMirroring::Custom(pages), _) => (pages[name_table as usize] as u16 & 1) * 0x400 + (vram_index & 0x3FF),
             _ => vram_index,
         }
     }
@@ -74,6 +74,14 @@ impl ControllerRegister {
         }
     }
 
+    pub fn sprite_size(&self) -> u8 {
+        if !self.contains(ControllerRegister::SPRITE_SIZE) {
+            8
+        } else {
+            16
+        }
+    }
+
     pub fn update(&mut self, data: u8) {
         *self = ControllerRegister::from_bits_truncate(data);
     }
@@ -0,0 +1,381 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::cartridge::mapper::Mapper;
+use crate::hw::ppu::{PPU, PPU_ADDR_MASK};
+use crate::rendering::palette;
+
+const MAX_SPRITES_PER_LINE: usize = 8;
+
+// state of the 2C02 rendering pipeline, only advanced when dot rendering is enabled
+// https://www.nesdev.org/wiki/PPU_rendering
+#[derive(Serialize, Deserialize, Default)]
+pub struct FetchPipeline {
+    // https://www.nesdev.org/wiki/PPU_scrolling#PPU_internal_registers
+    // yyy NN YYYYY XXXXX
+    // ||| || ||||| +++++-- coarse X scroll
+    // ||| || +++++-------- coarse Y scroll
+    // ||| ++-------------- nametable select
+    // +++----------------- fine Y scroll
+    v: u16,
+    t: u16,
+    fine_x: u8,
+    w: bool,
+
+    nametable_latch: u8,
+    attribute_latch: u8,
+    pattern_low_latch: u8,
+    pattern_high_latch: u8,
+    pattern_low_shift: u16,
+    pattern_high_shift: u16,
+    attribute_low_shift: u16,
+    attribute_high_shift: u16,
+
+    // sprites found by the evaluation for the next scanline
+    sprite_count: usize,
+    sprite_zero_on_line: bool,
+    sprite_rows: [u8; MAX_SPRITES_PER_LINE],
+    sprite_tiles: [u8; MAX_SPRITES_PER_LINE],
+    sprite_attributes: [u8; MAX_SPRITES_PER_LINE],
+    sprite_x: [u8; MAX_SPRITES_PER_LINE],
+    sprite_patterns_low: [u8; MAX_SPRITES_PER_LINE],
+    sprite_patterns_high: [u8; MAX_SPRITES_PER_LINE],
+
+    // last address driven onto the PPU bus, watched by mappers for CHR latches and A12 edges
+    bus_address: u16,
+}
+
+impl FetchPipeline {
+    // t: ...GH.. ........ <- d: ......GH
+    pub fn write_ctrl(&mut self, value: u8) {
+        self.t = (self.t & !0x0C00) | (((value & 0b11) as u16) << 10);
+    }
+
+    pub fn write_scroll(&mut self, value: u8) {
+        if !self.w {
+            self.t = (self.t & !0x001F) | (value >> 3) as u16;
+            self.fine_x = value & 0b111;
+        } else {
+            self.t = (self.t & !0x73E0) | (((value & 0b111) as u16) << 12) | (((value & 0xF8) as u16) << 2);
+        }
+        self.w = !self.w;
+    }
+
+    pub fn write_addr(&mut self, value: u8) {
+        if !self.w {
+            self.t = (self.t & 0x00FF) | (((value & 0x3F) as u16) << 8);
+        } else {
+            self.t = (self.t & 0xFF00) | value as u16;
+            self.v = self.t;
+        }
+        self.w = !self.w;
+    }
+
+    pub fn reset_latch(&mut self) {
+        self.w = false;
+    }
+
+    pub fn increment(&mut self, inc: u8) {
+        self.v = self.v.wrapping_add(inc as u16) & 0x7FFF;
+    }
+
+    pub fn bus_address(&self) -> u16 {
+        self.bus_address
+    }
+
+    pub fn set_bus_address(&mut self, addr: u16) {
+        self.bus_address = addr;
+    }
+
+    fn increment_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v &= !0x001F;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // rows 30 and 31 hold the attribute table, wrapping from them doesn't switch nametables
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn copy_horizontal(&mut self) {
+        self.v = (self.v & !0x041F) | (self.t & 0x041F);
+    }
+
+    fn copy_vertical(&mut self) {
+        self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+    }
+
+    fn shift_background(&mut self) {
+        self.pattern_low_shift <<= 1;
+        self.pattern_high_shift <<= 1;
+        self.attribute_low_shift <<= 1;
+        self.attribute_high_shift <<= 1;
+    }
+
+    fn load_background_shifters(&mut self) {
+        self.pattern_low_shift = (self.pattern_low_shift & 0xFF00) | self.pattern_low_latch as u16;
+        self.pattern_high_shift = (self.pattern_high_shift & 0xFF00) | self.pattern_high_latch as u16;
+        let attribute_low = if self.attribute_latch & 0b01 != 0 { 0xFF } else { 0x00 };
+        let attribute_high = if self.attribute_latch & 0b10 != 0 { 0xFF } else { 0x00 };
+        self.attribute_low_shift = (self.attribute_low_shift & 0xFF00) | attribute_low;
+        self.attribute_high_shift = (self.attribute_high_shift & 0xFF00) | attribute_high;
+    }
+
+    fn nametable_address(&self) -> u16 {
+        0x2000 | (self.v & 0x0FFF)
+    }
+
+    fn attribute_address(&self) -> u16 {
+        0x23C0 | (self.v & 0x0C00) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07)
+    }
+
+    fn fine_y(&self) -> u16 {
+        (self.v >> 12) & 0b111
+    }
+}
+
+impl PPU {
+    // one dot of the background and sprite fetches, called after the dot counter moved
+    pub(super) fn tick_pipeline(&mut self, mut mapper: Option<&mut dyn Mapper>) {
+        let dot = self.cycles;
+        let prerender = self.scanline == self.region.scanlines_per_frame() - 1;
+        let visible = self.scanline < 240;
+
+        if self.is_rendering_enabled() && (visible || prerender) {
+            self.fetch_background(dot, reborrow(&mut mapper));
+            match dot {
+                256 => self.pipeline.increment_y(),
+                257 => {
+                    self.pipeline.load_background_shifters();
+                    self.pipeline.copy_horizontal();
+                    self.evaluate_sprites(prerender);
+                }
+                280..=304 if prerender => self.pipeline.copy_vertical(),
+                // unused nametable fetches at the end of the line
+                338 | 340 => {
+                    self.read_ppu_bus(self.pipeline.nametable_address(), reborrow(&mut mapper));
+                }
+                _ => {}
+            }
+            if (257..=320).contains(&dot) {
+                self.fetch_sprite(dot, mapper);
+            }
+        }
+
+        if visible && (1..=256).contains(&dot) {
+            self.render_pixel(dot - 1);
+        }
+    }
+
+    // https://www.nesdev.org/wiki/PPU_rendering#Cycles_1-256
+    fn fetch_background(&mut self, dot: usize, mapper: Option<&mut dyn Mapper>) {
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.pipeline.shift_background();
+        }
+        if !((1..=256).contains(&dot) || (321..=336).contains(&dot)) {
+            return;
+        }
+        match (dot - 1) % 8 {
+            0 => {
+                self.pipeline.load_background_shifters();
+                self.pipeline.nametable_latch = self.read_ppu_bus(self.pipeline.nametable_address(), mapper);
+            }
+            2 => {
+                let mut attribute = self.read_ppu_bus(self.pipeline.attribute_address(), mapper);
+                // each attribute byte covers 4x4 tiles, 2 bits per 2x2 quadrant
+                if self.pipeline.v & 0x0040 != 0 {
+                    attribute >>= 4;
+                }
+                if self.pipeline.v & 0x0002 != 0 {
+                    attribute >>= 2;
+                }
+                self.pipeline.attribute_latch = attribute & 0b11;
+            }
+            4 => {
+                let addr = self.background_pattern_address();
+                self.pipeline.pattern_low_latch = self.read_ppu_bus(addr, mapper);
+            }
+            6 => {
+                let addr = self.background_pattern_address() + 8;
+                self.pipeline.pattern_high_latch = self.read_ppu_bus(addr, mapper);
+            }
+            7 => self.pipeline.increment_x(),
+            _ => {}
+        }
+    }
+
+    fn background_pattern_address(&self) -> u16 {
+        self.controller_register.bknd_pattern_addr() + self.pipeline.nametable_latch as u16 * 16 + self.pipeline.fine_y()
+    }
+
+    // https://www.nesdev.org/wiki/PPU_sprite_evaluation
+    // done at once instead of over dots 65-256, the overflow flag doesn't emulate the hardware bug
+    fn evaluate_sprites(&mut self, prerender: bool) {
+        self.pipeline.sprite_count = 0;
+        self.pipeline.sprite_zero_on_line = false;
+        if prerender {
+            return;
+        }
+
+        let height = self.controller_register.sprite_size() as isize;
+        for sprite in 0..64 {
+            let row = self.scanline as isize - self.oam_data[sprite * 4] as isize;
+            if !(0..height).contains(&row) {
+                continue;
+            }
+            if self.pipeline.sprite_count == MAX_SPRITES_PER_LINE {
+                self.status_register.set_sprite_overflow(true);
+                break;
+            }
+
+            let slot = self.pipeline.sprite_count;
+            self.pipeline.sprite_rows[slot] = row as u8;
+            self.pipeline.sprite_tiles[slot] = self.oam_data[sprite * 4 + 1];
+            self.pipeline.sprite_attributes[slot] = self.oam_data[sprite * 4 + 2];
+            self.pipeline.sprite_x[slot] = self.oam_data[sprite * 4 + 3];
+            if sprite == 0 {
+                self.pipeline.sprite_zero_on_line = true;
+            }
+            self.pipeline.sprite_count += 1;
+        }
+    }
+
+    // https://www.nesdev.org/wiki/PPU_rendering#Cycles_257-320
+    fn fetch_sprite(&mut self, dot: usize, mapper: Option<&mut dyn Mapper>) {
+        let slot = (dot - 257) / 8;
+        match (dot - 257) % 8 {
+            // garbage nametable fetches
+            0 | 2 => {
+                self.read_ppu_bus(self.pipeline.nametable_address(), mapper);
+            }
+            4 => {
+                let addr = self.sprite_pattern_address(slot);
+                let pattern = self.read_ppu_bus(addr, mapper);
+                self.pipeline.sprite_patterns_low[slot] = self.sprite_pattern(slot, pattern);
+            }
+            6 => {
+                let addr = self.sprite_pattern_address(slot) + 8;
+                let pattern = self.read_ppu_bus(addr, mapper);
+                self.pipeline.sprite_patterns_high[slot] = self.sprite_pattern(slot, pattern);
+            }
+            _ => {}
+        }
+    }
+
+    fn sprite_pattern_address(&self, slot: usize) -> u16 {
+        let height = self.controller_register.sprite_size();
+        // empty slots still fetch tile $FF
+        let (tile, attributes, row) = if slot < self.pipeline.sprite_count {
+            (self.pipeline.sprite_tiles[slot], self.pipeline.sprite_attributes[slot], self.pipeline.sprite_rows[slot])
+        } else {
+            (0xFF, 0, 0)
+        };
+        let row = if attributes & 0b1000_0000 != 0 { height - 1 - row } else { row } as u16;
+
+        if height == 16 {
+            // 8x16 sprites pick the pattern table with bit 0 of the tile index
+            let table = (tile as u16 & 1) * 0x1000;
+            let tile = (tile & 0xFE) as u16 + row / 8;
+            table + tile * 16 + row % 8
+        } else {
+            self.controller_register.sprt_pattern_addr() + tile as u16 * 16 + row
+        }
+    }
+
+    fn sprite_pattern(&self, slot: usize, pattern: u8) -> u8 {
+        if slot >= self.pipeline.sprite_count {
+            0
+        } else if self.pipeline.sprite_attributes[slot] & 0b0100_0000 != 0 {
+            pattern.reverse_bits()
+        } else {
+            pattern
+        }
+    }
+
+    // https://www.nesdev.org/wiki/PPU_rendering#Preface
+    fn render_pixel(&mut self, x: usize) {
+        let mut bg_pixel = 0;
+        let mut bg_palette = 0;
+        if self.mask_register.show_background() && (x >= 8 || self.mask_register.leftmost_8pxl_background()) {
+            let bit = 0x8000 >> self.pipeline.fine_x;
+            bg_pixel = ((self.pipeline.pattern_high_shift & bit != 0) as u8) << 1
+                | (self.pipeline.pattern_low_shift & bit != 0) as u8;
+            bg_palette = ((self.pipeline.attribute_high_shift & bit != 0) as u8) << 1
+                | (self.pipeline.attribute_low_shift & bit != 0) as u8;
+        }
+
+        let mut sprite_pixel = 0;
+        let mut sprite_palette = 0;
+        let mut sprite_behind_bg = false;
+        if self.mask_register.show_sprites() && (x >= 8 || self.mask_register.leftmost_8pxl_sprite()) {
+            for slot in 0..self.pipeline.sprite_count {
+                let offset = x as isize - self.pipeline.sprite_x[slot] as isize;
+                if !(0..8).contains(&offset) {
+                    continue;
+                }
+                let bit = 7 - offset;
+                let pixel = ((self.pipeline.sprite_patterns_high[slot] >> bit) & 1) << 1
+                    | ((self.pipeline.sprite_patterns_low[slot] >> bit) & 1);
+                if pixel == 0 {
+                    continue;
+                }
+
+                if slot == 0 && self.pipeline.sprite_zero_on_line && bg_pixel != 0 && x != 255 {
+                    self.status_register.set_sprite_zero_hit(true);
+                }
+                sprite_pixel = pixel;
+                sprite_palette = 4 + (self.pipeline.sprite_attributes[slot] & 0b11);
+                sprite_behind_bg = self.pipeline.sprite_attributes[slot] & 0b0010_0000 != 0;
+                break;
+            }
+        }
+
+        let palette_addr = match (bg_pixel, sprite_pixel) {
+            (0, 0) => 0,
+            (0, _) => sprite_palette * 4 + sprite_pixel,
+            (_, 0) => bg_palette * 4 + bg_pixel,
+            _ if sprite_behind_bg => bg_palette * 4 + bg_pixel,
+            _ => sprite_palette * 4 + sprite_pixel,
+        };
+        let color = self.palette_table[Self::palette_index(0x3F00 | palette_addr as u16)];
+        let rgb = palette::SYSTEM_PALLETE[(color & 0b0011_1111) as usize];
+        self.current_frame.set_pixel(x, self.scanline as usize, rgb);
+    }
+
+    fn read_ppu_bus(&mut self, addr: u16, mapper: Option<&mut dyn Mapper>) -> u8 {
+        let addr = addr & PPU_ADDR_MASK;
+        self.pipeline.set_bus_address(addr);
+        if let Some(value) = mapper.and_then(|mapper| mapper.ppu_read(addr)) {
+            return value;
+        }
+        match addr {
+            0..=0x1FFF => self.read_chr(addr),
+            0x2000..=0x3EFF => self.read_nametable(addr),
+            _ => self.palette_table[Self::palette_index(addr)],
+        }
+    }
+}
+
+// shortens the mapper's trait object lifetime so it can be lent out once per fetch
+pub(super) fn reborrow<'a>(mapper: &'a mut Option<&mut dyn Mapper>) -> Option<&'a mut dyn Mapper> {
+    match mapper {
+        Some(mapper) => Some(&mut **mapper),
+        None => None,
+    }
+}
@@ -2,6 +2,8 @@
 pub mod test {
     use crate::hw::cartridge::ScreenMirroring;
     use crate::hw::ppu::PPU;
+    use crate::hw::region::Region;
+    use crate::rendering::palette;
 
     #[test]
     fn test_ppu_vram_writes() {
@@ -198,4 +200,376 @@ pub mod test {
         ppu.write_to_oam_addr(0x11);
         assert_eq!(ppu.read_oam_data(), 0x66);
     }
-}
\ No newline at end of file
+
+    fn dots_per_frame(region: Region) -> usize {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.region = region;
+        let mut dots = 1;
+        while !ppu.tick(1) {
+            dots += 1;
+        }
+        dots
+    }
+
+    #[test]
+    fn test_frame_length_per_region() {
+        assert_eq!(dots_per_frame(Region::NTSC), 262 * 341);
+        assert_eq!(dots_per_frame(Region::PAL), 312 * 341);
+        assert_eq!(dots_per_frame(Region::Dendy), 312 * 341);
+    }
+
+    #[test]
+    fn test_region_frame_rates() {
+        assert!((Region::NTSC.frame_rate() - 60.0988).abs() < 0.001);
+        assert!((Region::PAL.frame_rate() - 50.0070).abs() < 0.001);
+        assert!((Region::Dendy.frame_rate() - 50.0070).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_read_status_low_bits_from_io_latch() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.status_register.set_vblank_status(true);
+        ppu.write_io_latch(0b0101_0101);
+
+        assert_eq!(ppu.read_status(), 0b1001_0101);
+    }
+
+    #[test]
+    fn test_palette_read_high_bits_from_io_latch() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.palette_table[1] = 0x2A;
+        ppu.write_io_latch(0xFF);
+        ppu.write_to_ppu_addr_reg(0x3F);
+        ppu.write_to_ppu_addr_reg(0x01);
+
+        assert_eq!(ppu.read_data(), 0xEA);
+    }
+
+    #[test]
+    fn test_oam_attribute_unused_bits() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_oam_addr(0x02);
+        ppu.write_to_oam_data(0xFF);
+
+        ppu.write_to_oam_addr(0x02);
+        assert_eq!(ppu.read_oam_data(), 0xE3);
+    }
+
+    #[test]
+    fn test_io_latch_decay() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_io_latch(0xFF);
+
+        // about 600ms of frames
+        for _ in 0..35 {
+            while !ppu.tick(100) {}
+        }
+        assert_eq!(ppu.read_io_latch(), 0xFF);
+
+        // refresh the top 3 bits only
+        ppu.read_status();
+        while !ppu.tick(100) {}
+        assert_eq!(ppu.read_io_latch(), 0b1110_0000 & ppu.status_register.snapshot());
+    }
+
+    #[test]
+    fn test_vram_mirror_above_0x3000() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_ppu_addr_reg(0x20);
+        ppu.write_to_ppu_addr_reg(0x05);
+        ppu.write_to_data(0x66);
+
+        ppu.write_to_ppu_addr_reg(0x30);
+        ppu.write_to_ppu_addr_reg(0x05);
+        ppu.read_data(); //load into buffer
+        assert_eq!(ppu.read_data(), 0x66);
+    }
+
+    #[test]
+    fn test_palette_mirrors() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_ppu_addr_reg(0x3F);
+        ppu.write_to_ppu_addr_reg(0x10);
+        ppu.write_to_data(0x12);
+        assert_eq!(ppu.palette_table[0], 0x12);
+
+        ppu.write_to_ppu_addr_reg(0x3F);
+        ppu.write_to_ppu_addr_reg(0x25);
+        ppu.write_to_data(0x34);
+        assert_eq!(ppu.palette_table[5], 0x34);
+
+        ppu.write_to_ppu_addr_reg(0x3F);
+        ppu.write_to_ppu_addr_reg(0xE0);
+        assert_eq!(ppu.read_data() & 0x3F, 0x12);
+    }
+
+    #[test]
+    fn test_palette_write_masked_to_6_bits() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_ppu_addr_reg(0x3F);
+        ppu.write_to_ppu_addr_reg(0x01);
+        ppu.write_to_data(0xFF);
+        assert_eq!(ppu.palette_table[1], 0x3F);
+    }
+
+    #[test]
+    fn test_palette_read_fills_buffer_from_nametable() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_ppu_addr_reg(0x2F);
+        ppu.write_to_ppu_addr_reg(0x01);
+        ppu.write_to_data(0x77);
+
+        ppu.write_to_ppu_addr_reg(0x3F);
+        ppu.write_to_ppu_addr_reg(0x01);
+        ppu.read_data();
+
+        assert_eq!(ppu.internal_data_buf, 0x77);
+    }
+
+    #[test]
+    fn test_chr_ram_write() {
+        let mut ppu = PPU::new(vec![], ScreenMirroring::Horizontal);
+        assert_eq!(ppu.chr_rom.len(), 8192);
+        ppu.write_to_ppu_addr_reg(0x1F);
+        ppu.write_to_ppu_addr_reg(0xFF);
+        ppu.write_to_data(0x42);
+
+        ppu.write_to_ppu_addr_reg(0x1F);
+        ppu.write_to_ppu_addr_reg(0xFF);
+        ppu.read_data(); //load into buffer
+        assert_eq!(ppu.read_data(), 0x42);
+    }
+
+    #[test]
+    fn test_chr_rom_write_ignored() {
+        let mut ppu = PPU::new(vec![0x11; 8192], ScreenMirroring::Horizontal);
+        ppu.write_to_ppu_addr_reg(0x00);
+        ppu.write_to_ppu_addr_reg(0x10);
+        ppu.write_to_data(0x42);
+        assert_eq!(ppu.chr_rom[0x10], 0x11);
+    }
+
+    #[test]
+    fn test_four_screen_nametables() {
+        let mut ppu = PPU::new(vec![], ScreenMirroring::FourScreen);
+        for (i, addr) in [0x2000u16, 0x2400, 0x2800, 0x2C00].iter().enumerate() {
+            ppu.write_to_ppu_addr_reg((addr >> 8) as u8);
+            ppu.write_to_ppu_addr_reg(0x00);
+            ppu.write_to_data(i as u8 + 1);
+        }
+
+        for table in 0..4 {
+            assert_eq!(ppu.nametable(table)[0], table as u8 + 1);
+        }
+    }
+
+    fn run_to(ppu: &mut PPU, scanline: u16, dot: usize) {
+        while ppu.scanline != scanline || ppu.cycles != dot {
+            ppu.tick(1);
+        }
+    }
+
+    #[test]
+    fn test_vblank_set_on_dot_1() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_ctrl(0b1000_0000);
+        run_to(&mut ppu, 241, 0);
+        assert!(!ppu.status_register.is_in_vblank());
+
+        ppu.tick(1);
+        assert!(ppu.status_register.is_in_vblank());
+        assert_eq!(ppu.nmi_interrupt, Some(1));
+    }
+
+    #[test]
+    fn test_flags_cleared_on_prerender_line() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_ctrl(0b1000_0000);
+        run_to(&mut ppu, 261, 0);
+        ppu.status_register.set_sprite_zero_hit(true);
+        ppu.status_register.set_sprite_overflow(true);
+        assert!(ppu.status_register.is_in_vblank());
+
+        ppu.tick(1);
+        assert_eq!(ppu.status_register.snapshot(), 0);
+        assert_eq!(ppu.nmi_interrupt, None);
+    }
+
+    #[test]
+    fn test_status_read_before_vblank_suppresses_flag() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_ctrl(0b1000_0000);
+        run_to(&mut ppu, 241, 0);
+
+        assert_eq!(ppu.read_status() & 0b1000_0000, 0);
+        ppu.tick(1);
+        assert!(!ppu.status_register.is_in_vblank());
+        assert_eq!(ppu.nmi_interrupt, None);
+
+        // the next frame is unaffected
+        ppu.tick(1);
+        run_to(&mut ppu, 241, 1);
+        assert!(ppu.status_register.is_in_vblank());
+    }
+
+    #[test]
+    fn test_status_read_on_vblank_suppresses_nmi() {
+        for dot in 1..=3 {
+            let mut ppu = PPU::new_empty_rom();
+            ppu.write_to_ctrl(0b1000_0000);
+            run_to(&mut ppu, 241, dot);
+
+            assert_eq!(ppu.read_status() & 0b1000_0000, 0b1000_0000);
+            assert_eq!(ppu.nmi_interrupt.is_some(), dot == 3);
+        }
+    }
+
+    #[test]
+    fn test_nmi_disabled_on_vblank_is_cancelled() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_ctrl(0b1000_0000);
+        run_to(&mut ppu, 241, 2);
+
+        ppu.write_to_ctrl(0);
+        assert_eq!(ppu.nmi_interrupt, None);
+    }
+
+    #[test]
+    fn test_nmi_enabled_during_vblank() {
+        let mut ppu = PPU::new_empty_rom();
+        run_to(&mut ppu, 250, 0);
+        assert_eq!(ppu.nmi_interrupt, None);
+
+        ppu.write_to_ctrl(0b1000_0000);
+        assert_eq!(ppu.nmi_interrupt, Some(1));
+    }
+
+    #[test]
+    fn test_odd_frame_dot_skip() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_mask(0b0000_1000);
+        let mut frames = vec![];
+        for _ in 0..4 {
+            let mut dots = 1;
+            while !ppu.tick(1) {
+                dots += 1;
+            }
+            frames.push(dots);
+        }
+        assert_eq!(frames, vec![262 * 341, 262 * 341 - 1, 262 * 341, 262 * 341 - 1]);
+    }
+
+    #[test]
+    fn test_no_dot_skip_on_pal() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.region = Region::PAL;
+        ppu.write_to_mask(0b0000_1000);
+        for _ in 0..2 {
+            let mut dots = 1;
+            while !ppu.tick(1) {
+                dots += 1;
+            }
+            assert_eq!(dots, 312 * 341);
+        }
+    }
+
+    fn dot_rendering_ppu() -> PPU {
+        let mut ppu = PPU::new(vec![0; 8192], ScreenMirroring::Horizontal);
+        ppu.set_dot_rendering(true);
+        // tile 1 is solid color 1 in both pattern tables
+        for row in 0..8 {
+            ppu.chr_rom[0x0010 + row] = 0xFF;
+            ppu.chr_rom[0x1010 + row] = 0xFF;
+        }
+        ppu.palette_table[0] = 0x0F;
+        ppu.palette_table[1] = 0x30;
+        ppu.palette_table[0x11] = 0x16;
+        // background and sprites, including the leftmost 8 pixels
+        ppu.write_to_mask(0b0001_1110);
+        ppu
+    }
+
+    fn pixel(ppu: &PPU, x: usize, y: usize) -> (u8, u8, u8) {
+        let base = (y * 256 + x) * 3;
+        let data = &ppu.current_frame.data;
+        (data[base], data[base + 1], data[base + 2])
+    }
+
+    #[test]
+    fn test_dot_rendering_background() {
+        let mut ppu = dot_rendering_ppu();
+        ppu.vram[0..0x3c0].fill(1);
+        // the first frame starts without the pre-render line prefetch
+        while !ppu.tick(1) {}
+        run_to(&mut ppu, 241, 0);
+
+        assert_eq!(pixel(&ppu, 0, 0), palette::SYSTEM_PALLETE[0x30]);
+        assert_eq!(pixel(&ppu, 255, 239), palette::SYSTEM_PALLETE[0x30]);
+    }
+
+    #[test]
+    fn test_dot_rendering_fine_x_scroll() {
+        let mut ppu = dot_rendering_ppu();
+        ppu.vram[0] = 1;
+        ppu.write_to_scroll(4);
+        ppu.write_to_scroll(0);
+        while !ppu.tick(1) {}
+        run_to(&mut ppu, 1, 0);
+
+        assert_eq!(pixel(&ppu, 3, 0), palette::SYSTEM_PALLETE[0x30]);
+        assert_eq!(pixel(&ppu, 4, 0), palette::SYSTEM_PALLETE[0x0F]);
+    }
+
+    #[test]
+    fn test_dot_rendering_mid_frame_chr_change() {
+        let mut ppu = dot_rendering_ppu();
+        ppu.vram[0..0x3c0].fill(1);
+        while !ppu.tick(1) {}
+        run_to(&mut ppu, 100, 0);
+        ppu.chr_rom[0x0010..0x0018].fill(0);
+        run_to(&mut ppu, 241, 0);
+
+        assert_eq!(pixel(&ppu, 50, 99), palette::SYSTEM_PALLETE[0x30]);
+        assert_eq!(pixel(&ppu, 50, 101), palette::SYSTEM_PALLETE[0x0F]);
+    }
+
+    #[test]
+    fn test_dot_rendering_sprite_zero_hit() {
+        let mut ppu = dot_rendering_ppu();
+        ppu.vram[0..0x3c0].fill(1);
+        ppu.oam_data[0..4].copy_from_slice(&[50, 1, 0, 60]);
+        // sprites are drawn one line below their Y coordinate
+        run_to(&mut ppu, 51, 60);
+        assert_eq!(ppu.status_register.snapshot() & 0b0100_0000, 0);
+
+        ppu.tick(1);
+        assert_eq!(ppu.status_register.snapshot() & 0b0100_0000, 0b0100_0000);
+        assert_eq!(pixel(&ppu, 60, 51), palette::SYSTEM_PALLETE[0x16]);
+    }
+
+    #[test]
+    fn test_dot_rendering_sprite_fetches_on_ppu_bus() {
+        let mut ppu = dot_rendering_ppu();
+        // background from $0000, sprites from $1000
+        ppu.write_to_ctrl(0b0000_1000);
+        run_to(&mut ppu, 10, 5);
+        assert_eq!(ppu.bus_address() & 0x1000, 0);
+
+        // pattern fetch of the first sprite slot, empty slots fetch tile $FF
+        run_to(&mut ppu, 10, 261);
+        assert_eq!(ppu.bus_address(), 0x1FF0);
+    }
+
+    #[test]
+    fn test_scroll_and_address_writes_share_latch() {
+        let mut ppu = PPU::new_empty_rom();
+        ppu.write_to_scroll(0x7D);
+        ppu.write_to_ppu_addr_reg(0x3F);
+        ppu.read_status();
+        ppu.write_to_ppu_addr_reg(0x21);
+        ppu.write_to_ppu_addr_reg(0x08);
+        ppu.read_data();
+
+        assert_eq!(ppu.bus_address(), 0x2108);
+    }
+}
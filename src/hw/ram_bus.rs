@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use crate::hw::memory::{CpuBus, Memory};
+
+// flat 64 KiB of RAM with no devices attached, for running and testing plain 6502 code
+#[derive(Serialize, Deserialize)]
+pub struct RamBus {
+    memory: Vec<u8>,
+    pub cycles: usize,
+    // reads and writes, the 6502 makes one on every cycle
+    pub accesses: usize,
+    nmi_interrupt: Option<u8>,
+    pub irq_line: bool,
+}
+
+impl Default for RamBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RamBus {
+    pub fn new() -> Self {
+        RamBus {
+            memory: vec![0; 0x10000],
+            cycles: 0,
+            accesses: 0,
+            nmi_interrupt: None,
+            irq_line: false,
+        }
+    }
+
+    pub fn load(&mut self, addr: u16, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            self.memory[addr.wrapping_add(i as u16) as usize] = *byte;
+        }
+    }
+
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_interrupt = Some(1);
+    }
+}
+
+impl Memory for RamBus {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.accesses += 1;
+        self.memory[addr as usize]
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.accesses += 1;
+        self.memory[addr as usize] = data;
+    }
+
+    fn mem_peek(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+}
+
+impl CpuBus for RamBus {
+    fn tick(&mut self, cycles: u8) {
+        self.cycles += cycles as usize;
+    }
+
+    fn poll_nmi_status(&mut self) -> Option<u8> {
+        self.nmi_interrupt.take()
+    }
+
+    fn poll_irq_status(&mut self) -> bool {
+        self.irq_line
+    }
+}
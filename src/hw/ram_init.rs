@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+// contents of the internal RAM at power on, real consoles leave it mostly unpredictable
+// https://www.nesdev.org/wiki/CPU_power_up_state#RAM_contents
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum RamInit {
+    #[default]
+    Zeros,
+    FF,
+    // same contents for the same seed, so runs can be reproduced
+    Random { seed: u64 },
+    // 4 bytes of $00 followed by 4 bytes of $FF, seen on many front-loading consoles
+    ConsolePattern,
+}
+
+impl RamInit {
+    pub fn fill(&self, ram: &mut [u8]) {
+        match self {
+            RamInit::Zeros => ram.fill(0x00),
+            RamInit::FF => ram.fill(0xFF),
+            RamInit::Random { seed } => {
+                // xorshift64, the state must never be zero
+                let mut state = if *seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { *seed };
+                for byte in ram.iter_mut() {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    *byte = (state >> 32) as u8;
+                }
+            }
+            RamInit::ConsolePattern => {
+                for (i, byte) in ram.iter_mut().enumerate() {
+                    *byte = if i % 8 < 4 { 0x00 } else { 0xFF };
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+// https://www.nesdev.org/wiki/Cycle_reference_chart
+//
+//                      NTSC        PAL         Dendy
+// Master clock         21.477 MHz  26.601 MHz  26.601 MHz
+// CPU divider          12          16          15
+// PPU divider          4           5           5
+// PPU dots per CPU     3           3.2         3
+// Scanlines per frame  262         312         312
+// VBlank scanline      241         241         291
+// Frame rate           60.0988     50.0070     50.0070
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Region {
+    #[default]
+    NTSC,
+    PAL,
+    Dendy,
+}
+
+impl Region {
+    // NES 2.0 header byte 12, bits 0-1 (https://www.nesdev.org/wiki/NES_2.0#CPU/PPU_Timing)
+    pub fn from_nes2_timing(timing: u8) -> Self {
+        match timing & 0b11 {
+            1 => Region::PAL,
+            3 => Region::Dendy,
+            // 0 is NTSC, 2 is a multi-region cartridge which runs fine on NTSC
+            _ => Region::NTSC,
+        }
+    }
+
+    pub fn master_clock_rate(&self) -> f64 {
+        match self {
+            Region::NTSC => 21_477_272.0,
+            Region::PAL | Region::Dendy => 26_601_712.0,
+        }
+    }
+
+    pub fn cpu_clock_rate(&self) -> f64 {
+        self.master_clock_rate() / self.cpu_divider() as f64
+    }
+
+    // master clock ticks per CPU cycle
+    pub fn cpu_divider(&self) -> u32 {
+        match self {
+            Region::NTSC => 12,
+            Region::PAL => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    // master clock ticks per PPU dot
+    pub fn ppu_divider(&self) -> u32 {
+        match self {
+            Region::NTSC => 4,
+            Region::PAL | Region::Dendy => 5,
+        }
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::NTSC => 262,
+            Region::PAL | Region::Dendy => 312,
+        }
+    }
+
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Region::NTSC | Region::PAL => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        let dots_per_frame = 341.0 * self.scanlines_per_frame() as f64;
+        // NTSC skips one dot every other frame when rendering is enabled
+        let dots_per_frame = if *self == Region::NTSC { dots_per_frame - 0.5 } else { dots_per_frame };
+        self.master_clock_rate() / (self.ppu_divider() as f64 * dots_per_frame)
+    }
+}
@@ -1,13 +1,51 @@
 use nesrs::api::emulator::{Emulator, EmulatorTrigger};
+use nesrs::api::nsf::NsfPlayer;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("nsf") {
+        if let Err(err) = nsf_command(&args[2..]) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let mut emu = Emulator::new("/home/stefan/Dev/nesrs/assets/pacman-level1.cpu",
                                 true, vec![EmulatorTrigger::MemEquals { addr: 0x67, value: 0 }]).unwrap();
-    emu.reset_cpu();
+    emu.power_on().unwrap();
     loop {
         let trigger = emu.step_emulation();
         if trigger {
-            emu.reset_cpu();
+            emu.power_on().unwrap();
         }
     }
 }
+
+const NSF_USAGE: &str = "usage: nesrs nsf <file.nsf> <output.wav> [--track N] [--seconds S] [--sample-rate HZ]";
+
+// renders one track of an NSF file to a WAV file, without opening a window
+fn nsf_command(args: &[String]) -> anyhow::Result<()> {
+    let [input, output, options @ ..] = args else {
+        anyhow::bail!(NSF_USAGE);
+    };
+    let mut player = NsfPlayer::new(input)?;
+    let mut seconds = 120.0;
+    for option in options.chunks(2) {
+        let [name, value] = option else {
+            anyhow::bail!(NSF_USAGE);
+        };
+        match name.as_str() {
+            // 1 based, like the track numbers players show
+            "--track" => player.select_track(value.parse::<u8>()?.saturating_sub(1))?,
+            "--seconds" => seconds = value.parse()?,
+            "--sample-rate" => player.set_sample_rate(value.parse()?),
+            _ => anyhow::bail!(NSF_USAGE),
+        }
+    }
+
+    println!("{} - {} ({})", player.get_artist(), player.get_title(), player.get_copyright());
+    println!("Track {} of {}, {} seconds", player.get_track() + 1, player.get_track_count(), seconds);
+    player.write_wav(output, seconds)?;
+    Ok(())
+}
@@ -1,4 +1,4 @@
-mod palette;
+pub(crate) mod palette;
 pub mod tile_viewer;
 pub mod frame;
 pub mod renderer;
\ No newline at end of file
@@ -1,4 +1,3 @@
-use crate::hw::cartridge::ScreenMirroring;
 use crate::hw::ppu::PPU;
 use crate::rendering::frame::Frame;
 use crate::rendering::palette;
@@ -52,7 +51,7 @@ fn render_name_table(ppu: &PPU, frame: &mut Frame, name_table: &[u8],
         let tile_column = i % 32;
         let tile_row = i / 32;
         let tile_idx = name_table[i] as u16;
-        let tile = &ppu.chr_rom[(bank + tile_idx * 16) as usize..=(bank + tile_idx * 16 + 15) as usize];
+        let tile = ppu.chr_tile(bank + tile_idx * 16);
         let palette = bg_pallette(ppu, attribute_table, tile_column, tile_row);
 
         for y in 0..=7 {
@@ -85,16 +84,13 @@ pub fn render_bg(ppu: &PPU, frame: &mut Frame) {
     let scroll_x = (ppu.scroll_register.scroll_x) as usize;
     let scroll_y = (ppu.scroll_register.scroll_y) as usize;
 
-    let (main_nametable, second_nametable) = match (&ppu.mirroring, ppu.controller_register.nametable_addr()) {
-        (ScreenMirroring::Vertical, 0x2000) | (ScreenMirroring::Vertical, 0x2800) | (ScreenMirroring::Horizontal, 0x2000) | (ScreenMirroring::Horizontal, 0x2400) => {
-            (&ppu.vram[0..0x400], &ppu.vram[0x400..0x800])
-        }
-        (ScreenMirroring::Vertical, 0x2400) | (ScreenMirroring::Vertical, 0x2C00) | (ScreenMirroring::Horizontal, 0x2800) | (ScreenMirroring::Horizontal, 0x2C00) => {
-            (&ppu.vram[0x400..0x800], &ppu.vram[0..0x400])
-        }
-        (_, _) => {
-            panic!("Not supported mirroring type {:?}", ppu.mirroring);
-        }
+    let base_nametable = (ppu.controller_register.nametable_addr() - 0x2000) / 0x400;
+    let main_nametable = ppu.nametable(base_nametable);
+    // the nametable next to the main one in the scroll direction
+    let second_nametable = if scroll_x > 0 {
+        ppu.nametable(base_nametable ^ 0b01)
+    } else {
+        ppu.nametable(base_nametable ^ 0b10)
     };
 
     render_name_table(ppu, frame,
@@ -148,7 +144,7 @@ pub fn render_sprites(ppu: &PPU, frame: &mut Frame) {
 
         let bank: u16 = ppu.controller_register.sprt_pattern_addr();
 
-        let tile = &ppu.chr_rom[(bank + tile_idx * 16) as usize..=(bank + tile_idx * 16 + 15) as usize];
+        let tile = ppu.chr_tile(bank + tile_idx * 16);
 
 
         for y in 0..=7 {
@@ -5,7 +5,6 @@ mod test {
     use nesrs::hw::cartridge::Cartridge;
     use nesrs::hw::cpu::CPU;
     use nesrs::hw::cpu::tracer::trace;
-    use nesrs::hw::memory::Memory;
 
     #[test]
     fn run_nestest() {
@@ -0,0 +1,54 @@
+#!/usr/bin/env python3
+# converts nes20db.xml, the NES 2.0 header database kept by the nesdev community, into the
+# src/hw/cartridge/gamedb.txt format:
+#
+#   python3 tools/nes20db_to_gamedb.py nes20db.xml > src/hw/cartridge/gamedb.txt
+#
+# each <game> has the headerless ROM hashes, the PCB and the console region, the title is taken
+# from the file name in the comment before them
+import os
+import sys
+import xml.etree.ElementTree as ElementTree
+
+HEADER = """# crc32;sha1;mapper;submapper;mirroring;battery;region;board;title
+# generated by tools/nes20db_to_gamedb.py from nes20db
+"""
+MIRRORING = {"H": "H", "V": "V", "4": "4"}
+# NTSC, PAL, multi-region, Dendy
+REGIONS = {"0": "NTSC", "1": "PAL", "2": "NTSC", "3": "Dendy"}
+
+
+def title(comment):
+    name = os.path.basename(comment.strip().replace("\\", "/"))
+    return os.path.splitext(name)[0].replace(";", ",")
+
+
+def convert(path):
+    parser = ElementTree.XMLParser(target=ElementTree.TreeBuilder(insert_comments=True))
+    root = ElementTree.parse(path, parser).getroot()
+    lines = []
+    for game in root.iter("game"):
+        comment = next((node.text for node in game if node.tag is ElementTree.Comment), "")
+        rom, pcb, console = game.find("rom"), game.find("pcb"), game.find("console")
+        # Vs. System, PlayChoice and other consoles don't run on the NES hardware
+        if rom is None or pcb is None or (console is not None and console.get("type", "0") != "0"):
+            continue
+        region = REGIONS.get(console.get("region", "0") if console is not None else "0", "NTSC")
+        lines.append(";".join([
+            rom.get("crc32").upper(),
+            rom.get("sha1", "-").upper() or "-",
+            pcb.get("mapper", "0"),
+            pcb.get("submapper", "0"),
+            MIRRORING.get(pcb.get("mirroring"), "-"),
+            "1" if pcb.get("battery") == "1" else "0",
+            region,
+            "-",
+            title(comment or ""),
+        ]))
+    return HEADER + "\n".join(sorted(lines)) + "\n"
+
+
+if __name__ == "__main__":
+    if len(sys.argv) != 2:
+        sys.exit("usage: nes20db_to_gamedb.py <nes20db.xml>")
+    sys.stdout.write(convert(sys.argv[1]))